@@ -1,22 +1,465 @@
-/// Parses amounts in Stripe CSV to eurocents.
+use std::cell::Cell;
+
+/// Number of decimals of the minor unit of most currencies, e.g. cents for EUR.
+pub const DEFAULT_EXPONENT: u32 = 2;
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    #[error("ambiguous separators in amount '{0}'")]
+    AmbiguousSeparators(String),
+    #[error("invalid amount '{0}'")]
+    Invalid(String),
+    #[error("amount field is empty or whitespace")]
+    Empty,
+}
+
+/// How amounts with more decimals than the currency are rounded to minor units.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    /// Halves are rounded away from zero, "0,125" is 13 cents
+    #[default]
+    HalfUp,
+    /// Halves are rounded to the even neighbour, "0,125" is 12 cents (banker's rounding)
+    HalfEven,
+    /// Rounded towards negative infinity
+    Floor,
+    /// Rounded towards positive infinity
+    Ceil,
+}
+
+/// Numeric conventions of the amounts, which separator is the decimal one and which groups digits.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Locale {
+    /// The last separator is the decimal one, see [`parse`]
+    #[default]
+    Auto,
+    /// Dot decimal separator and comma grouping, "1,234.56"
+    En,
+    /// Comma decimal separator and dot grouping, "1.234,56"
+    De,
+    /// Comma decimal separator and space grouping, "1 234,56"
+    Fr,
+}
+
+impl Locale {
+    /// Decimal separator and grouping separators, `None` for [`Self::Auto`].
+    const fn separators(self) -> Option<(char, &'static [char])> {
+        match self {
+            Self::Auto => None,
+            Self::En => Some(('.', &[','])),
+            Self::De => Some((',', &['.'])),
+            Self::Fr => Some((',', &SPACES)),
+        }
+    }
+}
+
+/// Spaces grouping the digits of French style amounts: a regular, a non-breaking
+/// and a narrow non-breaking space, e.g. "1 234,56".
+const SPACES: [char; 3] = [' ', '\u{a0}', '\u{202f}'];
+
+/// How empty amounts are handled, other malformed amounts are always an error.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum EmptyAmount {
+    /// Empty amounts are an error, like malformed ones
+    #[default]
+    Error,
+    /// Empty amounts are zero
+    Zero,
+    /// The rows with an empty amount are skipped
+    Skip,
+}
+
+thread_local! {
+    static EXPONENT: Cell<u32> = const { Cell::new(DEFAULT_EXPONENT) };
+    static ROUNDING: Cell<Rounding> = const { Cell::new(Rounding::HalfUp) };
+    static EMPTY_AMOUNT: Cell<EmptyAmount> = const { Cell::new(EmptyAmount::Error) };
+    static LOCALE: Cell<Locale> = const { Cell::new(Locale::Auto) };
+}
+
+/// Number of decimals of the minor unit of the given ISO currency code.
+///
+/// Zero-decimal currencies such as JPY return `0`, three-decimal currencies such as BHD return `3`.
+#[must_use]
+pub fn currency_exponent(currency: &str) -> u32 {
+    match currency.to_ascii_lowercase().as_str() {
+        "bif" | "clp" | "djf" | "gnf" | "jpy" | "kmf" | "krw" | "mga" | "pyg" | "rwf" | "ugx"
+        | "vnd" | "vuv" | "xaf" | "xof" | "xpf" => 0,
+        "bhd" | "jod" | "kwd" | "omr" | "tnd" => 3,
+        _ => DEFAULT_EXPONENT,
+    }
+}
+
+/// Runs `f` with amounts deserialized to minor units of a currency with the given exponent.
 ///
-/// Accepted format: "xxx,xx" or "xxx.xx"
+/// Outside of this scope, amounts are deserialized with [`DEFAULT_EXPONENT`].
+pub fn with_exponent<T>(exponent: u32, f: impl FnOnce() -> T) -> T {
+    let previous = EXPONENT.replace(exponent);
+    let result = f();
+    EXPONENT.set(previous);
+    result
+}
+
+/// Runs `f` with amounts rounded to minor units according to the given mode.
+///
+/// Outside of this scope, amounts are rounded with [`Rounding::HalfUp`].
+pub fn with_rounding<T>(rounding: Rounding, f: impl FnOnce() -> T) -> T {
+    let previous = ROUNDING.replace(rounding);
+    let result = f();
+    ROUNDING.set(previous);
+    result
+}
+
+/// Runs `f` with empty amounts deserialized according to the given policy.
+///
+/// Outside of this scope, empty amounts are an error.
+pub fn with_empty_amount<T>(empty_amount: EmptyAmount, f: impl FnOnce() -> T) -> T {
+    let previous = EMPTY_AMOUNT.replace(empty_amount);
+    let result = f();
+    EMPTY_AMOUNT.set(previous);
+    result
+}
+
+/// Runs `f` with amounts parsed with the separators of the given locale.
+///
+/// Outside of this scope, amounts are parsed with [`Locale::Auto`].
+pub fn with_locale<T>(locale: Locale, f: impl FnOnce() -> T) -> T {
+    let previous = LOCALE.replace(locale);
+    let result = f();
+    LOCALE.set(previous);
+    result
+}
+
+/// Parses amounts in Stripe CSV to minor units, eurocents by default.
+///
+/// See [`parse`] for the accepted formats. An empty or whitespace amount is `0` with [`EmptyAmount::Zero`]
+/// set by [`with_empty_amount`], and an error otherwise: skipping the rows with an empty amount
+/// is left to the caller.
 ///
 /// # Errors
 ///
-/// The amount is not in the specified format.
-#[allow(clippy::cast_possible_truncation)]
+/// The amount is empty, or not in the specified format.
 pub fn deserialize<'de, D>(deserializer: D) -> Result<i64, D::Error>
 where
     D: serde::Deserializer<'de>,
 {
     let s: String = serde::Deserialize::deserialize(deserializer)?;
-    let normalized = s.replace(',', ".");
-    let value = normalized
-        .parse::<f64>()
-        .map_err(serde::de::Error::custom)?;
+    if is_blank(&s) && EMPTY_AMOUNT.get() == EmptyAmount::Zero {
+        return Ok(0);
+    }
+    parse(&s).map_err(serde::de::Error::custom)
+}
 
-    Ok((value * 100.0).round() as i64)
+/// Formats amounts in minor units as "xxx.xx", eurocents by default.
+///
+/// The currency exponent can be changed with [`with_exponent`], see [`format`].
+///
+/// # Errors
+///
+/// The serializer failed to write the string.
+#[allow(clippy::trivially_copy_pass_by_ref)]
+pub fn serialize<S>(value: &i64, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_str(&format(*value, EXPONENT.get()))
+}
+
+/// Formats amounts in minor units as "xxx,xx", like the Stripe exports, see [`serialize`].
+///
+/// # Errors
+///
+/// The serializer failed to write the string.
+#[allow(clippy::trivially_copy_pass_by_ref)]
+pub fn serialize_comma<S>(value: &i64, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_str(&format(*value, EXPONENT.get()).replacen('.', ",", 1))
+}
+
+/// Parses an amount to minor units, eurocents by default.
+///
+/// The currency exponent can be changed with [`with_exponent`],
+/// e.g. "500" is 500 in a zero-decimal currency such as JPY.
+///
+/// Accepted format: "xxx,xx" or "xxx.xx", optionally prefixed with a sign.
+/// Extra decimals are rounded with the mode set by [`with_rounding`], by default halves are
+/// rounded away from zero. A negative zero is normalized to `0`.
+///
+/// Grouping separators are supported, the last separator is the decimal one:
+/// "1,234.56" and "1.234,56" are both 1234.56.
+/// A single separator followed by exactly three digits, such as "1,000", is ambiguous
+/// and read as a thousands separator, unless the integer part is `0`
+/// or the currency has three decimals.
+///
+/// Leading and trailing characters which are not digits, signs or separators are ignored,
+/// which strips currency symbols such as "€1,50", "$1.50" or "1,50 €".
+///
+/// Accounting-style negatives are wrapped in parentheses, "(1,50)" and "(€1,50)" are -150.
+///
+/// These separators are guessed with [`Locale::Auto`], the default. Another locale set by
+/// [`with_locale`] removes its grouping separators and only accepts its decimal separator,
+/// e.g. "1,000" is 1000.00 with [`Locale::En`] and 1.00 with [`Locale::De`].
+///
+/// # Errors
+///
+/// The amount is empty, not in the specified format, or has an unmatched parenthesis.
+pub fn parse(amount: &str) -> Result<i64, Error> {
+    if is_blank(amount) {
+        return Err(Error::Empty);
+    }
+    let stripped = strip_symbols(amount);
+    let mut normalized = normalize_with_locale(stripped, EXPONENT.get(), LOCALE.get())?;
+    if is_parenthesized(amount, stripped)? {
+        normalized.insert(0, '-');
+    }
+    to_minor_units(&normalized, EXPONENT.get(), ROUNDING.get())
+        .ok_or_else(|| Error::Invalid(amount.to_string()))
+}
+
+/// Whether an amount is empty or only whitespace, which [`parse`] reports as [`Error::Empty`]
+/// instead of a malformed amount.
+#[must_use]
+pub fn is_blank(amount: &str) -> bool {
+    amount.trim().is_empty()
+}
+
+/// Formats an amount in minor units as a decimal string with `exponent` decimals.
+///
+/// e.g. `150` is "1.50" and `-13` is "-0.13" with the default exponent.
+#[must_use]
+pub fn format(amount: i64, exponent: u32) -> String {
+    let sign = if amount < 0 { "-" } else { "" };
+    let magnitude = amount.unsigned_abs();
+    if exponent == 0 {
+        return format!("{sign}{magnitude}");
+    }
+
+    let divisor = 10_u64.pow(exponent);
+    format!(
+        "{sign}{}.{:0width$}",
+        magnitude / divisor,
+        magnitude % divisor,
+        width = exponent as usize
+    )
+}
+
+/// Maximum number of decimals of [`format_with_precision`].
+pub const MAX_PRECISION: u32 = 9;
+
+/// Formats an amount in minor units with `exponent` decimals as a decimal string with `precision` decimals.
+///
+/// Extra decimals are padded with zeros, missing ones are rounded half away from zero,
+/// e.g. `150` is "1.500" with 3 decimals and "2" without decimals.
+/// The precision is capped to [`MAX_PRECISION`].
+#[must_use]
+pub fn format_with_precision(amount: i64, exponent: u32, precision: u32) -> String {
+    let precision = precision.min(MAX_PRECISION);
+    let magnitude = if precision >= exponent {
+        u128::from(amount.unsigned_abs()) * 10_u128.pow(precision - exponent)
+    } else {
+        let divisor = 10_u128.pow(exponent - precision);
+        (u128::from(amount.unsigned_abs()) + divisor / 2) / divisor
+    };
+    let sign = if amount < 0 && magnitude != 0 { "-" } else { "" };
+    if precision == 0 {
+        return format!("{sign}{magnitude}");
+    }
+
+    let divisor = 10_u128.pow(precision);
+    format!(
+        "{sign}{}.{:0width$}",
+        magnitude / divisor,
+        magnitude % divisor,
+        width = precision as usize
+    )
+}
+
+/// Formats an amount parsed to minor units back and compares it with the input, to surface
+/// amounts which lost precision.
+///
+/// Returns the formatted amount when it is not the same decimal value as the input.
+/// An input with more decimals than the currency matches when the amount is one of the two
+/// nearest minor units, e.g. "0,125" matches either "0.12" or "0.13".
+/// Inputs which are not accepted by [`parse`] are reported by it and never returned.
+#[must_use]
+pub fn round_trip(amount: &str, parsed: i64, exponent: u32) -> Option<String> {
+    let stripped = strip_symbols(amount);
+    let mut normalized = normalize_with_locale(stripped, exponent, LOCALE.get()).ok()?;
+    if is_parenthesized(amount, stripped).ok()? {
+        normalized.insert(0, '-');
+    }
+
+    let formatted = format(parsed, exponent);
+    let (sign, unsigned) = normalized.strip_prefix('-').map_or_else(
+        || ("", normalized.trim_start_matches('+')),
+        |unsigned| ("-", unsigned),
+    );
+    let (integer, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.len() > exponent as usize {
+        let is_nearest = [Rounding::Floor, Rounding::Ceil]
+            .into_iter()
+            .any(|rounding| to_minor_units(&normalized, exponent, rounding) == Some(parsed));
+        return (!is_nearest).then_some(formatted);
+    }
+
+    let integer = match integer.trim_start_matches('0') {
+        "" => "0",
+        integer => integer,
+    };
+    let is_zero = integer == "0" && fraction.is_empty();
+    let sign = if is_zero { "" } else { sign };
+    let expected = if exponent == 0 {
+        format!("{sign}{integer}")
+    } else {
+        format!("{sign}{integer}.{fraction:0<width$}", width = exponent as usize)
+    };
+    (expected != formatted).then_some(formatted)
+}
+
+/// Converts an amount in minor units to major units, e.g. `150` cents are `1.5` euros.
+#[must_use]
+#[allow(clippy::cast_precision_loss, clippy::cast_possible_wrap)]
+pub fn to_major_units(amount: i64, exponent: u32) -> f64 {
+    amount as f64 / 10_f64.powi(exponent as i32)
+}
+
+/// Converts a normalized decimal such as "-0.125" to minor units, rounding the extra decimals.
+///
+/// The digits are rounded exactly, "0.135" is not affected by its binary floating point value.
+/// Returns `None` when the amount is not a decimal number or does not fit in an `i64`.
+fn to_minor_units(normalized: &str, exponent: u32, rounding: Rounding) -> Option<i64> {
+    let (negative, unsigned) = match normalized.as_bytes().first() {
+        Some(b'-') => (true, &normalized[1..]),
+        Some(b'+') => (false, &normalized[1..]),
+        _ => (false, normalized),
+    };
+    let (integer, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    let is_digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+    if (integer.is_empty() && fraction.is_empty()) || !is_digits(integer) || !is_digits(fraction) {
+        return None;
+    }
+
+    let exponent = exponent as usize;
+    let (kept, remainder) = fraction.split_at(exponent.min(fraction.len()));
+    let mut magnitude: i64 = 0;
+    let padding = std::iter::repeat_n(b'0', exponent - kept.len());
+    for digit in integer.bytes().chain(kept.bytes()).chain(padding) {
+        magnitude = magnitude.checked_mul(10)?.checked_add(i64::from(digit - b'0'))?;
+    }
+
+    let is_exact = remainder.bytes().all(|byte| byte == b'0');
+    let half = remainder.bytes().next().map_or(std::cmp::Ordering::Less, |first| {
+        first.cmp(&b'5').then_with(|| {
+            if remainder.bytes().skip(1).all(|byte| byte == b'0') {
+                std::cmp::Ordering::Equal
+            } else {
+                std::cmp::Ordering::Greater
+            }
+        })
+    });
+    let round_up = match rounding {
+        Rounding::HalfUp => half.is_ge(),
+        Rounding::HalfEven => half.is_gt() || (half.is_eq() && magnitude % 2 == 1),
+        Rounding::Floor => negative && !is_exact,
+        Rounding::Ceil => !negative && !is_exact,
+    };
+    if round_up {
+        magnitude = magnitude.checked_add(1)?;
+    }
+
+    Some(if negative { -magnitude } else { magnitude })
+}
+
+/// Trims currency symbols, whitespace and other non numeric characters around the amount.
+fn strip_symbols(amount: &str) -> &str {
+    amount.trim_matches(|c: char| !(c.is_ascii_digit() || matches!(c, '-' | '+' | ',' | '.')))
+}
+
+/// Whether the amount is an accounting-style negative wrapped in parentheses, such as "(1,50)",
+/// `stripped` being the amount without the characters around it, see [`strip_symbols`].
+///
+/// # Errors
+///
+/// A parenthesis is unmatched, or the amount in parentheses also has a sign.
+fn is_parenthesized(amount: &str, stripped: &str) -> Result<bool, Error> {
+    let start = amount.find(stripped).unwrap_or(0);
+    let (prefix, suffix) = (&amount[..start], &amount[start + stripped.len()..]);
+    match (prefix.contains('('), suffix.contains(')')) {
+        (true, true) if !stripped.starts_with(['-', '+']) => Ok(true),
+        (false, false) if !prefix.contains(')') && !suffix.contains('(') => Ok(false),
+        _ => Err(Error::Invalid(amount.to_string())),
+    }
+}
+
+/// Removes the grouping separators of the locale and turns its decimal separator into a period,
+/// the separators are guessed by [`normalize`] with [`Locale::Auto`] once the spaces grouping
+/// digits are removed, see [`remove_space_grouping`].
+///
+/// # Errors
+///
+/// The amount has several decimal separators, or a grouping separator after the decimal one.
+fn normalize_with_locale(amount: &str, exponent: u32, locale: Locale) -> Result<String, Error> {
+    let Some((decimal, grouping)) = locale.separators() else {
+        return normalize(&remove_space_grouping(amount), exponent);
+    };
+
+    let is_grouped_fraction = amount
+        .find(decimal)
+        .is_some_and(|position| amount[position..].contains(grouping));
+    if amount.matches(decimal).count() > 1 || is_grouped_fraction {
+        return Err(Error::Invalid(amount.to_string()));
+    }
+    let ungrouped: String = amount.chars().filter(|c| !grouping.contains(c)).collect();
+    Ok(ungrouped.replace(decimal, "."))
+}
+
+/// Removes the [`SPACES`] between two digits, e.g. "1 234,56" is "1234,56",
+/// a space anywhere else is left as is and still makes the amount malformed.
+fn remove_space_grouping(amount: &str) -> String {
+    let chars: Vec<char> = amount.chars().collect();
+    chars
+        .iter()
+        .enumerate()
+        .filter(|&(index, c)| {
+            let is_between_digits = index > 0
+                && chars[index - 1].is_ascii_digit()
+                && chars.get(index + 1).is_some_and(char::is_ascii_digit);
+            !(SPACES.contains(c) && is_between_digits)
+        })
+        .map(|(_, c)| *c)
+        .collect()
+}
+
+/// Removes grouping separators and turns the decimal separator into a period.
+fn normalize(amount: &str, exponent: u32) -> Result<String, Error> {
+    let Some(position) = amount.rfind([',', '.']) else {
+        return Ok(amount.to_string());
+    };
+
+    let separator = if amount[position..].starts_with(',') { ',' } else { '.' };
+    let other = if separator == ',' { '.' } else { ',' };
+    let (integer, fraction) = (&amount[..position], &amount[position + 1..]);
+
+    if integer.contains(separator) {
+        if amount.contains(other) {
+            return Err(Error::AmbiguousSeparators(amount.to_string()));
+        }
+        return Ok(amount.replace(separator, ""));
+    }
+
+    let digits = integer.trim_start_matches(['-', '+']);
+    let is_thousands = !integer.contains(other)
+        && exponent < 3
+        && fraction.len() == 3
+        && (1..=3).contains(&digits.len())
+        && !digits.starts_with('0');
+    if is_thousands {
+        return Ok(format!("{integer}{fraction}"));
+    }
+
+    Ok(format!("{}.{fraction}", integer.replace(other, "")))
 }
 
 #[cfg(test)]
@@ -59,8 +502,8 @@ mod tests {
     amount_test!(test_zero_amount_comma, "0,00", 0);
     amount_test!(test_large_amount, "123.45", 12345);
     amount_test!(test_large_amount_comma, "123,45", 12345);
-    amount_test!(test_three_decimal_places, "1.234", 123);
-    amount_test!(test_three_decimal_places_comma, "1,234", 123);
+    amount_test!(test_three_decimal_places, "0.234", 23);
+    amount_test!(test_three_decimal_places_comma, "0,234", 23);
     amount_test!(test_rounding_up, "0.126", 13);
     amount_test!(test_rounding_up_comma, "0,126", 13);
     amount_test!(test_rounding_down, "0.124", 12);
@@ -69,6 +512,339 @@ mod tests {
     amount_test!(test_single_decimal, "1.5", 150);
     amount_test!(test_single_decimal_comma, "1,5", 150);
 
+    amount_test!(test_negative_amount, "-1.50", -150);
+    amount_test!(test_negative_amount_comma, "-1,50", -150);
+    amount_test!(test_negative_rounding_comma, "-0,126", -13);
+    amount_test!(test_negative_rounding_down_comma, "-0,124", -12);
+    amount_test!(test_negative_zero_comma, "-0,00", 0);
+    amount_test!(test_positive_sign, "+1,50", 150);
+
+    amount_test!(test_thousands_comma_decimal_period, "1,234.56", 123_456);
+    amount_test!(test_thousands_period_decimal_comma, "1.234,56", 123_456);
+    amount_test!(test_multiple_thousands_separators, "1,234,567.89", 123_456_789);
+    amount_test!(test_multiple_thousands_separators_comma, "1.234.567,89", 123_456_789);
+    amount_test!(test_thousands_without_decimals, "1,234,567", 123_456_700);
+    amount_test!(test_ambiguous_thousands, "1,000", 100_000);
+    amount_test!(test_ambiguous_thousands_period, "1.000", 100_000);
+    amount_test!(test_negative_thousands, "-1.234,56", -123_456);
+    amount_test!(test_four_decimal_places_not_thousands, "1,2345", 123);
+
+    amount_test!(test_space_thousands, "1 234,56", 123_456);
+    amount_test!(test_nbsp_thousands, "1\u{a0}234,56", 123_456);
+    amount_test!(
+        test_narrow_nbsp_thousands,
+        "1\u{202f}234\u{202f}567,89 €",
+        123_456_789
+    );
+    amount_test!(test_negative_space_thousands, "-1 234,56", -123_456);
+    amount_error_test!(test_lone_space, " ");
+    amount_error_test!(test_space_after_sign, "- 1,50");
+    amount_error_test!(test_space_before_decimal, "1 ,50");
+
+    amount_test!(test_euro_symbol_before, "€1,50", 150);
+    amount_test!(test_dollar_symbol_before, "$1.50", 150);
+    amount_test!(test_pound_symbol_before, "£1.50", 150);
+    amount_test!(test_yen_symbol_before, "¥1,500", 150_000);
+    amount_test!(test_euro_symbol_after_with_space, "1,50 €", 150);
+    amount_test!(test_dollar_symbol_after_with_space, "1.50 $", 150);
+    amount_test!(test_symbol_before_negative, "€-1,50", -150);
+    amount_test!(test_symbol_with_thousands, "$1,234.56", 123_456);
+
+    amount_test!(test_parentheses_negative, "(1,50)", -150);
+    amount_test!(test_parentheses_zero, "(0,00)", 0);
+    amount_test!(test_parentheses_thousands, "(1.234,56)", -123_456);
+    amount_test!(test_parentheses_with_symbol, "(€1,50)", -150);
+    amount_test!(test_symbol_before_parentheses, "€(1,50)", -150);
+    amount_test!(test_parentheses_rounding, "(0,126)", -13);
+
+    macro_rules! exponent_test {
+        ($name:ident, $currency:expr, $input:expr, $expected:expr) => {
+            #[test]
+            fn $name() {
+                let csv_data = format!("amount\n\"{}\"", $input);
+                let mut reader = csv::Reader::from_reader(csv_data.as_bytes());
+                let result: TestAmount = with_exponent(currency_exponent($currency), || {
+                    reader.deserialize().next().expect("should be ok").expect("should deserialize")
+                });
+                assert_eq!(result.amount, $expected);
+            }
+        };
+    }
+
+    exponent_test!(test_jpy_whole_units, "jpy", "500", 500);
+    exponent_test!(test_jpy_uppercase, "JPY", "500", 500);
+    exponent_test!(test_jpy_thousands, "jpy", "1,000", 1000);
+    exponent_test!(test_jpy_rounding, "jpy", "499,6", 500);
+    exponent_test!(test_krw_whole_units, "krw", "12500", 12500);
+    exponent_test!(test_bhd_three_decimals, "bhd", "1.234", 1234);
+    exponent_test!(test_bhd_three_decimals_comma, "bhd", "1,234", 1234);
+    exponent_test!(test_bhd_single_decimal, "bhd", "0,5", 500);
+    exponent_test!(test_bhd_thousands, "bhd", "1.234,567", 1_234_567);
+    exponent_test!(test_eur_default_exponent, "eur", "1,50", 150);
+
+    macro_rules! rounding_test {
+        ($name:ident, $rounding:expr, $input:expr, $expected:expr) => {
+            #[test]
+            fn $name() {
+                let csv_data = format!("amount\n\"{}\"", $input);
+                let mut reader = csv::Reader::from_reader(csv_data.as_bytes());
+                let result: TestAmount = with_rounding($rounding, || {
+                    reader.deserialize().next().expect("should be ok").expect("should deserialize")
+                });
+                assert_eq!(result.amount, $expected);
+            }
+        };
+    }
+
+    rounding_test!(test_half_up_half, Rounding::HalfUp, "0,125", 13);
+    rounding_test!(test_half_up_negative_half, Rounding::HalfUp, "-0,125", -13);
+    rounding_test!(test_half_even_half_to_even, Rounding::HalfEven, "0,125", 12);
+    rounding_test!(test_half_even_half_to_odd, Rounding::HalfEven, "0,135", 14);
+    rounding_test!(test_half_even_negative_half, Rounding::HalfEven, "-0,125", -12);
+    rounding_test!(test_half_even_above_half, Rounding::HalfEven, "0,1251", 13);
+    rounding_test!(test_half_even_below_half, Rounding::HalfEven, "0,1249", 12);
+    rounding_test!(test_floor, Rounding::Floor, "0,129", 12);
+    rounding_test!(test_floor_negative, Rounding::Floor, "-0,121", -13);
+    rounding_test!(test_floor_exact, Rounding::Floor, "-0,120", -12);
+    rounding_test!(test_ceil, Rounding::Ceil, "0,121", 13);
+    rounding_test!(test_ceil_negative, Rounding::Ceil, "-0,129", -12);
+    rounding_test!(test_ceil_exact, Rounding::Ceil, "0,120", 12);
+
+    #[test]
+    fn test_rounding_with_exponent() {
+        let jpy = currency_exponent("jpy");
+        assert_eq!(with_rounding(Rounding::HalfEven, || with_exponent(jpy, || parse("2,5"))), Ok(2));
+        assert_eq!(with_rounding(Rounding::HalfUp, || with_exponent(jpy, || parse("2,5"))), Ok(3));
+        assert_eq!(parse("0,125"), Ok(13));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("10,00"), Ok(1000));
+        assert_eq!(parse("10.00"), Ok(1000));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse("abc"), Err(Error::Invalid("abc".to_string())));
+        assert_eq!(parse("-"), Err(Error::Invalid("-".to_string())));
+        assert_eq!(
+            parse("99999999999999999999"),
+            Err(Error::Invalid("99999999999999999999".to_string()))
+        );
+        assert_eq!(
+            parse("1,234,567.89,12"),
+            Err(Error::AmbiguousSeparators("1,234,567.89,12".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(format(150, 2), "1.50");
+        assert_eq!(format(5, 2), "0.05");
+        assert_eq!(format(-13, 2), "-0.13");
+        assert_eq!(format(0, 2), "0.00");
+        assert_eq!(format(500, 0), "500");
+        assert_eq!(format(-500, 0), "-500");
+        assert_eq!(format(1234, 3), "1.234");
+        assert_eq!(format(i64::MIN, 2), "-92233720368547758.08");
+    }
+
+    #[test]
+    fn test_format_with_precision() {
+        assert_eq!(format_with_precision(150, 2, 2), "1.50");
+        assert_eq!(format_with_precision(150, 2, 0), "2");
+        assert_eq!(format_with_precision(149, 2, 0), "1");
+        assert_eq!(format_with_precision(-150, 2, 0), "-2");
+        assert_eq!(format_with_precision(-4, 2, 1), "0.0");
+        assert_eq!(format_with_precision(1234, 2, 4), "12.3400");
+        assert_eq!(format_with_precision(500, 0, 2), "500.00");
+        assert_eq!(format_with_precision(1235, 3, 2), "1.24");
+        assert_eq!(format_with_precision(1, 2, 20), "0.010000000");
+        assert_eq!(
+            format_with_precision(i64::MIN, 2, 9),
+            "-92233720368547758.080000000"
+        );
+    }
+
+    #[test]
+    fn test_to_major_units() {
+        assert!((to_major_units(150, 2) - 1.5).abs() < f64::EPSILON);
+        assert!((to_major_units(500, 0) - 500.0).abs() < f64::EPSILON);
+        assert!((to_major_units(-1234, 3) + 1.234).abs() < f64::EPSILON);
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct Amounts {
+        #[serde(serialize_with = "serialize", deserialize_with = "deserialize")]
+        period: i64,
+        #[serde(serialize_with = "serialize_comma", deserialize_with = "deserialize")]
+        comma: i64,
+    }
+
+    fn serialize_amounts(period: i64, comma: i64) -> String {
+        let mut writer = csv::WriterBuilder::new().has_headers(false).from_writer(Vec::new());
+        writer.serialize(Amounts { period, comma }).expect("should serialize");
+        String::from_utf8(writer.into_inner().expect("should flush")).expect("should be utf-8")
+    }
+
+    #[test]
+    fn test_serialize() {
+        assert_eq!(serialize_amounts(150, 150), "1.50,\"1,50\"\n");
+        assert_eq!(serialize_amounts(-13, -13), "-0.13,\"-0,13\"\n");
+        assert_eq!(serialize_amounts(0, 123_456), "0.00,\"1234,56\"\n");
+        assert_eq!(with_exponent(0, || serialize_amounts(500, 500)), "500,500\n");
+    }
+
+    #[test]
+    fn test_serialize_round_trip() {
+        let output = serialize_amounts(-123_456, 7);
+        let mut reader = csv::ReaderBuilder::new().has_headers(false).from_reader(output.as_bytes());
+        let amounts: Amounts = reader.deserialize().next().expect("should have row").expect("should deserialize");
+        assert_eq!(amounts.period, -123_456);
+        assert_eq!(amounts.comma, 7);
+    }
+
+    #[test]
+    fn test_exponent_scope_is_restored() {
+        with_exponent(0, || assert_eq!(EXPONENT.get(), 0));
+        assert_eq!(EXPONENT.get(), DEFAULT_EXPONENT);
+    }
+
+    #[test]
+    fn test_currency_exponent() {
+        assert_eq!(currency_exponent("eur"), 2);
+        assert_eq!(currency_exponent("jpy"), 0);
+        assert_eq!(currency_exponent("bhd"), 3);
+        assert_eq!(currency_exponent("unknown"), DEFAULT_EXPONENT);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        assert_eq!(round_trip("1,50", 150, 2), None);
+        assert_eq!(round_trip("€1.234,56", 123_456, 2), None);
+        assert_eq!(round_trip("(0,05)", -5, 2), None);
+        assert_eq!(round_trip("-0,00", 0, 2), None);
+        assert_eq!(round_trip("+007", 700, 2), None);
+        assert_eq!(round_trip("500", 500, 0), None);
+        assert_eq!(round_trip("1,50", 149, 2), Some("1.49".to_string()));
+        assert_eq!(round_trip("-1,50", 150, 2), Some("1.50".to_string()));
+        assert_eq!(round_trip("1.5", 1, 0), None);
+        assert_eq!(round_trip("invalid", 0, 2), None);
+    }
+
+    #[test]
+    fn test_round_trip_extra_decimals() {
+        assert_eq!(round_trip("0,125", 12, 2), None);
+        assert_eq!(round_trip("0,125", 13, 2), None);
+        assert_eq!(round_trip("0,1200", 12, 2), None);
+        assert_eq!(round_trip("-0,125", -13, 2), None);
+        assert_eq!(round_trip("0,125", 14, 2), Some("0.14".to_string()));
+    }
+
+    amount_error_test!(test_bare_symbol, "€");
+    amount_error_test!(test_symbol_without_digits, "€ ,");
+    amount_error_test!(test_mixed_repeated_separators, "1,234,567.89,12");
     amount_error_test!(test_invalid_format, "invalid");
     amount_error_test!(test_empty_string, "");
+
+    #[test]
+    fn test_whitespace_amount() {
+        for amount in ["   ", "\t", " \t "] {
+            assert_eq!(parse(amount), Err(Error::Empty));
+            assert_eq!(
+                with_empty_amount(EmptyAmount::Zero, || parse(amount)),
+                Err(Error::Empty)
+            );
+        }
+        assert_eq!(
+            Error::Empty.to_string(),
+            "amount field is empty or whitespace"
+        );
+        assert_eq!(parse(" 1,50 "), Ok(150));
+    }
+
+    macro_rules! locale_test {
+        ($name:ident, $locale:expr, $input:expr, $expected:expr) => {
+            #[test]
+            fn $name() {
+                assert_eq!(with_locale($locale, || parse($input)), $expected);
+            }
+        };
+    }
+
+    locale_test!(test_locale_en_grouping, Locale::En, "1,234.56", Ok(123_456));
+    locale_test!(test_locale_en_thousands, Locale::En, "1,000", Ok(100_000));
+    locale_test!(test_locale_en_decimal, Locale::En, "0.125", Ok(13));
+    locale_test!(
+        test_locale_en_comma_decimal,
+        Locale::En,
+        "1.234,56",
+        Err(Error::Invalid("1.234,56".to_string()))
+    );
+    locale_test!(test_locale_de_grouping, Locale::De, "1.234,56", Ok(123_456));
+    locale_test!(test_locale_de_decimal, Locale::De, "1,000", Ok(100));
+    locale_test!(
+        test_locale_de_negative,
+        Locale::De,
+        "-1.000.000,5",
+        Ok(-100_000_050)
+    );
+    locale_test!(
+        test_locale_de_dot_decimal,
+        Locale::De,
+        "1,234.56",
+        Err(Error::Invalid("1,234.56".to_string()))
+    );
+    locale_test!(test_locale_fr_grouping, Locale::Fr, "1 234,56", Ok(123_456));
+    locale_test!(
+        test_locale_fr_nbsp,
+        Locale::Fr,
+        "1\u{a0}234,56 €",
+        Ok(123_456)
+    );
+    locale_test!(
+        test_locale_fr_several_decimals,
+        Locale::Fr,
+        "1,234,56",
+        Err(Error::Invalid("1,234,56".to_string()))
+    );
+    locale_test!(test_locale_auto, Locale::Auto, "1,000", Ok(100_000));
+
+    #[test]
+    fn test_locale_scope_is_restored() {
+        with_locale(Locale::De, || assert_eq!(LOCALE.get(), Locale::De));
+        assert_eq!(LOCALE.get(), Locale::Auto);
+    }
+
+    #[test]
+    fn test_empty_amount_policy() {
+        let deserialize_empty = || {
+            let mut reader = csv::Reader::from_reader(&b"amount\n\"\""[..]);
+            reader
+                .deserialize::<TestAmount>()
+                .next()
+                .expect("should have a row")
+                .map(|result| result.amount)
+        };
+
+        assert_eq!(parse(""), Err(Error::Empty));
+        assert!(
+            deserialize_empty()
+                .expect_err("should be error")
+                .to_string()
+                .contains("empty or whitespace")
+        );
+        assert!(with_empty_amount(EmptyAmount::Skip, deserialize_empty).is_err());
+        assert_eq!(
+            with_empty_amount(EmptyAmount::Zero, deserialize_empty).expect("should be zero"),
+            0
+        );
+        assert!(with_empty_amount(EmptyAmount::Zero, || parse("abc")).is_err());
+        assert_eq!(EMPTY_AMOUNT.get(), EmptyAmount::Error);
+    }
+    amount_error_test!(test_unmatched_opening_parenthesis, "(1,50");
+    amount_error_test!(test_unmatched_closing_parenthesis, "1,50)");
+    amount_error_test!(test_reversed_parentheses, ")1,50(");
+    amount_error_test!(test_parentheses_with_sign, "(-1,50)");
 }
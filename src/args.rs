@@ -1,24 +1,673 @@
-use std::path::PathBuf;
+use std::{num::NonZeroUsize, path::PathBuf};
 
-use clap::{Parser, ValueEnum, command};
+use clap::{ArgMatches, CommandFactory, Parser, ValueEnum, parser::ValueSource};
+
+use chrono::NaiveDate;
+
+use crate::{amount_serde, timestamp_serde};
 
 /// Command-line utility to parse and analyse csv files generated by Stripe.
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Args {
-    /// Path to the csv file to analyse
-    pub file: PathBuf,
+    /// Paths to the csv files to analyse, `-` to read from stdin.
+    /// Several fees files are aggregated into a single output
+    #[arg(required = true, num_args = 1..)]
+    pub files: Vec<PathBuf>,
 
     /// Type of csv file to analyse
     pub csv_type: CsvType,
 
-    /// Optional output file to write the analysis results to, default to the input filename + _out.csv
+    /// Optional output file to write the analysis results to, default to the input filename + _out.csv, `-` to write to stdout.
+    /// When it is an existing directory, the default filename is used inside of it.
+    /// The {stem}, {date}, {type} and {ext} placeholders are replaced, e.g. reports/{stem}_{date}.csv
     #[arg(short, long)]
     pub output_file: Option<PathBuf>,
+
+    /// Format of the generated output
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Csv)]
+    pub format: OutputFormat,
+
+    /// Field delimiter of the CSV output, a single ASCII character or `\t` for tabs
+    #[arg(short, long, default_value = ",", value_parser = parse_delimiter)]
+    pub delimiter: u8,
+
+    /// Field used to sort the output rows, ties are broken by account id
+    #[arg(long, value_enum, default_value_t = SortKey::Account)]
+    pub sort_by: SortKey,
+
+    /// Key of the fees aggregation, months are sorted chronologically regardless of --sort-by
+    #[arg(long, value_enum, default_value_t = GroupBy::Account)]
+    pub group_by: GroupBy,
+
+    /// Sort the output rows in descending order
+    #[arg(long, overrides_with = "no_desc")]
+    pub desc: bool,
+
+    /// Sort the output rows in ascending order, e.g. to override `desc = true` of the config file
+    #[arg(long, overrides_with = "desc")]
+    pub no_desc: bool,
+
+    /// Number of decimals of the amounts in the CSV, TSV and Excel outputs, from 0 to 9,
+    /// defaults to the decimals of each currency, e.g. 2 for EUR
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(..=i64::from(amount_serde::MAX_PRECISION)))]
+    pub output_precision: Option<u32>,
+
+    /// Which fields of the CSV and TSV outputs are quoted
+    #[arg(long, value_enum, default_value_t = QuoteStyle::Necessary)]
+    pub quote_style: QuoteStyle,
+
+    /// Line ending of the CSV, TSV and JSON Lines outputs
+    #[arg(long, value_enum, default_value_t = LineEnding::Lf)]
+    pub line_ending: LineEnding,
+
+    /// Do not write the header row of the CSV and TSV outputs, the JSON output is unaffected
+    #[arg(long)]
+    pub no_header: bool,
+
+    /// Add a column with the largest single fee of each account, to spot outlier transactions
+    #[arg(long)]
+    pub max_fee: bool,
+
+    /// Add a column with the average fee per transaction of each account
+    #[arg(long)]
+    pub average_fee: bool,
+
+    /// Add a column with the fees minus the Amount Refunded of the fee rows of each account
+    #[arg(long)]
+    pub net_fees: bool,
+
+    /// Add columns with the smallest single fee of each account and its range up to the largest one
+    #[arg(long)]
+    pub fee_range: bool,
+
+    /// Only write these fees output columns, in this order, comma-separated names of the output header
+    #[arg(long, value_name = "NAMES", value_delimiter = ',')]
+    pub columns: Vec<String>,
+
+    /// Append a TOTAL row summing the transaction counts and fees of all accounts
+    #[arg(long)]
+    pub totals: bool,
+
+    /// Drop accounts whose total fees are below this amount, e.g. 10,00 or 10.00
+    #[arg(long, value_parser = amount_serde::parse)]
+    pub min_fees: Option<i64>,
+
+    /// Only write the N accounts with the highest fees, or with the most transactions with --sort-by count
+    #[arg(long, value_name = "N")]
+    pub top: Option<usize>,
+
+    /// Write the N accounts with the highest fees and sum the other accounts into an OTHERS row per currency
+    #[arg(long, value_name = "N", conflicts_with_all = ["top", "sort_by", "desc"])]
+    pub rollup_after: Option<usize>,
+
+    /// Only aggregate the fees of these account ids, comma-separated or repeated
+    #[arg(long, value_name = "IDS", value_delimiter = ',')]
+    pub only_accounts: Vec<String>,
+
+    /// Drop the fees of these account ids, comma-separated or repeated, even if they are in --only-accounts
+    #[arg(long, value_name = "IDS", value_delimiter = ',')]
+    pub exclude_accounts: Vec<String>,
+
+    /// Only process the first N data rows of the fees input files, to sample a huge export
+    #[arg(long, value_name = "N")]
+    pub limit: Option<usize>,
+
+    /// Skip malformed rows and report them on stderr instead of aborting
+    #[arg(long)]
+    pub skip_errors: bool,
+
+    /// CSV file listing the file, line, content and error of each row skipped with --skip-errors
+    #[arg(long, value_name = "PATH")]
+    pub errors_file: Option<PathBuf>,
+
+    /// Fail when the fees input has columns which are not read by the analysis, a sign the export format changed
+    #[arg(long)]
+    pub fail_on_unknown_columns: bool,
+
+    /// Print the headline numbers of the fees analysis to stdout instead of writing the output file
+    #[arg(long)]
+    pub summary: bool,
+
+    /// Add the number of distinct emails to the --summary output, to compare it with the number of accounts
+    #[arg(long, requires = "summary")]
+    pub count_unique_emails: bool,
+
+    /// Warn when an account appears with a different email, the most recent email is used in the output
+    #[arg(long)]
+    pub warn_email_changes: bool,
+
+    /// Compare the emails case-insensitively, e.g. when grouping by email, the casing of the first
+    /// email seen is used in the output
+    #[arg(long)]
+    pub normalize_email: bool,
+
+    /// Three-letter ISO currency code used to label the fees in the output, amounts are not converted
+    #[arg(long, default_value = "eur", value_parser = parse_currency)]
+    pub currency: String,
+
+    /// Number of threads used to aggregate the fees, 1 keeps the aggregation on a single thread
+    #[arg(short, long, default_value = "1")]
+    pub jobs: NonZeroUsize,
+
+    /// Print the columns of the header of each input file to stdout, one per line, and exit without analysing the rows
+    #[arg(long)]
+    pub dump_headers: bool,
+
+    /// Also print the fees output to stdout while writing it to the output file
+    #[arg(long)]
+    pub tee: bool,
+
+    /// Compare the fees with a previous fees export, writing the previous and current fees of each account sorted by the biggest changes
+    #[arg(long, value_name = "PREVIOUS", conflicts_with_all = ["watch", "summary", "assume_sorted"])]
+    pub diff: Option<PathBuf>,
+
+    /// Keep running and regenerate the fees output whenever an input file changes, until Ctrl-C
+    #[arg(long)]
+    pub watch: bool,
+
+    /// Print the number of rows and accounts processed so far to stderr every 100,000 rows
+    #[arg(long)]
+    pub progress: bool,
+
+    /// Name of the amount column in the header of the fees export, or its number with --input-has-no-header
+    #[arg(long, value_name = "NAME", default_value = "Amount")]
+    pub col_amount: String,
+
+    /// Name of the account id column in the header of the fees export, or its number with --input-has-no-header
+    #[arg(long, value_name = "NAME", default_value = "User ID")]
+    pub col_account_id: String,
+
+    /// Name of the email column in the header of the fees export, or its number with --input-has-no-header
+    #[arg(long, value_name = "NAME", default_value = "User Email")]
+    pub col_email: String,
+
+    /// The fees inputs have no header row, the --col-* flags give the 1-based numbers of the columns
+    #[arg(long)]
+    pub input_has_no_header: bool,
+
+    /// Field delimiter of the fees input files, a single ASCII character, `\t` for tabs,
+    /// or `auto` to detect it from the header line
+    #[arg(long, value_name = "CHAR", default_value = ",", value_parser = parse_input_delimiter)]
+    pub input_delimiter: InputDelimiter,
+
+    /// Character encoding of the fees input files, decoded to UTF-8 before being parsed
+    #[arg(long, value_enum, default_value_t = Encoding::Utf8)]
+    pub encoding: Encoding,
+
+    /// Name of the entry read from the .zip fees input files, e.g. export/fees.csv, their only CSV file by default
+    #[arg(long, value_name = "NAME")]
+    pub zip_entry: Option<String>,
+
+    /// The fees amounts are already in cents, e.g. 150 is 1.50 EUR, instead of being multiplied by 100
+    #[arg(long)]
+    pub amounts_in_cents: bool,
+
+    /// How fees with more decimals than their currency are rounded, e.g. 0,125 EUR
+    #[arg(long, value_enum, default_value_t = Rounding::HalfUp)]
+    pub rounding: Rounding,
+
+    /// Whether an empty fees amount is an error like a malformed one, zero, or skips its row
+    #[arg(long, value_enum, default_value_t = EmptyAmount::Error)]
+    pub empty_amount: EmptyAmount,
+
+    /// Decimal and grouping separators of the amounts, the last separator is the decimal one by default
+    #[arg(long, value_enum, default_value_t = Locale::Auto)]
+    pub locale: Locale,
+
+    /// Warn about the fees amounts which do not read back as the same value once converted to cents
+    #[arg(long)]
+    pub round_trip: bool,
+
+    /// Only aggregate the fees created on or after this UTC date, e.g. 2025-12-01
+    #[arg(long, value_name = "DATE", value_parser = timestamp_serde::parse_date)]
+    pub from: Option<NaiveDate>,
+
+    /// Only aggregate the fees created on or before this UTC date, e.g. 2025-12-31
+    #[arg(long, value_name = "DATE", value_parser = timestamp_serde::parse_date)]
+    pub to: Option<NaiveDate>,
+
+    /// Only aggregate the fees created in the last N days, from N days before today in UTC, instead of --from
+    #[arg(long, value_name = "N", conflicts_with = "from")]
+    pub since_days: Option<u32>,
+
+    /// The fees input is sorted by account id: each account is written as soon as the next one starts,
+    /// keeping a single account in memory, and an unsorted input fails
+    #[arg(long)]
+    pub assume_sorted: bool,
+
+    /// Drop the fees rows whose id was already seen, e.g. when combining overlapping exports
+    #[arg(long)]
+    pub dedupe: bool,
+
+    /// Skip the fees rows identical to the header, e.g. the header of each export when several
+    /// exports were concatenated into one file
+    #[arg(long)]
+    pub skip_repeated_headers: bool,
+
+    /// Trim the whitespace around the account ids and emails of the fees rows, so ids which only
+    /// differ by stray spaces are the same account
+    #[arg(long)]
+    pub trim_whitespace: bool,
+
+    /// TOML file with default values for the format, delimiter, sort and currency flags and the
+    /// column names, flags given on the command line take precedence
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+}
+
+/// Arguments supported by every analysis, the other options are only supported by the fees analysis.
+const COMMON_ARGUMENTS: [&str; 6] = [
+    "files",
+    "csv_type",
+    "output_file",
+    "locale",
+    "config",
+    "dump_headers",
+];
+
+/// Options of `--dump-headers`, which reads the header of any export.
+const DUMP_HEADERS_OPTIONS: [&str; 3] = ["encoding", "input_delimiter", "zip_entry"];
+
+impl Args {
+    /// First option given on the command line which is only supported by the fees analysis,
+    /// e.g. `--top` for a payouts analysis, `None` for the fees analysis.
+    ///
+    /// The options set by the config file are ignored, it can hold the defaults of the fees analysis.
+    #[must_use]
+    pub fn fees_only_option(&self, matches: &ArgMatches) -> Option<String> {
+        if self.csv_type == CsvType::Fees {
+            return None;
+        }
+
+        Self::command()
+            .get_arguments()
+            .find(|argument| {
+                let id = argument.get_id().as_str();
+                matches.value_source(id) == Some(ValueSource::CommandLine)
+                    && !COMMON_ARGUMENTS.contains(&id)
+                    && !(self.dump_headers && DUMP_HEADERS_OPTIONS.contains(&id))
+            })
+            .map(|argument| {
+                argument
+                    .get_long()
+                    .map_or_else(|| argument.get_id().to_string(), |long| format!("--{long}"))
+            })
+    }
+}
+
+/// Parses a CSV delimiter given on the command line.
+///
+/// # Errors
+///
+/// The delimiter is not a single ASCII character.
+pub fn parse_delimiter(delimiter: &str) -> Result<u8, String> {
+    match delimiter.as_bytes() {
+        br"\t" => Ok(b'\t'),
+        [byte] if byte.is_ascii() => Ok(*byte),
+        _ => Err(format!(
+            "the delimiter '{delimiter}' must be a single ASCII character"
+        )),
+    }
+}
+
+/// Parses the delimiter of the input files given on the command line, `auto` to detect it.
+///
+/// # Errors
+///
+/// The delimiter is neither `auto` nor a single ASCII character.
+pub fn parse_input_delimiter(delimiter: &str) -> Result<InputDelimiter, String> {
+    if delimiter.eq_ignore_ascii_case("auto") {
+        Ok(InputDelimiter::Auto)
+    } else {
+        parse_delimiter(delimiter).map(InputDelimiter::Byte)
+    }
+}
+
+/// Parses a three-letter ISO currency code given on the command line, returned in lowercase.
+///
+/// # Errors
+///
+/// The currency is not made of three ASCII letters.
+pub fn parse_currency(currency: &str) -> Result<String, String> {
+    if currency.len() == 3 && currency.bytes().all(|byte| byte.is_ascii_alphabetic()) {
+        Ok(currency.to_ascii_lowercase())
+    } else {
+        Err(format!(
+            "the currency '{currency}' must be a three-letter ISO code, e.g. eur"
+        ))
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 pub enum CsvType {
     /// Application fees analysis
     Fees,
+    /// Payouts analysis, grouped by currency
+    Payouts,
+    /// Disputes analysis, grouped by account
+    Disputes,
+    /// Refunds analysis, grouped by account
+    Refunds,
+    /// Balance transactions analysis, grouped by type
+    BalanceTransactions,
+    /// Payments analysis, grouped by account with a success rate
+    Payments,
+    /// Invoices analysis, grouped by customer with the paid and outstanding amounts
+    Invoices,
+    /// Subscriptions analysis, with the monthly recurring revenue
+    Subscriptions,
+    /// Connect transfers analysis, grouped by destination account
+    Transfers,
+    /// Stripe Tax analysis, grouped by jurisdiction
+    Tax,
+    /// Checkout sessions analysis, grouped by customer email
+    CheckoutSessions,
+    /// Customers analysis, counted by email domain
+    Customers,
+    /// Events log analysis, counted by event type
+    Events,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum OutputFormat {
+    /// Comma separated values
+    #[default]
+    Csv,
+    /// JSON array of objects
+    Json,
+    /// JSON Lines, one compact JSON object per line
+    Jsonl,
+    /// Tab separated values
+    Tsv,
+    /// Excel workbook with a single "Fees" sheet
+    Xlsx,
+    /// Sqlite database with a table of the account fees, e.g. to join against other data
+    Sqlite,
+}
+
+impl OutputFormat {
+    /// File extension used for outputs of this format.
+    #[must_use]
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Json => "json",
+            Self::Jsonl => "jsonl",
+            Self::Tsv => "tsv",
+            Self::Xlsx => "xlsx",
+            Self::Sqlite => "sqlite",
+        }
+    }
+}
+
+/// Field delimiter of the input files.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InputDelimiter {
+    /// The given ASCII character
+    Byte(u8),
+    /// Detected from the header line of each file
+    Auto,
+}
+
+impl Default for InputDelimiter {
+    fn default() -> Self {
+        Self::Byte(b',')
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum QuoteStyle {
+    /// Only the fields containing a delimiter, a quote or a line break
+    #[default]
+    Necessary,
+    /// Every field
+    Always,
+    /// No field, even when the output becomes ambiguous
+    Never,
+    /// Every field which is not a number
+    NonNumeric,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum LineEnding {
+    /// A line feed, `\n`
+    #[default]
+    Lf,
+    /// A carriage return and a line feed, `\r\n`, as expected by some Windows programs
+    Crlf,
+}
+
+impl LineEnding {
+    /// Bytes written at the end of each line.
+    #[must_use]
+    pub const fn as_bytes(self) -> &'static [u8] {
+        match self {
+            Self::Lf => b"\n",
+            Self::Crlf => b"\r\n",
+        }
+    }
+}
+
+impl From<LineEnding> for csv::Terminator {
+    fn from(line_ending: LineEnding) -> Self {
+        match line_ending {
+            LineEnding::Lf => Self::Any(b'\n'),
+            LineEnding::Crlf => Self::CRLF,
+        }
+    }
+}
+
+impl From<QuoteStyle> for csv::QuoteStyle {
+    fn from(quote_style: QuoteStyle) -> Self {
+        match quote_style {
+            QuoteStyle::Necessary => Self::Necessary,
+            QuoteStyle::Always => Self::Always,
+            QuoteStyle::Never => Self::Never,
+            QuoteStyle::NonNumeric => Self::NonNumeric,
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum Encoding {
+    /// UTF-8, the encoding of the Stripe exports
+    #[default]
+    #[value(name = "utf-8", alias = "utf8")]
+    Utf8,
+    /// Windows-1252, a superset of Latin-1 used by older exports
+    #[value(name = "windows-1252", alias = "latin-1")]
+    Windows1252,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum GroupBy {
+    /// Account id and currency
+    #[default]
+    Account,
+    /// Year and month of the creation time, e.g. 2025-12, and currency
+    Month,
+    /// Email and currency, case-insensitive with --normalize-email
+    Email,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum SortKey {
+    /// Account id
+    #[default]
+    Account,
+    /// Email, accounts without an email are last
+    Email,
+    /// Total fees
+    Fees,
+    /// Transaction count
+    Count,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum Rounding {
+    /// Halves are rounded away from zero, "0,125" is 13 cents
+    #[default]
+    HalfUp,
+    /// Halves are rounded to the even neighbour, "0,125" is 12 cents (banker's rounding)
+    HalfEven,
+    /// Rounded towards negative infinity
+    Floor,
+    /// Rounded towards positive infinity
+    Ceil,
+}
+
+impl From<Rounding> for amount_serde::Rounding {
+    fn from(rounding: Rounding) -> Self {
+        match rounding {
+            Rounding::HalfUp => Self::HalfUp,
+            Rounding::HalfEven => Self::HalfEven,
+            Rounding::Floor => Self::Floor,
+            Rounding::Ceil => Self::Ceil,
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum EmptyAmount {
+    /// Empty amounts are an error, like malformed ones
+    #[default]
+    Error,
+    /// Empty amounts are zero
+    Zero,
+    /// The rows with an empty amount are skipped
+    Skip,
+}
+
+impl From<EmptyAmount> for amount_serde::EmptyAmount {
+    fn from(empty_amount: EmptyAmount) -> Self {
+        match empty_amount {
+            EmptyAmount::Error => Self::Error,
+            EmptyAmount::Zero => Self::Zero,
+            EmptyAmount::Skip => Self::Skip,
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum Locale {
+    /// The last separator is the decimal one
+    #[default]
+    Auto,
+    /// Dot decimal separator and comma grouping, "1,234.56"
+    En,
+    /// Comma decimal separator and dot grouping, "1.234,56"
+    De,
+    /// Comma decimal separator and space grouping, "1 234,56"
+    Fr,
+}
+
+impl From<Locale> for amount_serde::Locale {
+    fn from(locale: Locale) -> Self {
+        match locale {
+            Locale::Auto => Self::Auto,
+            Locale::En => Self::En,
+            Locale::De => Self::De,
+            Locale::Fr => Self::Fr,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::FromArgMatches;
+
+    #[test]
+    fn test_parse_delimiter() {
+        assert_eq!(parse_delimiter(","), Ok(b','));
+        assert_eq!(parse_delimiter(";"), Ok(b';'));
+        assert_eq!(parse_delimiter(r"\t"), Ok(b'\t'));
+        assert_eq!(parse_delimiter("\t"), Ok(b'\t'));
+        assert!(parse_delimiter(";;").is_err());
+        assert!(parse_delimiter("€").is_err());
+        assert!(parse_delimiter("").is_err());
+    }
+
+    #[test]
+    fn test_parse_input_delimiter() {
+        assert_eq!(parse_input_delimiter(";"), Ok(InputDelimiter::Byte(b';')));
+        assert_eq!(
+            parse_input_delimiter(r"\t"),
+            Ok(InputDelimiter::Byte(b'\t'))
+        );
+        assert_eq!(parse_input_delimiter("auto"), Ok(InputDelimiter::Auto));
+        assert_eq!(parse_input_delimiter("AUTO"), Ok(InputDelimiter::Auto));
+        assert!(parse_input_delimiter("au").is_err());
+    }
+
+    #[test]
+    fn test_parse_currency() {
+        assert_eq!(parse_currency("eur"), Ok("eur".to_string()));
+        assert_eq!(parse_currency("USD"), Ok("usd".to_string()));
+        assert!(parse_currency("eu").is_err());
+        assert!(parse_currency("euro").is_err());
+        assert!(parse_currency("e1r").is_err());
+        assert!(parse_currency("").is_err());
+    }
+
+    fn fees_only_option(flags: &[&str]) -> Option<String> {
+        let matches = Args::command()
+            .try_get_matches_from(std::iter::once(&"stripe-csv").chain(flags))
+            .expect("should parse flags");
+        Args::from_arg_matches(&matches)
+            .expect("should create arguments")
+            .fees_only_option(&matches)
+    }
+
+    #[test]
+    fn test_fees_only_option() {
+        assert_eq!(fees_only_option(&["fees.csv", "fees", "--top", "3"]), None);
+        assert_eq!(
+            fees_only_option(&["payouts.csv", "payouts", "-o", "-", "--locale", "en"]),
+            None
+        );
+        assert_eq!(
+            fees_only_option(&["payouts.csv", "payouts", "--top", "3"]),
+            Some("--top".to_string())
+        );
+        assert_eq!(
+            fees_only_option(&["payouts.csv", "payouts", "--format", "json"]),
+            Some("--format".to_string())
+        );
+        assert_eq!(
+            fees_only_option(&["payouts.csv", "payouts", "--zip-entry", "payouts.csv"]),
+            Some("--zip-entry".to_string())
+        );
+        assert_eq!(
+            fees_only_option(&[
+                "payouts.zip",
+                "payouts",
+                "--dump-headers",
+                "--zip-entry",
+                "payouts.csv"
+            ]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_account_lists() {
+        let arguments = Args::try_parse_from([
+            "stripe-csv",
+            "fees.csv",
+            "fees",
+            "--only-accounts",
+            "acct_1,acct_2",
+            "--only-accounts",
+            "acct_3",
+            "--exclude-accounts",
+            "acct_2",
+        ])
+        .expect("should parse flags");
+
+        assert_eq!(arguments.only_accounts, ["acct_1", "acct_2", "acct_3"]);
+        assert_eq!(arguments.exclude_accounts, ["acct_2"]);
+    }
 }
@@ -0,0 +1,204 @@
+use std::path::{Path, PathBuf};
+
+use clap::{ArgMatches, ValueEnum, parser::ValueSource};
+
+use crate::args::{self, Args};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("unable to read the config file '{path}': {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("invalid config file '{path}': {source}")]
+    Parse {
+        path: PathBuf,
+        source: Box<toml::de::Error>,
+    },
+    #[error("invalid value for '{key}' in the config file: {message}")]
+    InvalidValue { key: &'static str, message: String },
+}
+
+/// Default flags loaded from a TOML file, keys are named after the long flags, e.g. `sort-by`.
+///
+/// The flags given on the command line take precedence over the config file,
+/// which takes precedence over the built-in defaults.
+#[derive(Debug, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub format: Option<String>,
+    pub delimiter: Option<String>,
+    pub sort_by: Option<String>,
+    pub desc: Option<bool>,
+    pub currency: Option<String>,
+    pub col_amount: Option<String>,
+    pub col_account_id: Option<String>,
+    pub col_email: Option<String>,
+}
+
+impl Config {
+    /// Loads the config file located at the given path.
+    ///
+    /// # Errors
+    ///
+    /// File could not be read or is not a valid config file.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let content = std::fs::read_to_string(path).map_err(|source| Error::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        toml::from_str(&content).map_err(|source| Error::Parse {
+            path: path.to_path_buf(),
+            source: Box::new(source),
+        })
+    }
+
+    /// Sets the arguments which were not given on the command line to the config file values.
+    ///
+    /// # Errors
+    ///
+    /// A value of the config file is not valid for its flag.
+    pub fn apply(self, arguments: &mut Args, matches: &ArgMatches) -> Result<(), Error> {
+        let is_default = |id: &str| matches.value_source(id) != Some(ValueSource::CommandLine);
+
+        if let Some(format) = self.format.filter(|_| is_default("format")) {
+            arguments.format = value_enum("format", &format)?;
+        }
+        if let Some(delimiter) = self.delimiter.filter(|_| is_default("delimiter")) {
+            arguments.delimiter =
+                args::parse_delimiter(&delimiter).map_err(|message| Error::InvalidValue {
+                    key: "delimiter",
+                    message,
+                })?;
+        }
+        if let Some(sort_by) = self.sort_by.filter(|_| is_default("sort_by")) {
+            arguments.sort_by = value_enum("sort-by", &sort_by)?;
+        }
+        if let Some(desc) = self
+            .desc
+            .filter(|_| is_default("desc") && is_default("no_desc"))
+        {
+            arguments.desc = desc;
+        }
+        if let Some(currency) = self.currency.filter(|_| is_default("currency")) {
+            arguments.currency =
+                args::parse_currency(&currency).map_err(|message| Error::InvalidValue {
+                    key: "currency",
+                    message,
+                })?;
+        }
+        if let Some(col_amount) = self.col_amount.filter(|_| is_default("col_amount")) {
+            arguments.col_amount = col_amount;
+        }
+        if let Some(col_account_id) = self.col_account_id.filter(|_| is_default("col_account_id")) {
+            arguments.col_account_id = col_account_id;
+        }
+        if let Some(col_email) = self.col_email.filter(|_| is_default("col_email")) {
+            arguments.col_email = col_email;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses a value of the config file with the same names as on the command line.
+fn value_enum<T: ValueEnum>(key: &'static str, value: &str) -> Result<T, Error> {
+    T::from_str(value, false).map_err(|message| Error::InvalidValue { key, message })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::{OutputFormat, SortKey};
+    use clap::{CommandFactory, FromArgMatches};
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn arguments(config: &str, flags: &[&str]) -> Result<Args, Error> {
+        let command_line = ["stripe-csv", "fees.csv", "fees"].iter().chain(flags);
+        let matches = Args::command()
+            .try_get_matches_from(command_line)
+            .expect("should parse flags");
+        let mut arguments = Args::from_arg_matches(&matches).expect("should create arguments");
+        let config: Config = toml::from_str(config).expect("should parse config");
+        config.apply(&mut arguments, &matches)?;
+        Ok(arguments)
+    }
+
+    #[test]
+    fn test_config_overrides_defaults() {
+        let arguments = arguments(
+            "format = \"tsv\"\ndelimiter = \";\"\nsort-by = \"fees\"\ndesc = true\ncurrency = \"USD\"\ncol-amount = \"Fee\"",
+            &[],
+        )
+        .expect("should apply config");
+
+        assert_eq!(arguments.format, OutputFormat::Tsv);
+        assert_eq!(arguments.delimiter, b';');
+        assert_eq!(arguments.sort_by, SortKey::Fees);
+        assert!(arguments.desc);
+        assert_eq!(arguments.currency, "usd");
+        assert_eq!(arguments.col_amount, "Fee");
+        assert_eq!(arguments.col_email, "User Email");
+    }
+
+    #[test]
+    fn test_command_line_overrides_config() {
+        let arguments = arguments(
+            "format = \"tsv\"\nsort-by = \"fees\"\ncurrency = \"usd\"",
+            &[
+                "--format",
+                "json",
+                "--sort-by",
+                "account",
+                "--currency",
+                "gbp",
+            ],
+        )
+        .expect("should apply config");
+
+        assert_eq!(arguments.format, OutputFormat::Json);
+        assert_eq!(arguments.sort_by, SortKey::Account);
+        assert_eq!(arguments.currency, "gbp");
+    }
+
+    #[test]
+    fn test_command_line_no_desc_overrides_config() {
+        let ascending = arguments("desc = true", &["--no-desc"]).expect("should apply config");
+        assert!(!ascending.desc);
+
+        let descending =
+            arguments("desc = true", &["--no-desc", "--desc"]).expect("should apply config");
+        assert!(descending.desc);
+    }
+
+    #[test]
+    fn test_invalid_config_value() {
+        let error = arguments("sort-by = \"size\"", &[]).expect_err("should be error");
+        assert!(error.to_string().contains("sort-by"));
+        assert!(arguments("currency = \"euro\"", &[]).is_err());
+    }
+
+    #[test]
+    fn test_load() {
+        let mut temp_file = NamedTempFile::new().expect("should create temp file");
+        writeln!(temp_file, "format = \"json\"\ncol-email = \"Email\"")
+            .expect("should write to temp file");
+
+        let config = Config::load(temp_file.path()).expect("should load config");
+        assert_eq!(config.format.as_deref(), Some("json"));
+        assert_eq!(config.col_email.as_deref(), Some("Email"));
+    }
+
+    #[test]
+    fn test_load_unknown_key() {
+        let mut temp_file = NamedTempFile::new().expect("should create temp file");
+        writeln!(temp_file, "colour = \"red\"").expect("should write to temp file");
+
+        assert!(matches!(
+            Config::load(temp_file.path()),
+            Err(Error::Parse { .. })
+        ));
+    }
+}
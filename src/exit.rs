@@ -0,0 +1,82 @@
+use crate::{
+    config,
+    parser::{common, fees::FeesError},
+};
+
+/// Exit code of the errors which are not told apart, e.g. an option not supported by an analysis.
+pub const FAILURE: i32 = 1;
+/// Exit code of a missing input, likely a misconfiguration.
+pub const NOT_FOUND: i32 = 2;
+/// Exit code of an input which is not a valid Stripe export.
+pub const MALFORMED_DATA: i32 = 3;
+/// Exit code of an input or output which could not be read from or written to.
+pub const IO: i32 = 4;
+/// Exit code of a config file which could not be read or is invalid.
+pub const CONFIG: i32 = 5;
+/// Exit code of an invalid command line, `EX_USAGE` of `sysexits.h`.
+pub const USAGE: i32 = 64;
+
+/// Process exit code of an error of any analysis, so scripts can tell a missing input from
+/// malformed data, see [`FeesError::exit_code`].
+#[must_use]
+pub fn code(error: &(dyn std::error::Error + 'static)) -> i32 {
+    if let Some(error) = error.downcast_ref::<FeesError>() {
+        return error.exit_code();
+    }
+    if let Some(error) = error.downcast_ref::<csv::Error>() {
+        return if error.is_io_error() {
+            IO
+        } else {
+            MALFORMED_DATA
+        };
+    }
+
+    if error.is::<common::FileNotFound>() {
+        NOT_FOUND
+    } else if error.is::<std::io::Error>() {
+        IO
+    } else if error.is::<config::Error>() {
+        CONFIG
+    } else if error.is::<clap::Error>() {
+        USAGE
+    } else {
+        FAILURE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_code() {
+        let missing: Box<dyn std::error::Error> =
+            common::FileNotFound(PathBuf::from("payouts.csv")).into();
+        assert_eq!(code(missing.as_ref()), NOT_FOUND);
+
+        let missing: Box<dyn std::error::Error> =
+            FeesError::FileNotFound(PathBuf::from("fees.csv")).into();
+        assert_eq!(code(missing.as_ref()), NOT_FOUND);
+
+        let malformed = csv::Reader::from_reader(&b"a,b\n1,2,3\n"[..])
+            .records()
+            .find_map(Result::err)
+            .expect("should be error");
+        let malformed: Box<dyn std::error::Error> = malformed.into();
+        assert_eq!(code(malformed.as_ref()), MALFORMED_DATA);
+
+        let io: Box<dyn std::error::Error> = std::io::Error::other("disk full").into();
+        assert_eq!(code(io.as_ref()), IO);
+
+        let config =
+            config::Config::load(std::path::Path::new("/tmp/non_existent_config_12345.toml"))
+                .expect_err("should be error");
+        let config: Box<dyn std::error::Error> = config.into();
+        assert_eq!(code(config.as_ref()), CONFIG);
+
+        let other: Box<dyn std::error::Error> =
+            "--watch is only supported by the fees analysis".into();
+        assert_eq!(code(other.as_ref()), FAILURE);
+    }
+}
@@ -1,3 +1,7 @@
 pub mod amount_serde;
 pub mod args;
+pub mod config;
+pub mod exit;
 pub mod parser;
+pub mod prelude;
+pub mod timestamp_serde;
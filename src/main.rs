@@ -1,18 +1,136 @@
-use clap::Parser;
+use clap::{CommandFactory, FromArgMatches, ValueEnum, error::ErrorKind};
+use std::path::PathBuf;
 use stripe_csv::{
+    amount_serde,
     args::{Args, CsvType},
-    parser,
+    config::Config,
+    exit,
+    parser::{
+        self, common,
+        fees::{self, FeesError},
+    },
 };
 
-fn main() {
-    let arguments = Args::parse();
+/// Prints an error of the command line and exits with [`exit::USAGE`], `--help` and `--version`
+/// are printed to stdout and exit with `0`.
+fn exit_usage(error: &clap::Error) -> ! {
+    if !error.use_stderr() {
+        error.exit();
+    }
+    let _ = error.print();
+    std::process::exit(exit::USAGE);
+}
+
+/// Parses the command line, the flags which were not given default to the config file values.
+fn arguments() -> Result<Args, Box<dyn std::error::Error>> {
+    let matches = Args::command()
+        .try_get_matches()
+        .unwrap_or_else(|error| exit_usage(&error));
+    let mut arguments = Args::from_arg_matches(&matches).unwrap_or_else(|error| exit_usage(&error));
+    if let Some(option) = arguments.fees_only_option(&matches) {
+        exit_usage(&Args::command().error(
+            ErrorKind::ArgumentConflict,
+            format!("{option} is only supported by the fees analysis"),
+        ));
+    }
+    if let Some(path) = &arguments.config {
+        Config::load(path)?.apply(&mut arguments, &matches)?;
+    }
+    Ok(arguments)
+}
+
+/// Expands the placeholders of the output file, named after the first input file,
+/// see [`common::expand_output_template`].
+fn expand_output_file(arguments: &mut Args) -> Result<(), common::OutputTemplateError> {
+    let (Some(template), Some(file)) = (&arguments.output_file, arguments.files.first()) else {
+        return Ok(());
+    };
+    let analysis = arguments
+        .csv_type
+        .to_possible_value()
+        .map_or_else(String::new, |value| value.get_name().to_string());
+    let extension = if arguments.csv_type == CsvType::Fees {
+        arguments.format.extension()
+    } else {
+        "csv"
+    };
+    arguments.output_file = Some(common::expand_output_template(
+        template,
+        file,
+        &analysis,
+        extension,
+        chrono::Utc::now().date_naive(),
+    )?);
+    Ok(())
+}
+
+/// Prints the header of each input file, see [`common::dump_headers`].
+fn dump_headers(arguments: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let mut stdout = std::io::stdout().lock();
+    for file in &arguments.files {
+        if common::is_missing(file) {
+            return Err(common::FileNotFound(file.clone()).into());
+        }
+        eprintln!("headers of file: {}", file.display());
+        common::dump_headers(
+            file,
+            arguments.encoding,
+            arguments.input_delimiter,
+            arguments.zip_entry.as_deref(),
+            &mut stdout,
+        )?;
+    }
+    Ok(())
+}
+
+/// Runs the fees analysis of every input file, or compares or watches them.
+fn run_fees(arguments: Args) -> Result<(), FeesError> {
+    let options = fees::Options::from(&arguments);
+    if let Some(previous) = &arguments.diff {
+        return fees::diff_files(&arguments.files, previous, arguments.output_file, &options);
+    }
+    if arguments.watch {
+        return fees::watch(&arguments.files, arguments.output_file.as_ref(), &options);
+    }
+    fees::parse_files(&arguments.files, arguments.output_file, &options)
+}
+
+/// Parse function of the analyses of a single file, e.g. [`parser::payouts::parse`].
+type Parse = fn(PathBuf, Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>>;
 
-    if let Err(error) = match arguments.csv_type {
-        CsvType::Fees => parser::fees::parse(arguments.file, arguments.output_file),
-    } {
+fn run(mut arguments: Args) -> Result<(), Box<dyn std::error::Error>> {
+    if arguments.dump_headers {
+        return dump_headers(&arguments);
+    }
+    expand_output_file(&mut arguments)?;
+    let parse: Parse = match arguments.csv_type {
+        CsvType::Fees => return Ok(run_fees(arguments)?),
+        CsvType::Payouts => parser::payouts::parse,
+        CsvType::Disputes => parser::disputes::parse,
+        CsvType::Refunds => parser::refunds::parse,
+        CsvType::Payments => parser::payments::parse,
+        CsvType::BalanceTransactions => parser::balance::parse,
+        CsvType::Invoices => parser::invoices::parse,
+        CsvType::Subscriptions => parser::subscriptions::parse,
+        CsvType::Transfers => parser::transfers::parse,
+        CsvType::Tax => parser::tax::parse,
+        CsvType::CheckoutSessions => parser::checkout::parse,
+        CsvType::Customers => parser::customers::parse,
+        CsvType::Events => parser::events::parse,
+    };
+
+    let [file] = <[PathBuf; 1]>::try_from(arguments.files)
+        .map_err(|_| "multiple input files are only supported by the fees analysis")?;
+    amount_serde::with_locale(arguments.locale.into(), || {
+        parse(file, arguments.output_file)
+    })
+}
+
+fn main() {
+    if let Err(error) = arguments().and_then(run) {
         eprintln!("Error: {error}");
-        std::process::exit(1);
+        std::process::exit(exit::code(error.as_ref()));
     }
 
-    println!("done.");
+    eprintln!("done.");
 }
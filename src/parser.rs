@@ -1 +1,14 @@
+pub mod balance;
+pub mod checkout;
+pub mod common;
+pub mod customers;
+pub mod disputes;
+pub mod events;
 pub mod fees;
+pub mod invoices;
+pub mod payments;
+pub mod payouts;
+pub mod refunds;
+pub mod subscriptions;
+pub mod tax;
+pub mod transfers;
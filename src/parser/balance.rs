@@ -0,0 +1,199 @@
+use super::common;
+use crate::amount_serde::{self, deserialize as amount_serde};
+use std::{collections::BTreeMap, path::PathBuf};
+
+#[derive(Debug, serde::Deserialize)]
+struct Entry {
+    #[serde(rename = "Type")]
+    pub kind: String,
+    #[serde(rename = "Amount", deserialize_with = "amount_serde")]
+    pub amount: i64,
+    #[serde(rename = "Fee", deserialize_with = "amount_serde")]
+    pub fee: i64,
+    #[serde(rename = "Net", deserialize_with = "amount_serde")]
+    pub net: i64,
+    #[serde(rename = "Currency")]
+    pub currency: String,
+    #[serde(rename = "Created (UTC)")]
+    pub created: String,
+}
+
+impl Entry {
+    /// Whether the net amount is the amount minus the fee.
+    pub const fn is_net_consistent(&self) -> bool {
+        self.amount - self.fee == self.net
+    }
+}
+
+#[derive(Debug)]
+struct TypeBalance {
+    pub kind: String,
+    pub currency: String,
+    pub transaction_count: u32,
+    pub total_net: i64,
+}
+
+impl TypeBalance {
+    pub fn new(kind: &str, currency: &str) -> Self {
+        Self {
+            kind: kind.to_string(),
+            currency: currency.to_string(),
+            transaction_count: 0,
+            total_net: 0,
+        }
+    }
+
+    pub const fn add_transaction(&mut self, net: i64) {
+        self.transaction_count += 1;
+        self.total_net += net;
+    }
+
+    pub const fn csv_header() -> &'static str {
+        "type,currency,transaction_count,total_net"
+    }
+
+    pub fn record(&self) -> [String; 4] {
+        [
+            self.kind.clone(),
+            self.currency.clone(),
+            self.transaction_count.to_string(),
+            amount_serde::format(
+                self.total_net,
+                amount_serde::currency_exponent(&self.currency),
+            ),
+        ]
+    }
+}
+
+/// Parse a Stripe balance transactions CSV file located at the given path, `-` reads from stdin.
+///
+/// Transactions are grouped by type (charge, refund, payout, adjustment...) and currency, sorted
+/// by type, a warning is printed for every row whose `Net` is not `Amount - Fee`.
+///
+/// # Errors
+///
+/// File was not found or could not be read.
+/// Unable to parse the CSV file.
+/// Unable to create an Entry from a CSV line.
+/// Output file could not be created or written to, `-` writes to stdout.
+pub fn parse(file: PathBuf, output: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut csv_reader, output) = common::open(file, output, "balance transactions")?;
+    let mut statistics: BTreeMap<(String, String), TypeBalance> = BTreeMap::new();
+    for result in common::entries(&mut csv_reader)? {
+        let entry: Entry = result?;
+        if !entry.is_net_consistent() {
+            let exponent = amount_serde::currency_exponent(&entry.currency);
+            eprintln!(
+                "warning: the net {} of the {} created at {} is not the amount {} minus the fee {}",
+                amount_serde::format(entry.net, exponent),
+                entry.kind,
+                entry.created,
+                amount_serde::format(entry.amount, exponent),
+                amount_serde::format(entry.fee, exponent)
+            );
+        }
+
+        statistics
+            .entry((entry.kind.to_lowercase(), entry.currency.to_lowercase()))
+            .or_insert_with_key(|(kind, currency)| TypeBalance::new(kind, currency))
+            .add_transaction(entry.net);
+    }
+
+    common::write_csv(
+        &output,
+        TypeBalance::csv_header(),
+        statistics.values().map(TypeBalance::record),
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::common::parser_test;
+
+    const HEADER: &str = "id,Type,Amount,Fee,Net,Currency,Created (UTC)";
+
+    parser_test!(
+        entry_deserialize_valid,
+        format!("{HEADER}\ntxn_123,charge,\"100,00\",\"1,75\",\"98,25\",eur,2025-12-31 14:30"),
+        kind is "charge".to_string(),
+        amount is 10000,
+        fee is 175,
+        net is 9825,
+        currency is "eur".to_string(),
+        created is "2025-12-31 14:30".to_string()
+    );
+
+    parser_test!(
+        test_entry_deserialize_missing_net,
+        "Type,Amount,Fee,Currency,Created (UTC)\ncharge,\"100,00\",\"1,75\",eur,2025-12-31 14:30",
+        is_error
+    );
+
+    parser_test!(
+        test_entry_deserialize_invalid_fee,
+        format!("{HEADER}\ntxn_123,charge,\"100,00\",invalid,\"98,25\",eur,2025-12-31 14:30"),
+        is_error
+    );
+
+    #[test]
+    fn test_net_consistency() {
+        let data = format!(
+            "{HEADER}\ntxn_1,charge,\"100,00\",\"1,75\",\"98,25\",eur,2025-12-31 14:30\ntxn_2,charge,\"100,00\",\"1,75\",\"100,00\",eur,2025-12-31 15:30"
+        );
+        let mut reader = csv::Reader::from_reader(data.as_bytes());
+        let consistency: Vec<bool> = reader
+            .deserialize::<Entry>()
+            .map(|entry| entry.expect("should be ok").is_net_consistent())
+            .collect();
+        assert_eq!(consistency, [true, false]);
+    }
+
+    parser_test!(
+        test_parse_groups_by_type,
+        file,
+        format!(
+            "{HEADER}\ntxn_1,charge,\"100,00\",\"1,75\",\"98,25\",eur,2025-12-31 14:30\ntxn_2,Charge,\"50,00\",\"1,00\",\"49,00\",eur,2025-12-31 15:30\ntxn_3,refund,\"-20,00\",\"0,00\",\"-20,00\",eur,2025-12-31 16:00\ntxn_4,payout,\"-100,00\",\"0,00\",\"-100,00\",eur,2025-12-31 18:00\ntxn_5,charge,1000,30,970,jpy,2025-12-31 19:00"
+        ),
+        [
+            TypeBalance::csv_header(),
+            "charge,eur,2,147.25",
+            "charge,jpy,1,970",
+            "payout,eur,1,-100.00",
+            "refund,eur,1,-20.00"
+        ]
+    );
+
+    parser_test!(
+        test_parse_inconsistent_net_is_kept,
+        file,
+        format!("{HEADER}\ntxn_1,adjustment,\"10,00\",\"1,00\",\"10,00\",eur,2025-12-31 14:30"),
+        [TypeBalance::csv_header(), "adjustment,eur,1,10.00"]
+    );
+
+    parser_test!(
+        test_parse_empty_csv,
+        file,
+        HEADER,
+        [TypeBalance::csv_header()]
+    );
+
+    parser_test!(
+        test_parse_invalid_csv_format,
+        file,
+        format!("{HEADER}\ntxn_1,charge,invalid,\"1,75\",\"98,25\",eur,2025-12-31 14:30"),
+        is_error
+    );
+
+    #[test]
+    fn test_parse_file_not_found() {
+        let non_existent_path = PathBuf::from("/tmp/non_existent_balance_12345.csv");
+        let result = parse(non_existent_path, None);
+
+        assert!(result.is_err());
+        let err_msg = result.expect_err("should be error").to_string();
+        assert!(err_msg.contains("does not exist"));
+    }
+}
@@ -0,0 +1,270 @@
+use super::common::{self, DEFAULT_CURRENCY};
+use crate::{
+    amount_serde::{self, deserialize as amount_serde},
+    timestamp_serde::deserialize as timestamp_serde,
+};
+use chrono::NaiveDateTime;
+use std::{collections::BTreeMap, path::PathBuf};
+
+/// Email of the overall row of each currency.
+const TOTAL: &str = "TOTAL";
+
+#[derive(Debug, serde::Deserialize)]
+struct Entry {
+    #[serde(rename = "Amount Total", deserialize_with = "amount_serde")]
+    pub amount_total: i64,
+    #[serde(rename = "Status")]
+    pub status: String,
+    #[serde(rename = "Customer Email")]
+    pub email: String,
+    /// Currency as written in the export, `eur` when the export has no `Currency` column.
+    #[serde(rename = "Currency", default = "common::default_currency")]
+    pub currency: String,
+    #[serde(rename = "Created (UTC)", deserialize_with = "timestamp_serde")]
+    pub created: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Complete,
+    Open,
+    Expired,
+}
+
+impl Entry {
+    /// Status of the session, `None` for statuses which are not analyzed.
+    pub fn status(&self) -> Option<Status> {
+        match self.status.to_ascii_lowercase().as_str() {
+            "complete" => Some(Status::Complete),
+            "open" => Some(Status::Open),
+            "expired" => Some(Status::Expired),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CustomerSessions {
+    pub email: String,
+    pub currency: String,
+    pub completed_count: u32,
+    pub open_count: u32,
+    pub expired_count: u32,
+    pub total_completed: i64,
+}
+
+impl CustomerSessions {
+    pub fn new(email: &str, currency: &str) -> Self {
+        Self {
+            email: email.to_string(),
+            currency: currency.to_string(),
+            completed_count: 0,
+            open_count: 0,
+            expired_count: 0,
+            total_completed: 0,
+        }
+    }
+
+    /// Adds a session, only the completed sessions count towards the total amount.
+    pub const fn add_session(&mut self, amount: i64, status: Status) {
+        match status {
+            Status::Complete => {
+                self.completed_count += 1;
+                self.total_completed += amount;
+            }
+            Status::Open => self.open_count += 1,
+            Status::Expired => self.expired_count += 1,
+        }
+    }
+
+    /// Adds the sessions of another customer in the same currency, to compute the overall row.
+    pub const fn merge(&mut self, other: &Self) {
+        self.completed_count += other.completed_count;
+        self.open_count += other.open_count;
+        self.expired_count += other.expired_count;
+        self.total_completed += other.total_completed;
+    }
+
+    /// Percentage of the finished sessions which were completed rather than expired,
+    /// open sessions may still be completed and are left out.
+    pub fn completion_rate(&self) -> f64 {
+        let finished = self.completed_count + self.expired_count;
+        if finished == 0 {
+            return 0.0;
+        }
+
+        f64::from(self.completed_count) * 100.0 / f64::from(finished)
+    }
+
+    pub const fn csv_header() -> &'static str {
+        "email,currency,completed_count,open_count,expired_count,total_completed,completion_rate"
+    }
+
+    pub fn record(&self) -> [String; 7] {
+        [
+            self.email.clone(),
+            self.currency.clone(),
+            self.completed_count.to_string(),
+            self.open_count.to_string(),
+            self.expired_count.to_string(),
+            amount_serde::format(
+                self.total_completed,
+                amount_serde::currency_exponent(&self.currency),
+            ),
+            format!("{:.2}", self.completion_rate()),
+        ]
+    }
+}
+
+/// Parse a Stripe Checkout sessions CSV file located at the given path, `-` reads from stdin.
+///
+/// Sessions are grouped by customer email and currency, sorted by email, with a count per status,
+/// the total amount of the completed sessions and the percentage of the finished sessions which
+/// were completed, followed by a `TOTAL` row per currency over every customer.
+/// Sessions with another status are ignored.
+/// The period covered by the sessions is printed to stderr.
+///
+/// # Errors
+///
+/// File was not found or could not be read.
+/// Unable to parse the CSV file.
+/// Unable to create an Entry from a CSV line.
+/// Output file could not be created or written to, `-` writes to stdout.
+pub fn parse(file: PathBuf, output: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut csv_reader, output) = common::open(file, output, "checkout sessions")?;
+    let mut statistics: BTreeMap<(String, String), CustomerSessions> = BTreeMap::new();
+    let mut period = common::Period::default();
+    for result in common::entries(&mut csv_reader)? {
+        let entry: Entry = result?;
+        period.add(entry.created);
+        let Some(status) = entry.status() else {
+            continue;
+        };
+
+        statistics
+            .entry((entry.email.clone(), entry.currency.to_lowercase()))
+            .or_insert_with_key(|(email, currency)| CustomerSessions::new(email, currency))
+            .add_session(entry.amount_total, status);
+    }
+    period.report("checkout sessions");
+
+    let mut totals: BTreeMap<String, CustomerSessions> = BTreeMap::new();
+    for statistic in statistics.values() {
+        totals
+            .entry(statistic.currency.clone())
+            .or_insert_with_key(|currency| CustomerSessions::new(TOTAL, currency))
+            .merge(statistic);
+    }
+    if totals.is_empty() {
+        totals.insert(
+            DEFAULT_CURRENCY.to_string(),
+            CustomerSessions::new(TOTAL, DEFAULT_CURRENCY),
+        );
+    }
+
+    common::write_csv(
+        &output,
+        CustomerSessions::csv_header(),
+        statistics
+            .values()
+            .chain(totals.values())
+            .map(CustomerSessions::record),
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::common::parser_test;
+
+    const HEADER: &str = "id,Amount Total,Currency,Status,Customer Email,Created (UTC)";
+
+    parser_test!(
+        entry_deserialize_valid,
+        format!("{HEADER}\ncs_123,\"49,90\",eur,complete,user@example.com,2025-12-31 14:30"),
+        amount_total is 4990,
+        status is "complete".to_string(),
+        email is "user@example.com".to_string()
+    );
+
+    parser_test!(
+        test_entry_deserialize_missing_status,
+        "id,Amount Total,Currency,Customer Email,Created (UTC)\ncs_123,\"49,90\",eur,user@example.com,2025-12-31 14:30",
+        is_error
+    );
+
+    parser_test!(
+        test_entry_deserialize_invalid_amount,
+        format!("{HEADER}\ncs_123,invalid,eur,complete,user@example.com,2025-12-31 14:30"),
+        is_error
+    );
+
+    parser_test!(
+        test_parse_groups_by_email,
+        file,
+        format!(
+            "{HEADER}\ncs_1,\"49,90\",eur,complete,user@example.com,2025-12-31 14:30\ncs_2,\"10,00\",eur,expired,user@example.com,2025-12-30 10:00\ncs_3,\"20,00\",eur,Complete,user@example.com,2025-12-30 11:00\ncs_4,\"15,00\",eur,open,test@example.com,2025-12-29 09:00\ncs_5,\"5,00\",eur,expired,test@example.com,2025-12-29 10:00"
+        ),
+        [
+            CustomerSessions::csv_header(),
+            "test@example.com,eur,0,1,1,0.00,0.00",
+            "user@example.com,eur,2,0,1,69.90,66.67",
+            "TOTAL,eur,2,1,2,69.90,50.00"
+        ]
+    );
+
+    parser_test!(
+        test_parse_ignores_unknown_statuses,
+        file,
+        format!(
+            "{HEADER}\ncs_1,\"49,90\",eur,complete,user@example.com,2025-12-31 14:30\ncs_2,\"10,00\",eur,unknown,user@example.com,2025-12-30 10:00"
+        ),
+        [
+            CustomerSessions::csv_header(),
+            "user@example.com,eur,1,0,0,49.90,100.00",
+            "TOTAL,eur,1,0,0,49.90,100.00"
+        ]
+    );
+
+    parser_test!(
+        test_parse_total_per_currency,
+        file,
+        format!(
+            "{HEADER}\ncs_1,\"49,90\",eur,complete,user@example.com,2025-12-31 14:30\ncs_2,5000,jpy,complete,user@example.com,2025-12-30 10:00\ncs_3,3000,JPY,expired,test@example.com,2025-12-30 11:00"
+        ),
+        [
+            CustomerSessions::csv_header(),
+            "test@example.com,jpy,0,0,1,0,0.00",
+            "user@example.com,eur,1,0,0,49.90,100.00",
+            "user@example.com,jpy,1,0,0,5000,100.00",
+            "TOTAL,eur,1,0,0,49.90,100.00",
+            "TOTAL,jpy,1,0,1,5000,50.00"
+        ]
+    );
+
+    parser_test!(
+        test_parse_empty_csv,
+        file,
+        HEADER,
+        [CustomerSessions::csv_header(), "TOTAL,eur,0,0,0,0.00,0.00"]
+    );
+
+    parser_test!(
+        test_parse_invalid_csv_format,
+        file,
+        format!("{HEADER}\ncs_1,\"49,90\",eur,complete,user@example.com,31/12/2025"),
+        is_error
+    );
+
+    #[test]
+    fn test_parse_file_not_found() {
+        let non_existent_path = PathBuf::from("/tmp/non_existent_checkout_12345.csv");
+        let result = parse(non_existent_path, None);
+
+        assert!(result.is_err());
+        let err_msg = result.expect_err("should be error").to_string();
+        assert!(err_msg.contains("does not exist"));
+    }
+}
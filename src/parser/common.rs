@@ -0,0 +1,1338 @@
+use crate::{
+    amount_serde::{self, DEFAULT_EXPONENT},
+    args::{Encoding, InputDelimiter},
+};
+use chrono::NaiveDateTime;
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use flate2::{Compression, read::MultiGzDecoder, write::GzEncoder};
+use serde::de::DeserializeOwned;
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+};
+use tempfile::NamedTempFile;
+
+/// Path used on the command line to read from stdin or write to stdout.
+pub const STDIO: &str = "-";
+
+/// Returns true if the path refers to stdin or stdout.
+#[must_use]
+pub fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == STDIO
+}
+
+/// Returns true if the path is an existing file which is not a regular file nor a directory,
+/// such as a named pipe or the `/dev/fd/63` of a shell process substitution.
+///
+/// The files of `/dev` are always special, `/dev/stdin` is a regular file when redirected from one.
+#[must_use]
+pub fn is_special_file(path: &Path) -> bool {
+    std::fs::metadata(path).is_ok_and(|metadata| {
+        let file_type = metadata.file_type();
+        path.starts_with("/dev") || (!file_type.is_file() && !file_type.is_dir())
+    })
+}
+
+/// Returns true if the input file does not exist, stdin and the special files are never missing.
+#[must_use]
+pub fn is_missing(file: &Path) -> bool {
+    !is_stdio(file) && !is_special_file(file) && !file.exists()
+}
+
+/// The input file of an analysis does not exist.
+#[derive(thiserror::Error, Debug)]
+#[error("the file '{0}' does not exist")]
+pub struct FileNotFound(pub PathBuf);
+
+/// An output path cannot be derived from the name of an input which is not a regular file.
+#[derive(thiserror::Error, Debug)]
+#[error("an --output-file is required when reading from '{0}', which is not a regular file")]
+pub struct OutputRequired(pub PathBuf);
+
+/// A zip input has no CSV file to read, or several and none was chosen with `--zip-entry`.
+#[derive(thiserror::Error, Debug)]
+pub enum ZipEntryError {
+    #[error("the archive '{0}' has no CSV file")]
+    NoCsvFile(PathBuf),
+    #[error("the archive '{path}' has several CSV files, choose one with --zip-entry: {entries}")]
+    SeveralCsvFiles { path: PathBuf, entries: String },
+    #[error("the archive '{path}' has no entry named '{entry}'")]
+    MissingEntry { path: PathBuf, entry: String },
+}
+
+/// Returns true if the path has a `.zip` extension.
+#[must_use]
+pub fn is_zip(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("zip"))
+}
+
+/// Returns true if the path has a `.gz` extension.
+#[must_use]
+pub fn is_gzip(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("gz"))
+}
+
+/// Template of the default output filename, see [`default_output`].
+pub const DEFAULT_OUTPUT_TEMPLATE: &str = "{stem}_out.{ext}";
+
+/// Default output path for the given input file: the input filename + `_out.<extension>`,
+/// the [`DEFAULT_OUTPUT_TEMPLATE`] expanded by [`expand_output_template`] next to the input.
+///
+/// The `.gz` extension of compressed inputs is ignored, `fees.csv.gz` gives `fees_out.csv`.
+/// When reading from stdin the output defaults to `stdin_out.<extension>` in the current directory.
+#[must_use]
+pub fn default_output(file: &Path, extension: &str) -> PathBuf {
+    // The template has neither a {type} nor a {date} and only known placeholders, it always expands.
+    let name = expand_output_template(
+        Path::new(DEFAULT_OUTPUT_TEMPLATE),
+        file,
+        "",
+        extension,
+        chrono::NaiveDate::MIN,
+    )
+    .unwrap_or_default();
+    if is_stdio(file) {
+        return name;
+    }
+
+    let mut output_path = file.to_path_buf();
+    output_path.set_file_name(name);
+    output_path
+}
+
+/// Filename of the input without its extensions, ignoring the `.gz` extension of compressed
+/// inputs, `stdin` when reading from stdin.
+fn input_stem(file: &Path) -> &str {
+    if is_stdio(file) {
+        return "stdin";
+    }
+
+    let uncompressed = if is_gzip(file) {
+        file.file_stem().map(Path::new)
+    } else {
+        Some(file)
+    };
+    uncompressed
+        .and_then(Path::file_stem)
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("output")
+}
+
+/// An output file template has a placeholder which is not one of [`expand_output_template`].
+#[derive(thiserror::Error, Debug)]
+pub enum OutputTemplateError {
+    #[error(
+        "unknown placeholder '{{{name}}}' in the output file '{template}', the placeholders are {{stem}}, {{date}}, {{type}} and {{ext}}"
+    )]
+    UnknownPlaceholder { name: String, template: String },
+    #[error("unclosed placeholder in the output file '{0}', write {{{{ for a literal brace")]
+    Unclosed(String),
+    #[error("unopened placeholder in the output file '{0}', write }}}} for a literal brace")]
+    Unopened(String),
+}
+
+/// Replaces the placeholders of an output file template, e.g. `reports/{stem}_{date}.csv`:
+///
+/// - `{stem}`: filename of the input without its extensions, see [`default_output`]
+/// - `{date}`: the given date, e.g. `2025-12-31`
+/// - `{type}`: name of the analysis, e.g. `fees`
+/// - `{ext}`: extension of the output format, e.g. `csv`
+///
+/// `{{` and `}}` are literal braces, outputs without placeholders are returned unchanged.
+///
+/// # Errors
+///
+/// The template has an unknown placeholder, or a lone `{` or `}`.
+pub fn expand_output_template(
+    template: &Path,
+    file: &Path,
+    analysis: &str,
+    extension: &str,
+    date: chrono::NaiveDate,
+) -> Result<PathBuf, OutputTemplateError> {
+    let Some(text) = template.to_str().filter(|text| text.contains(['{', '}'])) else {
+        return Ok(template.to_path_buf());
+    };
+
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(position) = rest.find(['{', '}']) {
+        expanded.push_str(&rest[..position]);
+        let (brace, after) = rest[position..].split_at(1);
+        if let Some(after) = after.strip_prefix(brace) {
+            expanded.push_str(brace);
+            rest = after;
+            continue;
+        }
+        if brace == "}" {
+            return Err(OutputTemplateError::Unopened(text.to_string()));
+        }
+
+        let Some((name, after)) = after.split_once('}') else {
+            return Err(OutputTemplateError::Unclosed(text.to_string()));
+        };
+        match name {
+            "stem" => expanded.push_str(input_stem(file)),
+            "date" => expanded.push_str(&date.format("%Y-%m-%d").to_string()),
+            "type" => expanded.push_str(analysis),
+            "ext" => expanded.push_str(extension),
+            _ => {
+                return Err(OutputTemplateError::UnknownPlaceholder {
+                    name: name.to_string(),
+                    template: text.to_string(),
+                });
+            }
+        }
+        rest = after;
+    }
+    expanded.push_str(rest);
+    Ok(PathBuf::from(expanded))
+}
+
+/// Resolves the output path of an analysis of the given input file.
+///
+/// Without an output the [`default_output`] is used, and when the output is an existing directory
+/// the default output filename is used inside of it.
+///
+/// # Errors
+///
+/// The input is a [special file](is_special_file) and the output is not given or is a directory,
+/// the name of a pipe such as `/dev/fd/63` being no base for the output filename.
+pub fn output_path(
+    file: &Path,
+    output: Option<PathBuf>,
+    extension: &str,
+) -> Result<PathBuf, OutputRequired> {
+    let default = || {
+        if !is_stdio(file) && is_special_file(file) {
+            return Err(OutputRequired(file.to_path_buf()));
+        }
+        Ok(default_output(file, extension))
+    };
+    match output {
+        Some(output) if output.is_dir() => Ok(default()?
+            .file_name()
+            .map_or_else(|| output.clone(), |name| output.join(name))),
+        Some(output) => Ok(output),
+        None => default(),
+    }
+}
+
+/// CSV reader of an input opened by [`reader`].
+pub type Reader = csv::Reader<Box<dyn Read>>;
+
+/// UTF-8 byte order mark written by some tools at the start of the exports.
+const BOM: char = '\u{feff}';
+
+/// Removes a UTF-8 byte order mark left at the start of the first header.
+///
+/// The csv reader already skips a BOM at the start of the input, unless the input is read in
+/// chunks smaller than the BOM, e.g. through a decompressor.
+#[must_use]
+pub fn strip_bom(headers: &csv::StringRecord) -> csv::StringRecord {
+    headers
+        .iter()
+        .enumerate()
+        .map(|(column, header)| {
+            if column == 0 {
+                header.trim_start_matches(BOM)
+            } else {
+                header
+            }
+        })
+        .collect()
+}
+
+/// Opens a csv reader on the given file, or on stdin if the path is `-`.
+///
+/// Files with a `.gz` extension are decompressed while being read,
+/// the single CSV file of a `.zip` archive is read, see [`reader_with`].
+///
+/// # Errors
+///
+/// The file could not be opened.
+pub fn reader(file: &Path) -> Result<csv::Reader<Box<dyn Read>>, csv::Error> {
+    Ok(csv::Reader::from_reader(input(file, None)?))
+}
+
+/// Opens the input of an analysis and resolves its CSV output, see [`output_path`].
+///
+/// # Errors
+///
+/// The file does not exist or could not be opened, or no output path can be derived from it.
+pub fn open(
+    file: PathBuf,
+    output: Option<PathBuf>,
+    analysis: &str,
+) -> Result<(Reader, PathBuf), Box<dyn std::error::Error>> {
+    if is_missing(&file) {
+        return Err(FileNotFound(file).into());
+    }
+
+    let output = output_path(&file, output, "csv")?;
+
+    eprintln!("parsing {analysis} from file: {}", file.display());
+    Ok((reader(&file)?, output))
+}
+
+/// Deserializes the rows of a CSV file, the amounts of a row being parsed with the exponent of
+/// its `Currency` column, see [`amount_serde::currency_exponent`].
+///
+/// The amounts of the files without a `Currency` column are parsed with [`DEFAULT_EXPONENT`].
+///
+/// # Errors
+///
+/// The header could not be read, the iterator yields the rows which could not be read or deserialized.
+pub fn entries<T: DeserializeOwned, R: Read>(
+    csv_reader: &mut csv::Reader<R>,
+) -> csv::Result<impl Iterator<Item = csv::Result<T>>> {
+    let headers = strip_bom(csv_reader.headers()?);
+    let currency_column = headers.iter().position(|header| header == "Currency");
+    Ok(csv_reader.records().map(move |result| {
+        let record = result?;
+        let exponent = currency_column
+            .and_then(|column| record.get(column))
+            .map_or(DEFAULT_EXPONENT, amount_serde::currency_exponent);
+        amount_serde::with_exponent(exponent, || record.deserialize(Some(&headers)))
+    }))
+}
+
+/// Currency of the rows when the export has no `Currency` column.
+pub const DEFAULT_CURRENCY: &str = "eur";
+
+/// Default of the `Currency` column, see [`DEFAULT_CURRENCY`].
+#[must_use]
+pub fn default_currency() -> String {
+    DEFAULT_CURRENCY.to_string()
+}
+
+/// Parses an amount like [`amount_serde::deserialize`], a blank cell is zero, e.g. the refunded
+/// amount of a fee which was not refunded.
+///
+/// # Errors
+///
+/// The amount is not in the format of [`amount_serde::parse`].
+pub fn amount_or_zero<'de, D>(deserializer: D) -> Result<i64, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let amount: String = serde::Deserialize::deserialize(deserializer)?;
+    if amount_serde::is_blank(&amount) {
+        return Ok(0);
+    }
+    amount_serde::parse(&amount).map_err(serde::de::Error::custom)
+}
+
+/// First and last creation times of the rows of an export.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Period(Option<(NaiveDateTime, NaiveDateTime)>);
+
+impl Period {
+    /// Extends the period to the given creation time, rows without one are ignored.
+    pub fn add(&mut self, created: Option<NaiveDateTime>) {
+        if let Some(created) = created {
+            self.0 = Some(self.0.map_or((created, created), |(first, last)| {
+                (first.min(created), last.max(created))
+            }));
+        }
+    }
+
+    /// Prints the period covered by the rows to stderr, e.g. `refunds created from ... to ...`,
+    /// nothing when no row has a creation time.
+    pub fn report(&self, rows: &str) {
+        if let Some((first, last)) = self.0 {
+            eprintln!("{rows} created from {first} to {last}");
+        }
+    }
+}
+
+/// Opens a CSV reader like [`reader`], decoding the file from the given encoding to UTF-8.
+///
+/// # Errors
+///
+/// The file could not be opened.
+pub fn reader_with_encoding(
+    file: &Path,
+    encoding: Encoding,
+) -> Result<csv::Reader<Box<dyn Read>>, csv::Error> {
+    reader_with(file, encoding, InputDelimiter::default(), &[], true, None)
+}
+
+/// Opens a CSV reader like [`reader_with_encoding`] with the given field delimiter.
+///
+/// With [`InputDelimiter::Auto`] the delimiter is sniffed from the header line, see [`sniff_delimiter`].
+///
+/// Without `has_headers` the first line is read as a data row.
+///
+/// The CSV file of a `.zip` archive is read into memory and decompressed: the entry named
+/// `zip_entry`, or the only entry with a `.csv` extension when none is given.
+///
+/// # Errors
+///
+/// The file could not be opened, a zip archive has no such entry, or its header line could not be
+/// read while sniffing the delimiter.
+pub fn reader_with(
+    file: &Path,
+    encoding: Encoding,
+    delimiter: InputDelimiter,
+    expected_columns: &[&str],
+    has_headers: bool,
+    zip_entry: Option<&str>,
+) -> Result<csv::Reader<Box<dyn Read>>, csv::Error> {
+    let input = input(file, zip_entry)?;
+    let input: Box<dyn Read> = match encoding {
+        Encoding::Utf8 => input,
+        Encoding::Windows1252 => Box::new(
+            DecodeReaderBytesBuilder::new()
+                .encoding(Some(encoding_rs::WINDOWS_1252))
+                .build(input),
+        ),
+    };
+    let (delimiter, input) = match delimiter {
+        InputDelimiter::Byte(delimiter) => (delimiter, input),
+        InputDelimiter::Auto => {
+            let (delimiter, input) = sniff_delimiter(input, expected_columns)?;
+            eprintln!(
+                "detected the delimiter '{}' in file: {}",
+                delimiter.escape_ascii(),
+                file.display()
+            );
+            (delimiter, input)
+        }
+    };
+
+    Ok(csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(has_headers)
+        .from_reader(input))
+}
+
+/// Writes the header of a CSV file to `output`, one column per line, without reading its rows.
+///
+/// The file is opened like [`reader_with`], the header is written as read, without the BOM.
+///
+/// # Errors
+///
+/// The file could not be opened, its header could not be read, or the output could not be written to.
+pub fn dump_headers<W: Write>(
+    file: &Path,
+    encoding: Encoding,
+    delimiter: InputDelimiter,
+    zip_entry: Option<&str>,
+    output: &mut W,
+) -> Result<(), csv::Error> {
+    let mut csv_reader = reader_with(file, encoding, delimiter, &[], true, zip_entry)?;
+    for header in &strip_bom(csv_reader.headers()?) {
+        writeln!(output, "{header}")?;
+    }
+    Ok(())
+}
+
+/// Delimiters tried when sniffing the delimiter of an input, by order of preference.
+const SNIFFED_DELIMITERS: [u8; 4] = [b',', b';', b'\t', b'|'];
+
+/// Detects the delimiter of an input from its header line, returning the delimiter and the whole input.
+///
+/// The delimiter splitting the header into the most `expected_columns` wins, then the one giving
+/// the most fields, ties are broken in favour of the comma, then the semicolon, tab and pipe.
+///
+/// # Errors
+///
+/// The header line could not be read.
+pub fn sniff_delimiter(
+    input: Box<dyn Read>,
+    expected_columns: &[&str],
+) -> std::io::Result<(u8, Box<dyn Read>)> {
+    let mut input = BufReader::new(input);
+    let mut header = Vec::new();
+    input.read_until(b'\n', &mut header)?;
+
+    let score = |delimiter: u8| {
+        let fields: Vec<String> = header
+            .split(|byte| *byte == delimiter)
+            .map(|field| {
+                String::from_utf8_lossy(field)
+                    .trim_start_matches(BOM)
+                    .trim()
+                    .trim_matches('"')
+                    .to_string()
+            })
+            .collect();
+        let expected = fields
+            .iter()
+            .filter(|field| expected_columns.contains(&field.as_str()))
+            .count();
+        (expected, fields.len())
+    };
+    let mut best = (SNIFFED_DELIMITERS[0], score(SNIFFED_DELIMITERS[0]));
+    for delimiter in &SNIFFED_DELIMITERS[1..] {
+        let candidate = score(*delimiter);
+        if candidate > best.1 {
+            best = (*delimiter, candidate);
+        }
+    }
+
+    let input: Box<dyn Read> = Box::new(std::io::Cursor::new(header).chain(input));
+    Ok((best.0, input))
+}
+
+/// Opens the file, stdin for `-`, decompressing files with a `.gz` extension
+/// and reading the CSV file of a `.zip` archive, see [`zip_input`].
+fn input(file: &Path, zip_entry: Option<&str>) -> std::io::Result<Box<dyn Read>> {
+    Ok(if is_stdio(file) {
+        Box::new(std::io::stdin())
+    } else if is_zip(file) {
+        Box::new(zip_input(file, zip_entry)?)
+    } else if is_gzip(file) {
+        Box::new(MultiGzDecoder::new(File::open(file)?))
+    } else {
+        Box::new(File::open(file)?)
+    })
+}
+
+/// Decompresses the entry of a zip archive named `zip_entry` into memory, or its only entry
+/// with a `.csv` extension when none is given.
+///
+/// # Errors
+///
+/// The archive could not be read, or has no such entry, no CSV file or several ones.
+fn zip_input(file: &Path, zip_entry: Option<&str>) -> std::io::Result<std::io::Cursor<Vec<u8>>> {
+    let invalid =
+        |error: ZipEntryError| std::io::Error::new(std::io::ErrorKind::InvalidData, error);
+    let mut archive = zip::ZipArchive::new(File::open(file)?).map_err(std::io::Error::other)?;
+    let name = if let Some(entry) = zip_entry {
+        entry.to_string()
+    } else {
+        let csv_files: Vec<&str> = archive
+            .file_names()
+            .filter(|name| {
+                Path::new(name)
+                    .extension()
+                    .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"))
+            })
+            .collect();
+        match csv_files.as_slice() {
+            [] => return Err(invalid(ZipEntryError::NoCsvFile(file.to_path_buf()))),
+            [name] => (*name).to_string(),
+            names => {
+                let mut entries = names.to_vec();
+                entries.sort_unstable();
+                return Err(invalid(ZipEntryError::SeveralCsvFiles {
+                    path: file.to_path_buf(),
+                    entries: entries.join(", "),
+                }));
+            }
+        }
+    };
+
+    let mut entry = match archive.by_name(&name) {
+        Ok(entry) => entry,
+        Err(zip::result::ZipError::FileNotFound) => {
+            return Err(invalid(ZipEntryError::MissingEntry {
+                path: file.to_path_buf(),
+                entry: name,
+            }));
+        }
+        Err(error) => return Err(std::io::Error::other(error)),
+    };
+    let mut content = Vec::new();
+    entry.read_to_end(&mut content)?;
+    Ok(std::io::Cursor::new(content))
+}
+
+/// Writes to an output and copies everything written to a second writer, e.g. stdout.
+#[derive(Debug)]
+pub struct Tee<A, B> {
+    output: A,
+    copy: Option<B>,
+}
+
+impl<A: Write, B: Write> Tee<A, B> {
+    /// Writes to the output, and to the copy when there is one.
+    pub const fn new(output: A, copy: Option<B>) -> Self {
+        Self { output, copy }
+    }
+}
+
+impl<A: Write, B: Write> Write for Tee<A, B> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.output.write(buf)?;
+        if let Some(copy) = &mut self.copy {
+            copy.write_all(&buf[..written])?;
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.output.flush()?;
+        if let Some(copy) = &mut self.copy {
+            copy.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Output of an analysis opened by [`writer`].
+///
+/// Files are written to a temporary file in the same directory, which replaces the destination
+/// once [`Output::commit`] is called. The temporary file is removed if the output is dropped
+/// without being committed, leaving the destination unchanged.
+/// Files with a `.gz` extension are compressed while being written.
+#[derive(Debug)]
+pub enum Output {
+    Stdout(std::io::Stdout),
+    File {
+        temp: NamedTempFile,
+        path: PathBuf,
+    },
+    Gzip {
+        encoder: Box<GzEncoder<NamedTempFile>>,
+        path: PathBuf,
+    },
+}
+
+impl Output {
+    /// Flushes the output and moves the temporary file over the destination.
+    ///
+    /// # Errors
+    ///
+    /// The output could not be flushed or the temporary file could not be renamed.
+    pub fn commit(self) -> std::io::Result<()> {
+        match self {
+            Self::Stdout(mut stdout) => stdout.flush(),
+            Self::File { temp, path } => persist(temp, &path),
+            Self::Gzip { encoder, path } => persist(encoder.finish()?, &path),
+        }
+    }
+}
+
+/// Moves the temporary file over the destination, keeping the permissions of the destination.
+fn persist(mut temp: NamedTempFile, path: &Path) -> std::io::Result<()> {
+    temp.flush()?;
+    if let Ok(metadata) = std::fs::metadata(path) {
+        temp.as_file().set_permissions(metadata.permissions())?;
+    }
+    temp.persist(path).map_err(|error| error.error)?;
+    Ok(())
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Stdout(stdout) => stdout.write(buf),
+            Self::File { temp, .. } => temp.write(buf),
+            Self::Gzip { encoder, .. } => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Stdout(stdout) => stdout.flush(),
+            Self::File { temp, .. } => temp.flush(),
+            Self::Gzip { encoder, .. } => encoder.flush(),
+        }
+    }
+}
+
+/// Opens the given output file for writing, or stdout if the path is `-`.
+///
+/// Missing parent directories are created, the file is only replaced by [`Output::commit`].
+/// Paths with a `.gz` extension are gzip-compressed.
+///
+/// # Errors
+///
+/// The temporary file or the parent directories could not be created.
+pub fn writer(output: &Path) -> std::io::Result<Output> {
+    if is_stdio(output) {
+        return Ok(Output::Stdout(std::io::stdout()));
+    }
+
+    let directory = output
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    std::fs::create_dir_all(directory)?;
+
+    let temp = temp_file(directory)?;
+    let path = output.to_path_buf();
+    if is_gzip(output) {
+        return Ok(Output::Gzip {
+            encoder: Box::new(GzEncoder::new(temp, Compression::default())),
+            path,
+        });
+    }
+
+    Ok(Output::File { temp, path })
+}
+
+/// Creates the temporary file of an output in the given directory with the mode of a file created
+/// by [`std::fs::File::create`], `0o666` restricted by the umask, instead of the private `0o600`
+/// of temporary files.
+fn temp_file(directory: &Path) -> std::io::Result<NamedTempFile> {
+    let mut builder = tempfile::Builder::new();
+    #[cfg(unix)]
+    builder.permissions(std::os::unix::fs::PermissionsExt::from_mode(0o666));
+    builder.tempfile_in(directory)
+}
+
+/// Writes the result of an analysis as CSV to the given output, see [`writer`].
+///
+/// The header is a comma separated list of columns, the fields of the records are quoted when
+/// they contain a delimiter, a quote or a line break.
+///
+/// # Errors
+///
+/// The output could not be created or written to.
+pub fn write_csv<I>(output: &Path, header: &str, records: I) -> csv::Result<()>
+where
+    I: IntoIterator,
+    I::Item: IntoIterator,
+    <I::Item as IntoIterator>::Item: AsRef<[u8]>,
+{
+    eprintln!("writing results to file: {}", output.display());
+    let mut csv_writer = csv::Writer::from_writer(writer(output)?);
+    csv_writer.write_record(header.split(','))?;
+    for record in records {
+        csv_writer.write_record(record)?;
+    }
+    csv_writer
+        .into_inner()
+        .map_err(csv::IntoInnerError::into_error)?
+        .commit()?;
+    Ok(())
+}
+
+/// Tests of the `Entry` and of the `parse` function of the module it is used in.
+///
+/// - `name, data, property is value, ...`: the first row of `data` deserializes to an entry with the given properties
+/// - `name, data, is_error`: the first row of `data` cannot be deserialized
+/// - `name, file, data, is_error`: parsing `data` fails
+/// - `name, file, data, [lines]`: parsing `data` writes the given lines, in order
+#[cfg(test)]
+macro_rules! parser_test {
+    ($name:ident, $data:expr, $($property:ident is $value:expr),+) => {
+        #[test]
+        fn $name() {
+            let data = $data;
+            let mut reader = csv::Reader::from_reader(data.as_bytes());
+            let entry: Entry = $crate::parser::common::entries(&mut reader)
+                .expect("should read headers")
+                .next()
+                .expect("should have entry")
+                .expect("should be ok");
+
+            $(
+                assert_eq!(entry.$property, $value);
+            )*
+        }
+    };
+    ($name:ident, $data:expr, is_error) => {
+        #[test]
+        fn $name() {
+            let data = $data;
+            let mut reader = csv::Reader::from_reader(data.as_bytes());
+            let entry: Result<Entry, _> = $crate::parser::common::entries(&mut reader)
+                .expect("should read headers")
+                .next()
+                .expect("should have entry");
+            assert!(entry.is_err());
+        }
+    };
+    ($name:ident, file, $data:expr, is_error) => {
+        #[test]
+        fn $name() {
+            use std::io::Write as _;
+
+            let mut temp_file = tempfile::NamedTempFile::new().expect("should create temp file");
+            let out_temp_file = tempfile::NamedTempFile::new().expect("should create out temp file");
+            writeln!(temp_file, "{}", $data).expect("should write to temp file");
+
+            let result = parse(temp_file.path().to_path_buf(), Some(out_temp_file.path().to_path_buf()));
+            assert!(result.is_err());
+        }
+    };
+    ($name:ident, file, $data:expr, $expected:expr) => {
+        #[test]
+        fn $name() {
+            use std::io::Write as _;
+
+            let mut temp_file = tempfile::NamedTempFile::new().expect("should create temp file");
+            let out_temp_file = tempfile::NamedTempFile::new().expect("should create out temp file");
+            writeln!(temp_file, "{}", $data).expect("should write to temp file");
+
+            parse(temp_file.path().to_path_buf(), Some(out_temp_file.path().to_path_buf())).expect("should parse");
+            let output = std::fs::read_to_string(out_temp_file.path()).expect("should read output");
+            let lines: Vec<&str> = output.lines().collect();
+            assert_eq!(lines, $expected);
+        }
+    };
+}
+#[cfg(test)]
+pub(crate) use parser_test;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_stdio() {
+        assert!(is_stdio(Path::new("-")));
+        assert!(!is_stdio(Path::new("-.csv")));
+        assert!(!is_stdio(Path::new("fees.csv")));
+    }
+
+    #[test]
+    fn test_default_output_from_file() {
+        assert_eq!(
+            default_output(Path::new("path/to/fees.csv"), "csv"),
+            PathBuf::from("path/to/fees_out.csv")
+        );
+    }
+
+    #[test]
+    fn test_default_output_extension() {
+        assert_eq!(
+            default_output(Path::new("path/to/fees.csv"), "json"),
+            PathBuf::from("path/to/fees_out.json")
+        );
+    }
+
+    fn expand(template: &str, file: &str) -> Result<PathBuf, OutputTemplateError> {
+        let date = chrono::NaiveDate::from_ymd_opt(2025, 12, 31).expect("should be a date");
+        expand_output_template(Path::new(template), Path::new(file), "fees", "json", date)
+    }
+
+    #[test]
+    fn test_expand_output_template() {
+        assert_eq!(
+            expand("reports/{stem}_{date}.{ext}", "path/to/fees.csv.gz").expect("should expand"),
+            PathBuf::from("reports/fees_2025-12-31.json")
+        );
+        assert_eq!(
+            expand("{type}/{stem}-{stem}", "-").expect("should expand"),
+            PathBuf::from("fees/stdin-stdin")
+        );
+        assert_eq!(
+            expand("{{stem}}_}}.csv", "fees.csv").expect("should expand"),
+            PathBuf::from("{stem}_}.csv")
+        );
+        assert_eq!(
+            expand("results/report.csv", "fees.csv").expect("should expand"),
+            PathBuf::from("results/report.csv")
+        );
+    }
+
+    #[test]
+    fn test_expand_output_template_errors() {
+        let error = expand("reports/{month}.csv", "fees.csv").expect_err("should be error");
+        assert!(matches!(
+            &error,
+            OutputTemplateError::UnknownPlaceholder { name, .. } if name == "month"
+        ));
+        assert!(error.to_string().contains("unknown placeholder '{month}'"));
+
+        let error = expand("reports/{stem.csv", "fees.csv").expect_err("should be error");
+        assert!(matches!(error, OutputTemplateError::Unclosed(_)));
+
+        let error = expand("reports/{stem}_}.csv", "fees.csv").expect_err("should be error");
+        assert!(matches!(error, OutputTemplateError::Unopened(_)));
+        assert!(error.to_string().contains("write }} for a literal brace"));
+    }
+
+    #[test]
+    fn test_entries_currency_exponent() {
+        #[derive(Debug, serde::Deserialize)]
+        struct Entry {
+            #[serde(rename = "Amount", deserialize_with = "amount_serde::deserialize")]
+            amount: i64,
+        }
+
+        let data = "Amount,Currency\n\"12,34\",eur\n1234,jpy\n\"1,234\",kwd";
+        let mut csv_reader = csv::Reader::from_reader(data.as_bytes());
+        let amounts: Vec<i64> = entries(&mut csv_reader)
+            .expect("should read headers")
+            .map(|entry: csv::Result<Entry>| entry.expect("should be ok").amount)
+            .collect();
+        assert_eq!(amounts, [1234, 1234, 1234]);
+
+        let mut csv_reader = csv::Reader::from_reader(&b"Amount\n\"12,34\""[..]);
+        let entry: Entry = entries(&mut csv_reader)
+            .expect("should read headers")
+            .next()
+            .expect("should have entry")
+            .expect("should be ok");
+        assert_eq!(entry.amount, 1234);
+    }
+
+    #[test]
+    fn test_write_csv_quotes_fields() {
+        let output = NamedTempFile::new().expect("should create temp file");
+        write_csv(
+            output.path(),
+            "name,count",
+            [["Paris, FR", "1"], ["\"quoted\"", "2"]],
+        )
+        .expect("should write");
+
+        let content = std::fs::read_to_string(output.path()).expect("should read output");
+        assert_eq!(
+            content,
+            "name,count\n\"Paris, FR\",1\n\"\"\"quoted\"\"\",2\n"
+        );
+    }
+
+    #[test]
+    fn test_output_path_defaults() {
+        assert_eq!(
+            output_path(Path::new("path/to/fees.csv"), None, "csv").expect("should derive output"),
+            PathBuf::from("path/to/fees_out.csv")
+        );
+    }
+
+    #[test]
+    fn test_output_path_file() {
+        assert_eq!(
+            output_path(
+                Path::new("path/to/fees.csv"),
+                Some(PathBuf::from("results/report.csv")),
+                "csv"
+            )
+            .expect("should use output"),
+            PathBuf::from("results/report.csv")
+        );
+    }
+
+    #[test]
+    fn test_output_path_directory() {
+        let directory = tempfile::tempdir().expect("should create temp dir");
+        assert_eq!(
+            output_path(
+                Path::new("path/to/fees.csv.gz"),
+                Some(directory.path().to_path_buf()),
+                "json"
+            )
+            .expect("should derive output"),
+            directory.path().join("fees_out.json")
+        );
+        assert_eq!(
+            output_path(Path::new("-"), Some(directory.path().to_path_buf()), "csv")
+                .expect("should derive output"),
+            directory.path().join("stdin_out.csv")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_output_path_special_file() {
+        let device = Path::new("/dev/null");
+        assert!(is_special_file(device));
+        assert!(!is_missing(device));
+        assert!(!is_special_file(Path::new("Cargo.toml")));
+        assert!(is_missing(Path::new("/tmp/non_existent_fees_12345.csv")));
+
+        let error = output_path(device, None, "csv").expect_err("should require an output");
+        assert!(error.to_string().contains("--output-file is required"));
+        let directory = tempfile::tempdir().expect("should create temp dir");
+        assert!(output_path(device, Some(directory.path().to_path_buf()), "csv").is_err());
+        assert_eq!(
+            output_path(device, Some(PathBuf::from("report.csv")), "csv")
+                .expect("should use output"),
+            PathBuf::from("report.csv")
+        );
+    }
+
+    #[test]
+    fn test_strip_bom() {
+        let headers = csv::StringRecord::from(vec!["\u{feff}Amount", "User ID"]);
+        assert_eq!(strip_bom(&headers), vec!["Amount", "User ID"]);
+
+        let headers = csv::StringRecord::from(vec!["Amount", "\u{feff}User ID"]);
+        assert_eq!(strip_bom(&headers), headers);
+    }
+
+    #[test]
+    fn test_reader_skips_bom() {
+        let mut temp_file = NamedTempFile::new().expect("should create temp file");
+        temp_file
+            .write_all("\u{feff}Amount,User ID\n\"0,25\",acct_123\n".as_bytes())
+            .expect("should write to temp file");
+
+        let mut reader = reader(temp_file.path()).expect("should open reader");
+        assert_eq!(
+            reader.headers().expect("should have headers"),
+            vec!["Amount", "User ID"]
+        );
+    }
+
+    #[test]
+    fn test_is_gzip() {
+        assert!(is_gzip(Path::new("fees.csv.gz")));
+        assert!(is_gzip(Path::new("fees.csv.GZ")));
+        assert!(!is_gzip(Path::new("fees.csv")));
+        assert!(!is_gzip(Path::new("gz")));
+    }
+
+    #[test]
+    fn test_default_output_from_gzip() {
+        assert_eq!(
+            default_output(Path::new("path/to/fees.csv.gz"), "csv"),
+            PathBuf::from("path/to/fees_out.csv")
+        );
+    }
+
+    #[test]
+    fn test_reader_gzip_file() {
+        let mut temp_file = tempfile::Builder::new()
+            .suffix(".csv.gz")
+            .tempfile()
+            .expect("should create temp file");
+        let mut encoder =
+            flate2::write::GzEncoder::new(&mut temp_file, flate2::Compression::default());
+        encoder
+            .write_all(b"Amount,User ID\n\"0,25\",acct_123\n")
+            .expect("should compress");
+        encoder.finish().expect("should finish compression");
+
+        let mut reader = reader(temp_file.path()).expect("should open reader");
+        let record = reader
+            .records()
+            .next()
+            .expect("should have record")
+            .expect("should be ok");
+        assert_eq!(&record[1], "acct_123");
+    }
+
+    #[test]
+    fn test_reader_concatenated_gzip_file() {
+        let mut temp_file = tempfile::Builder::new()
+            .suffix(".csv.gz")
+            .tempfile()
+            .expect("should create temp file");
+        for member in ["Amount,User ID\n\"0,25\",acct_123\n", "\"0,50\",acct_456\n"] {
+            let mut encoder =
+                flate2::write::GzEncoder::new(&mut temp_file, flate2::Compression::default());
+            encoder
+                .write_all(member.as_bytes())
+                .expect("should compress");
+            encoder.finish().expect("should finish compression");
+        }
+
+        let mut reader = reader(temp_file.path()).expect("should open reader");
+        let accounts: Vec<String> = reader
+            .records()
+            .map(|record| record.expect("should be ok")[1].to_string())
+            .collect();
+        assert_eq!(accounts, ["acct_123", "acct_456"]);
+    }
+
+    fn zip_archive(entries: &[(&str, &str)]) -> NamedTempFile {
+        let temp_file = tempfile::Builder::new()
+            .suffix(".zip")
+            .tempfile()
+            .expect("should create temp file");
+        let mut archive = zip::ZipWriter::new(temp_file.reopen().expect("should reopen temp file"));
+        for (name, content) in entries {
+            archive
+                .start_file(*name, zip::write::SimpleFileOptions::default())
+                .expect("should start entry");
+            archive
+                .write_all(content.as_bytes())
+                .expect("should write entry");
+        }
+        archive.finish().expect("should write archive");
+        temp_file
+    }
+
+    fn zip_content(file: &Path, zip_entry: Option<&str>) -> std::io::Result<String> {
+        let mut content = String::new();
+        input(file, zip_entry)?.read_to_string(&mut content)?;
+        Ok(content)
+    }
+
+    #[test]
+    fn test_tee() {
+        let (mut output, mut copy) = (Vec::new(), Vec::new());
+        let mut tee = Tee::new(&mut output, Some(&mut copy));
+        tee.write_all(b"a,b\n1,2\n").expect("should write");
+        tee.flush().expect("should flush");
+        assert_eq!(output, b"a,b\n1,2\n");
+        assert_eq!(copy, b"a,b\n1,2\n");
+
+        let mut output = Vec::new();
+        Tee::new(&mut output, None::<Vec<u8>>)
+            .write_all(b"a,b\n")
+            .expect("should write");
+        assert_eq!(output, b"a,b\n");
+    }
+
+    #[test]
+    fn test_zip_input() {
+        let archive = zip_archive(&[
+            ("README.txt", "notes"),
+            ("export/fees.csv", "Amount\n1,50\n"),
+        ]);
+        assert!(is_zip(archive.path()));
+        assert_eq!(
+            zip_content(archive.path(), None).expect("should read the csv entry"),
+            "Amount\n1,50\n"
+        );
+        assert_eq!(
+            zip_content(archive.path(), Some("README.txt")).expect("should read the named entry"),
+            "notes"
+        );
+
+        let error = zip_content(archive.path(), Some("missing.csv")).expect_err("should be error");
+        assert!(error.to_string().contains("no entry named 'missing.csv'"));
+    }
+
+    #[test]
+    fn test_zip_input_without_single_csv() {
+        let archive = zip_archive(&[("README.txt", "notes")]);
+        let error = zip_content(archive.path(), None).expect_err("should be error");
+        assert!(error.to_string().contains("has no CSV file"));
+
+        let archive = zip_archive(&[("february.csv", "Amount\n"), ("january.CSV", "Amount\n")]);
+        let error = zip_content(archive.path(), None).expect_err("should be error");
+        assert!(
+            error
+                .to_string()
+                .contains("--zip-entry: february.csv, january.CSV")
+        );
+        assert_eq!(
+            zip_content(archive.path(), Some("january.CSV")).expect("should read the named entry"),
+            "Amount\n"
+        );
+    }
+
+    #[test]
+    fn test_dump_headers() {
+        let mut temp_file = NamedTempFile::new().expect("should create temp file");
+        temp_file
+            .write_all("\u{feff}id;Amount;User ID\nfee_1;not an amount;acct_123\n".as_bytes())
+            .expect("should write to temp file");
+
+        let mut output = Vec::new();
+        dump_headers(
+            temp_file.path(),
+            Encoding::Utf8,
+            InputDelimiter::Byte(b';'),
+            None,
+            &mut output,
+        )
+        .expect("should dump headers");
+        assert_eq!(
+            String::from_utf8(output).expect("should be utf-8"),
+            "id\nAmount\nUser ID\n"
+        );
+    }
+
+    fn first_email(bytes: &[u8], encoding: Encoding) -> csv::Result<String> {
+        let mut temp_file = NamedTempFile::new().expect("should create temp file");
+        temp_file
+            .write_all(bytes)
+            .expect("should write to temp file");
+
+        let mut reader =
+            reader_with_encoding(temp_file.path(), encoding).expect("should open reader");
+        let record = reader.records().next().expect("should have record")?;
+        Ok(record[1].to_string())
+    }
+
+    #[test]
+    fn test_reader_windows_1252() {
+        let data = b"User ID,User Email\nacct_123,caf\xe9@example.com\n";
+        assert_eq!(
+            first_email(data, Encoding::Windows1252).expect("should decode"),
+            "caf\u{e9}@example.com"
+        );
+        assert!(first_email(data, Encoding::Utf8).is_err());
+    }
+
+    #[test]
+    fn test_reader_utf8() {
+        let data = "User ID,User Email\nacct_123,caf\u{e9}@example.com\n".as_bytes();
+        assert_eq!(
+            first_email(data, Encoding::Utf8).expect("should decode"),
+            "caf\u{e9}@example.com"
+        );
+    }
+
+    fn sniffed_records(data: &str, delimiter: InputDelimiter) -> Vec<Vec<String>> {
+        let mut temp_file = NamedTempFile::new().expect("should create temp file");
+        temp_file
+            .write_all(data.as_bytes())
+            .expect("should write to temp file");
+
+        let mut reader = reader_with(
+            temp_file.path(),
+            Encoding::Utf8,
+            delimiter,
+            &["Amount", "User ID", "User Email"],
+            true,
+            None,
+        )
+        .expect("should open reader");
+        let mut records = vec![
+            reader
+                .headers()
+                .expect("should have headers")
+                .iter()
+                .map(str::to_string)
+                .collect::<Vec<_>>(),
+        ];
+        records.extend(reader.records().map(|record| {
+            record
+                .expect("should be ok")
+                .iter()
+                .map(str::to_string)
+                .collect()
+        }));
+        records
+    }
+
+    #[test]
+    fn test_reader_with_delimiter() {
+        let records = sniffed_records(
+            "Amount;User ID;User Email\n0,25;acct_123;user@example.com\n",
+            InputDelimiter::Byte(b';'),
+        );
+        assert_eq!(
+            records,
+            [
+                ["Amount", "User ID", "User Email"],
+                ["0,25", "acct_123", "user@example.com"]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reader_sniffs_semicolon() {
+        let records = sniffed_records(
+            "id;Amount;User ID;User Email\nfee_1;0,25;acct_123;user@example.com\n",
+            InputDelimiter::Auto,
+        );
+        assert_eq!(records[0], ["id", "Amount", "User ID", "User Email"]);
+        assert_eq!(
+            records[1],
+            ["fee_1", "0,25", "acct_123", "user@example.com"]
+        );
+    }
+
+    #[test]
+    fn test_reader_sniffs_tab() {
+        let records = sniffed_records(
+            "Amount\tUser ID\tUser Email\n\"0,25\"\tacct_123\tuser@example.com\n",
+            InputDelimiter::Auto,
+        );
+        assert_eq!(records[1], ["0,25", "acct_123", "user@example.com"]);
+    }
+
+    #[test]
+    fn test_reader_sniffs_comma_by_default() {
+        let records = sniffed_records(
+            "\u{feff}Amount,User ID,User Email\n\"0,25\",acct_123,user@example.com\n",
+            InputDelimiter::Auto,
+        );
+        assert_eq!(records[1], ["0,25", "acct_123", "user@example.com"]);
+        assert_eq!(
+            sniffed_records("", InputDelimiter::Auto),
+            [Vec::<String>::new()]
+        );
+    }
+
+    #[test]
+    fn test_writer_gzip() {
+        let directory = tempfile::tempdir().expect("should create temp dir");
+        let path = directory.path().join("fees_out.csv.gz");
+
+        let mut output = writer(&path).expect("should open writer");
+        output
+            .write_all(b"account_id\nacct_123\n")
+            .expect("should write");
+        output.commit().expect("should commit");
+
+        let mut reader = reader(&path).expect("should open reader");
+        let record = reader
+            .records()
+            .next()
+            .expect("should have record")
+            .expect("should be ok");
+        assert_eq!(&record[0], "acct_123");
+    }
+
+    #[test]
+    fn test_writer_replaces_file_on_commit() {
+        let directory = tempfile::tempdir().expect("should create temp dir");
+        let path = directory.path().join("nested").join("fees_out.csv");
+
+        let mut output = writer(&path).expect("should open writer");
+        output.write_all(b"first").expect("should write");
+        output.commit().expect("should commit");
+        let mut output = writer(&path).expect("should open writer");
+        output.write_all(b"second").expect("should write");
+        output.commit().expect("should commit");
+
+        assert_eq!(
+            std::fs::read_to_string(&path).expect("should read output"),
+            "second"
+        );
+        let files = std::fs::read_dir(path.parent().expect("should have parent"))
+            .expect("should list directory")
+            .count();
+        assert_eq!(files, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_writer_creates_file_with_default_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let directory = tempfile::tempdir().expect("should create temp dir");
+        let path = directory.path().join("fees_out.csv");
+        let mut output = writer(&path).expect("should open writer");
+        output.write_all(b"fees").expect("should write");
+        output.commit().expect("should commit");
+
+        let created = directory.path().join("created.csv");
+        std::fs::File::create(&created).expect("should create file");
+        let mode = |path: &Path| {
+            std::fs::metadata(path)
+                .expect("should read metadata")
+                .permissions()
+                .mode()
+                & 0o777
+        };
+        assert_eq!(mode(&path), mode(&created));
+    }
+
+    #[test]
+    fn test_writer_keeps_file_without_commit() {
+        let directory = tempfile::tempdir().expect("should create temp dir");
+        let path = directory.path().join("fees_out.csv");
+        std::fs::write(&path, "complete").expect("should write output");
+
+        let mut output = writer(&path).expect("should open writer");
+        output.write_all(b"trunc").expect("should write");
+        drop(output);
+
+        assert_eq!(
+            std::fs::read_to_string(&path).expect("should read output"),
+            "complete"
+        );
+        let files = std::fs::read_dir(directory.path())
+            .expect("should list directory")
+            .count();
+        assert_eq!(files, 1);
+    }
+
+    #[test]
+    fn test_default_output_from_stdin() {
+        assert_eq!(
+            default_output(Path::new("-"), "csv"),
+            PathBuf::from("stdin_out.csv")
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use super::common;
+use crate::timestamp_serde::deserialize as timestamp_serde;
+use chrono::NaiveDateTime;
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+/// Domain of the customers without an email, or with an email lacking a domain.
+const NO_EMAIL: &str = "(no email)";
+
+#[derive(Debug, serde::Deserialize)]
+struct Entry {
+    #[serde(rename = "id")]
+    pub id: String,
+    #[serde(rename = "Email")]
+    pub email: String,
+    #[serde(rename = "Created (UTC)", deserialize_with = "timestamp_serde")]
+    pub created: Option<NaiveDateTime>,
+}
+
+impl Entry {
+    /// Lowercase domain of the email, the part after the last `@`,
+    /// [`NO_EMAIL`] when the email is missing or has no domain.
+    pub fn domain(&self) -> String {
+        self.email
+            .trim()
+            .rsplit_once('@')
+            .map(|(_, domain)| domain.to_lowercase())
+            .filter(|domain| !domain.is_empty())
+            .unwrap_or_else(|| NO_EMAIL.to_string())
+    }
+}
+
+#[derive(Debug)]
+struct DomainCustomers {
+    pub domain: String,
+    pub customer_count: u32,
+}
+
+impl DomainCustomers {
+    pub const fn csv_header() -> &'static str {
+        "domain,customer_count"
+    }
+
+    pub fn record(&self) -> [String; 2] {
+        [self.domain.clone(), self.customer_count.to_string()]
+    }
+}
+
+/// Parse a Stripe customers CSV file located at the given path, `-` reads from stdin.
+///
+/// Customers are counted by lowercase email domain, the customers without an email are counted
+/// under `(no email)` and a customer id repeated in the export is only counted once. Domains are sorted by descending customer count, ties by domain.
+/// The period covered by the customers is printed to stderr.
+///
+/// # Errors
+///
+/// File was not found or could not be read.
+/// Unable to parse the CSV file.
+/// Unable to create an Entry from a CSV line.
+/// Output file could not be created or written to, `-` writes to stdout.
+pub fn parse(file: PathBuf, output: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut csv_reader, output) = common::open(file, output, "customers")?;
+    let mut counts: HashMap<String, u32> = HashMap::new();
+    let mut seen_ids = HashSet::new();
+    let mut period = common::Period::default();
+    for result in common::entries(&mut csv_reader)? {
+        let entry: Entry = result?;
+        period.add(entry.created);
+
+        if !seen_ids.insert(entry.id.clone()) {
+            continue;
+        }
+
+        *counts.entry(entry.domain()).or_insert(0) += 1;
+    }
+    period.report("customers");
+
+    let mut statistics: Vec<DomainCustomers> = counts
+        .into_iter()
+        .map(|(domain, customer_count)| DomainCustomers {
+            domain,
+            customer_count,
+        })
+        .collect();
+    statistics.sort_unstable_by(|a, b| {
+        b.customer_count
+            .cmp(&a.customer_count)
+            .then_with(|| a.domain.cmp(&b.domain))
+    });
+
+    common::write_csv(
+        &output,
+        DomainCustomers::csv_header(),
+        statistics.iter().map(DomainCustomers::record),
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::common::parser_test;
+
+    const HEADER: &str = "id,Email,Name,Created (UTC)";
+
+    parser_test!(
+        entry_deserialize_valid,
+        format!("{HEADER}\ncus_123,User@Example.com,Jane,2025-12-31 14:30"),
+        id is "cus_123".to_string(),
+        email is "User@Example.com".to_string()
+    );
+
+    parser_test!(
+        entry_deserialize_empty_email,
+        format!("{HEADER}\ncus_123,,Jane,2025-12-31 14:30"),
+        email is String::new()
+    );
+
+    parser_test!(
+        test_entry_deserialize_invalid_created,
+        format!("{HEADER}\ncus_123,user@example.com,Jane,31/12/2025"),
+        is_error
+    );
+
+    #[test]
+    fn test_entry_domain() {
+        let domain = |email: &str| {
+            Entry {
+                id: "cus_123".to_string(),
+                email: email.to_string(),
+                created: None,
+            }
+            .domain()
+        };
+        assert_eq!(domain("User@Example.COM"), "example.com");
+        assert_eq!(domain(" user@example.com "), "example.com");
+        assert_eq!(domain("\"a@b\"@example.com"), "example.com");
+        assert_eq!(domain("user@"), NO_EMAIL);
+        assert_eq!(domain("user"), NO_EMAIL);
+        assert_eq!(domain(""), NO_EMAIL);
+    }
+
+    parser_test!(
+        test_parse_counts_by_domain,
+        file,
+        format!(
+            "{HEADER}\ncus_1,a@example.com,A,2025-12-31 14:30\ncus_2,b@other.com,B,2025-12-30 10:00\ncus_3,c@EXAMPLE.com,C,2025-12-30 11:00\ncus_4,,D,2025-12-29 09:00\ncus_5,e@acme.io,E,2025-12-29 10:00\ncus_6,f@example.com,F,2025-12-29 10:00\ncus_7,g@other.com,G,2025-12-29 10:00\ncus_1,a@example.com,A,2025-12-31 14:30"
+        ),
+        [
+            DomainCustomers::csv_header(),
+            "example.com,3",
+            "other.com,2",
+            "(no email),1",
+            "acme.io,1"
+        ]
+    );
+
+    parser_test!(
+        test_parse_empty_csv,
+        file,
+        HEADER,
+        [DomainCustomers::csv_header()]
+    );
+
+    parser_test!(
+        test_parse_missing_email_column,
+        file,
+        "id,Name,Created (UTC)\ncus_1,A,2025-12-31 14:30",
+        is_error
+    );
+
+    #[test]
+    fn test_parse_file_not_found() {
+        let non_existent_path = PathBuf::from("/tmp/non_existent_customers_12345.csv");
+        let result = parse(non_existent_path, None);
+
+        assert!(result.is_err());
+        let err_msg = result.expect_err("should be error").to_string();
+        assert!(err_msg.contains("does not exist"));
+    }
+}
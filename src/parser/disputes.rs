@@ -0,0 +1,196 @@
+use super::common;
+use crate::amount_serde::{self, deserialize as amount_serde};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+};
+
+#[derive(Debug, serde::Deserialize)]
+struct Entry {
+    #[serde(rename = "Dispute Amount", deserialize_with = "amount_serde")]
+    pub amount: i64,
+    #[serde(rename = "User ID")]
+    pub account_id: String,
+    #[serde(rename = "User Email")]
+    pub email: String,
+    #[serde(rename = "Reason")]
+    pub reason: String,
+    /// Currency as written in the export, `eur` when the export has no `Currency` column.
+    #[serde(rename = "Currency", default = "common::default_currency")]
+    pub currency: String,
+}
+
+#[derive(Debug)]
+struct AccountDisputes {
+    pub account_id: String,
+    pub email: String,
+    pub currency: String,
+    pub dispute_count: u32,
+    pub total_disputed: i64,
+    pub reasons: HashMap<String, u32>,
+}
+
+impl AccountDisputes {
+    pub fn new(account_id: &str, email: &str, currency: &str) -> Self {
+        Self {
+            account_id: account_id.to_string(),
+            email: email.to_string(),
+            currency: currency.to_string(),
+            dispute_count: 0,
+            total_disputed: 0,
+            reasons: HashMap::new(),
+        }
+    }
+
+    pub fn add_dispute(&mut self, amount: i64, reason: &str) {
+        self.dispute_count += 1;
+        self.total_disputed += amount;
+        *self.reasons.entry(reason.to_lowercase()).or_insert(0) += 1;
+    }
+
+    pub fn reason_count(&self, reason: &str) -> u32 {
+        self.reasons.get(reason).copied().unwrap_or(0)
+    }
+
+    pub const fn csv_header() -> &'static str {
+        "account_id,email,currency,dispute_count,total_disputed,fraudulent_count"
+    }
+
+    pub fn record(&self) -> [String; 6] {
+        [
+            self.account_id.clone(),
+            self.email.clone(),
+            self.currency.clone(),
+            self.dispute_count.to_string(),
+            amount_serde::format(
+                self.total_disputed,
+                amount_serde::currency_exponent(&self.currency),
+            ),
+            self.reason_count("fraudulent").to_string(),
+        ]
+    }
+}
+
+/// Parse a Stripe disputes CSV file located at the given path, `-` reads from stdin.
+///
+/// Disputes are grouped by account and currency, sorted by account id, with a breakdown of the
+/// dispute reasons.
+///
+/// # Errors
+///
+/// File was not found or could not be read.
+/// Unable to parse the CSV file.
+/// Unable to create an Entry from a CSV line.
+/// Output file could not be created or written to, `-` writes to stdout.
+pub fn parse(file: PathBuf, output: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut csv_reader, output) = common::open(file, output, "disputes")?;
+    let mut statistics: BTreeMap<(String, String), AccountDisputes> = BTreeMap::new();
+    for result in common::entries(&mut csv_reader)? {
+        let entry: Entry = result?;
+        statistics
+            .entry((entry.account_id.clone(), entry.currency.to_lowercase()))
+            .or_insert_with_key(|(account_id, currency)| {
+                AccountDisputes::new(account_id, &entry.email, currency)
+            })
+            .add_dispute(entry.amount, &entry.reason);
+    }
+
+    common::write_csv(
+        &output,
+        AccountDisputes::csv_header(),
+        statistics.values().map(AccountDisputes::record),
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::common::parser_test;
+
+    const HEADER: &str = "id,Dispute Amount,User ID,User Email,Reason,Status";
+
+    parser_test!(
+        entry_deserialize_valid,
+        format!("{HEADER}\ndp_123,\"25,00\",acct_123,user@example.com,fraudulent,lost"),
+        amount is 2500,
+        account_id is "acct_123".to_string(),
+        email is "user@example.com".to_string(),
+        reason is "fraudulent".to_string()
+    );
+
+    parser_test!(
+        test_entry_deserialize_missing_reason,
+        "Dispute Amount,User ID,User Email\n\"25,00\",acct_123,user@example.com",
+        is_error
+    );
+
+    parser_test!(
+        test_entry_deserialize_invalid_amount,
+        format!("{HEADER}\ndp_123,invalid,acct_123,user@example.com,fraudulent,lost"),
+        is_error
+    );
+
+    parser_test!(
+        test_parse_groups_by_account,
+        file,
+        format!(
+            "{HEADER}\ndp_3,\"5,00\",acct_456,test@example.com,Fraudulent,needs_response\ndp_1,\"25,00\",acct_123,user@example.com,fraudulent,lost\ndp_2,\"10,50\",acct_123,user@example.com,duplicate,won"
+        ),
+        [
+            AccountDisputes::csv_header(),
+            "acct_123,user@example.com,eur,2,35.50,1",
+            "acct_456,test@example.com,eur,1,5.00,1"
+        ]
+    );
+
+    parser_test!(
+        test_parse_groups_by_currency,
+        file,
+        "Dispute Amount,User ID,User Email,Reason,Currency\n\"25,00\",acct_123,user@example.com,fraudulent,eur\n2500,acct_123,user@example.com,duplicate,JPY",
+        [
+            AccountDisputes::csv_header(),
+            "acct_123,user@example.com,eur,1,25.00,1",
+            "acct_123,user@example.com,jpy,1,2500,0"
+        ]
+    );
+
+    parser_test!(
+        test_parse_empty_csv,
+        file,
+        HEADER,
+        [AccountDisputes::csv_header()]
+    );
+
+    parser_test!(
+        test_parse_invalid_csv_format,
+        file,
+        format!("{HEADER}\ndp_1,invalid,acct_123,user@example.com,fraudulent,lost"),
+        is_error
+    );
+
+    #[test]
+    fn test_reason_breakdown() {
+        let mut disputes = AccountDisputes::new("acct_123", "user@example.com", "eur");
+        disputes.add_dispute(100, "fraudulent");
+        disputes.add_dispute(200, "Duplicate");
+        disputes.add_dispute(300, "duplicate");
+
+        assert_eq!(disputes.dispute_count, 3);
+        assert_eq!(disputes.total_disputed, 600);
+        assert_eq!(disputes.reason_count("fraudulent"), 1);
+        assert_eq!(disputes.reason_count("duplicate"), 2);
+        assert_eq!(disputes.reason_count("product_not_received"), 0);
+    }
+
+    #[test]
+    fn test_parse_file_not_found() {
+        let non_existent_path = PathBuf::from("/tmp/non_existent_disputes_12345.csv");
+        let result = parse(non_existent_path, None);
+
+        assert!(result.is_err());
+        let err_msg = result.expect_err("should be error").to_string();
+        assert!(err_msg.contains("does not exist"));
+    }
+}
@@ -0,0 +1,140 @@
+use super::common;
+use crate::timestamp_serde::deserialize as timestamp_serde;
+use chrono::NaiveDateTime;
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+#[derive(Debug, serde::Deserialize)]
+struct Entry {
+    #[serde(rename = "id")]
+    pub id: String,
+    #[serde(rename = "Type")]
+    pub event_type: String,
+    #[serde(rename = "Created (UTC)", deserialize_with = "timestamp_serde")]
+    pub created: Option<NaiveDateTime>,
+}
+
+#[derive(Debug)]
+struct EventTypeCount {
+    pub event_type: String,
+    pub count: u32,
+}
+
+impl EventTypeCount {
+    pub const fn csv_header() -> &'static str {
+        "event_type,count"
+    }
+
+    pub fn record(&self) -> [String; 2] {
+        [self.event_type.clone(), self.count.to_string()]
+    }
+}
+
+/// Parse a Stripe events CSV file located at the given path, `-` reads from stdin.
+///
+/// Events are counted by type, e.g. `charge.succeeded`, and an event id repeated in the export is
+/// only counted once. Types are sorted by descending count, ties by type.
+/// The period covered by the events is printed to stderr.
+///
+/// # Errors
+///
+/// File was not found or could not be read.
+/// Unable to parse the CSV file.
+/// Unable to create an Entry from a CSV line.
+/// Output file could not be created or written to, `-` writes to stdout.
+pub fn parse(file: PathBuf, output: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut csv_reader, output) = common::open(file, output, "events")?;
+    let mut counts: HashMap<String, u32> = HashMap::new();
+    let mut seen_ids = HashSet::new();
+    let mut period = common::Period::default();
+    for result in common::entries(&mut csv_reader)? {
+        let entry: Entry = result?;
+        period.add(entry.created);
+
+        if !seen_ids.insert(entry.id) {
+            continue;
+        }
+
+        *counts.entry(entry.event_type).or_insert(0) += 1;
+    }
+    period.report("events");
+
+    let mut statistics: Vec<EventTypeCount> = counts
+        .into_iter()
+        .map(|(event_type, count)| EventTypeCount { event_type, count })
+        .collect();
+    statistics.sort_unstable_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then_with(|| a.event_type.cmp(&b.event_type))
+    });
+
+    common::write_csv(
+        &output,
+        EventTypeCount::csv_header(),
+        statistics.iter().map(EventTypeCount::record),
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::common::parser_test;
+
+    const HEADER: &str = "id,Type,Source,Created (UTC)";
+
+    parser_test!(
+        entry_deserialize_valid,
+        format!("{HEADER}\nevt_123,charge.succeeded,API,2025-12-31 14:30"),
+        id is "evt_123".to_string(),
+        event_type is "charge.succeeded".to_string()
+    );
+
+    parser_test!(
+        test_entry_deserialize_invalid_created,
+        format!("{HEADER}\nevt_123,charge.succeeded,API,31/12/2025"),
+        is_error
+    );
+
+    parser_test!(
+        test_parse_counts_by_type,
+        file,
+        format!(
+            "{HEADER}\nevt_1,charge.succeeded,API,2025-12-31 14:30\nevt_2,invoice.paid,Automatic,2025-12-30 10:00\nevt_3,charge.succeeded,API,2025-12-30 11:00\nevt_4,customer.created,Dashboard,2025-12-29 09:00\nevt_5,charge.succeeded,API,2025-12-29 10:00\nevt_6,invoice.paid,Automatic,2025-12-29 10:00\nevt_4,customer.created,Dashboard,2025-12-29 09:00"
+        ),
+        [
+            EventTypeCount::csv_header(),
+            "charge.succeeded,3",
+            "invoice.paid,2",
+            "customer.created,1"
+        ]
+    );
+
+    parser_test!(
+        test_parse_empty_csv,
+        file,
+        HEADER,
+        [EventTypeCount::csv_header()]
+    );
+
+    parser_test!(
+        test_parse_missing_type_column,
+        file,
+        "id,Source,Created (UTC)\nevt_1,API,2025-12-31 14:30",
+        is_error
+    );
+
+    #[test]
+    fn test_parse_file_not_found() {
+        let non_existent_path = PathBuf::from("/tmp/non_existent_events_12345.csv");
+        let result = parse(non_existent_path, None);
+
+        assert!(result.is_err());
+        let err_msg = result.expect_err("should be error").to_string();
+        assert!(err_msg.contains("does not exist"));
+    }
+}
@@ -1,241 +1,6753 @@
-use crate::amount_serde::deserialize as amount_serde;
-use std::{collections::HashMap, fs::File, io::Write, path::PathBuf};
+use super::common::{self, DEFAULT_CURRENCY};
+use crate::{
+    amount_serde::{self, EmptyAmount, Locale, Rounding, deserialize as amount_serde},
+    args::{
+        Args, Encoding, GroupBy, InputDelimiter, LineEnding, OutputFormat, QuoteStyle, SortKey,
+    },
+    exit, timestamp_serde,
+};
+use chrono::{NaiveDate, NaiveDateTime};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, HashSet},
+    hash::BuildHasher,
+    io::{Read, Write},
+    num::NonZeroUsize,
+    path::PathBuf,
+    sync::mpsc::Receiver,
+    time::Duration,
+};
 
+/// Errors returned by the fees analysis.
 #[derive(thiserror::Error, Debug)]
-enum Error {
+pub enum FeesError {
+    /// The input file does not exist.
     #[error("the file '{0}' does not exist")]
     FileNotFound(PathBuf),
-    #[error("unable to store entry for account")]
-    UnableToStoreEntry,
+    /// No input file was given.
+    #[error("no input files given")]
+    NoInputFiles,
+    /// The first line of the CSV, quoted in the error, is not a header as it has none of the
+    /// required columns, e.g. an export saved without its header row.
+    #[error(
+        "no header found, the first line is '{line}' but a header with the columns {expected} was expected"
+    )]
+    NoHeader { line: String, expected: String },
+    /// The header of the CSV lacks required columns, listed comma-separated.
+    #[error("the CSV is missing the required columns: {0}")]
+    MissingColumn(String),
+    /// A row has no creation time although the fees are grouped by month.
+    #[error("the row on line {0} has no creation time to group it by month")]
+    MissingTimestamp(u64),
+    /// The creation time of a row could not be parsed although it is needed to filter the rows
+    /// by date or to group them by month.
+    #[error("line {line}: {source}")]
+    InvalidTimestamp {
+        line: u64,
+        source: timestamp_serde::Error,
+    },
+    /// The first day of the date range comes after its last day.
+    #[error("--from {from} is after --to {to}, the date range is empty")]
+    InvalidDateRange { from: NaiveDate, to: NaiveDate },
+    /// A row of an input assumed to be sorted comes before the previous account id.
+    #[error(
+        "the input is not sorted by account id, {account_id} on line {line} comes after {previous}"
+    )]
+    UnsortedInput {
+        line: u64,
+        account_id: String,
+        previous: String,
+    },
+    /// The second option cannot be applied in the mode of the first one, e.g. an option which needs
+    /// every account to be aggregated before writing the output with `--assume-sorted`.
+    #[error("{0} cannot be combined with {1}")]
+    IncompatibleOption(&'static str, &'static str),
+    /// Every row of the input was malformed while skipping errors.
+    #[error("all {0} rows failed to parse")]
+    AllRowsFailed(usize),
+    /// The header of the CSV has columns which are not read, listed comma-separated,
+    /// with [`Options::fail_on_unknown_columns`].
+    #[error("the CSV has unexpected columns: {0}")]
+    UnknownColumns(String),
+    /// A column given with [`Options::input_has_no_header`] is not a 1-based position within the
+    /// fields of the first row.
+    #[error(
+        "the column '{column}' is not a column number between 1 and {width}, the columns are numbered with --input-has-no-header"
+    )]
+    InvalidColumnPosition { column: String, width: usize },
+    /// [`Options::tee`] was given with the xlsx or the sqlite format, which are binary and cannot
+    /// be printed.
+    #[error("--tee cannot print the {0} format to stdout")]
+    TeeBinary(&'static str),
+    /// Stdin was given as an input with [`watch`], it has no modification time to watch.
+    #[error("--watch cannot read from stdin, the input files must be paths")]
+    WatchStdin,
+    /// The input files could not be watched for changes, see [`watch`].
+    #[error("unable to watch the input files: {0}")]
+    Watch(#[from] notify::Error),
+    /// A selected output column is not one of [`AccountFees::column_names`].
+    #[error("unknown output column '{name}', the valid columns are: {valid}")]
+    UnknownOutputColumn { name: String, valid: String },
+    /// The input is a pipe or another special file and no output file was given.
+    #[error(transparent)]
+    OutputRequired(#[from] common::OutputRequired),
+    /// The CSV could not be read or a row could not be parsed.
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    /// The input or output could not be read from or written to.
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// The JSON output could not be serialized.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// The Excel output could not be created.
+    #[error(transparent)]
+    Xlsx(#[from] rust_xlsxwriter::XlsxError),
+    /// The sqlite output could not be created.
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+    /// The fees are in several currencies, which the sqlite output has no column for.
+    #[error(
+        "the sqlite output has no currency column but the fees are in {0}, split the export by currency"
+    )]
+    SqliteCurrencies(String),
+    /// The threads of the parallel aggregation could not be started.
+    #[error(transparent)]
+    ThreadPool(#[from] rayon::ThreadPoolBuildError),
 }
 
-#[derive(Debug, serde::Deserialize)]
-struct Entry {
+impl FeesError {
+    /// Process exit code of the error, so scripts can tell a missing input from malformed data,
+    /// [`exit::FAILURE`] for the other errors.
+    #[must_use]
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::FileNotFound(_) | Self::NoInputFiles => exit::NOT_FOUND,
+            Self::Csv(error) if error.is_io_error() => exit::IO,
+            Self::NoHeader { .. }
+            | Self::MissingColumn(_)
+            | Self::UnknownColumns(_)
+            | Self::MissingTimestamp(_)
+            | Self::InvalidTimestamp { .. }
+            | Self::AllRowsFailed(_)
+            | Self::UnsortedInput { .. }
+            | Self::InvalidColumnPosition { .. }
+            | Self::Csv(_) => exit::MALFORMED_DATA,
+            Self::Io(_) | Self::Json(_) | Self::Xlsx(_) | Self::Sqlite(_) | Self::Watch(_) => {
+                exit::IO
+            }
+            Self::ThreadPool(_)
+            | Self::IncompatibleOption(..)
+            | Self::InvalidDateRange { .. }
+            | Self::WatchStdin
+            | Self::TeeBinary(_)
+            | Self::SqliteCurrencies(_)
+            | Self::UnknownOutputColumn { .. }
+            | Self::OutputRequired(_) => exit::FAILURE,
+        }
+    }
+}
+
+/// Maximum number of skipped rows listed in the summary printed to stderr.
+const MAX_REPORTED_ERRORS: usize = 20;
+
+/// Columns which must be present in the header of a fees CSV, as named in the Stripe export.
+const REQUIRED_COLUMNS: [&str; 3] = ["Amount", "User ID", "User Email"];
+
+/// Columns of the Stripe export which are read when present.
+const OPTIONAL_COLUMNS: [&str; 4] = ["id", "Created (UTC)", "Currency", "Amount Refunded"];
+
+/// Names of the required columns in the header of the fees CSV,
+/// for exports whose columns were renamed or localized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Columns {
+    pub amount: String,
+    pub account_id: String,
+    pub email: String,
+}
+
+impl Default for Columns {
+    fn default() -> Self {
+        let [amount, account_id, email] = REQUIRED_COLUMNS.map(str::to_string);
+        Self {
+            amount,
+            account_id,
+            email,
+        }
+    }
+}
+
+impl Columns {
+    fn names(&self) -> [&str; 3] {
+        [&self.amount, &self.account_id, &self.email]
+    }
+
+    /// Renames the columns of the header to the names of the Stripe export,
+    /// which are the names the entries are deserialized from.
+    ///
+    /// Other columns already using one of these names are blanked so they are not read instead.
+    fn rename(&self, headers: &csv::StringRecord) -> csv::StringRecord {
+        headers
+            .iter()
+            .map(
+                |header| match self.names().into_iter().position(|name| name == header) {
+                    Some(index) => REQUIRED_COLUMNS[index],
+                    None if REQUIRED_COLUMNS.contains(&header) => "",
+                    None => header,
+                },
+            )
+            .collect()
+    }
+
+    /// Header of an input without a header row, whose columns are 1-based positions,
+    /// naming the columns at these positions like the Stripe export and leaving the others blank.
+    ///
+    /// An empty input has no fields and is valid, it simply has no rows.
+    fn positional_header(&self, width: usize) -> Result<csv::StringRecord, FeesError> {
+        if width == 0 {
+            return Ok(csv::StringRecord::new());
+        }
+        let mut headers = vec![""; width];
+        for (column, name) in self.names().into_iter().zip(REQUIRED_COLUMNS) {
+            let position = column
+                .parse::<usize>()
+                .ok()
+                .filter(|position| (1..=width).contains(position))
+                .ok_or_else(|| FeesError::InvalidColumnPosition {
+                    column: column.to_string(),
+                    width,
+                })?;
+            headers[position - 1] = name;
+        }
+        Ok(headers.into_iter().collect())
+    }
+}
+
+/// Checks that the header contains every column of `columns`.
+///
+/// An empty input has no header at all and is valid, it simply has no rows.
+/// A header without any of the columns is quoted with the `delimiter` of the input.
+fn validate_headers(
+    headers: &csv::StringRecord,
+    columns: &Columns,
+    delimiter: InputDelimiter,
+) -> Result<(), FeesError> {
+    if headers.is_empty() {
+        return Ok(());
+    }
+
+    let missing: Vec<&str> = columns
+        .names()
+        .into_iter()
+        .filter(|column| !headers.iter().any(|header| header == *column))
+        .collect();
+    if missing.is_empty() {
+        Ok(())
+    } else if missing.len() == REQUIRED_COLUMNS.len() {
+        Err(FeesError::NoHeader {
+            line: raw_record(headers, delimiter),
+            expected: missing.join(", "),
+        })
+    } else {
+        Err(FeesError::MissingColumn(missing.join(", ")))
+    }
+}
+
+/// Checks that the header has no columns besides `columns` and the [`OPTIONAL_COLUMNS`].
+fn validate_known_headers(headers: &csv::StringRecord, columns: &Columns) -> Result<(), FeesError> {
+    let unknown: Vec<&str> = headers
+        .iter()
+        .filter(|header| !columns.names().contains(header) && !OPTIONAL_COLUMNS.contains(header))
+        .collect();
+    if unknown.is_empty() {
+        Ok(())
+    } else {
+        Err(FeesError::UnknownColumns(unknown.join(", ")))
+    }
+}
+
+/// A row of a Stripe fees CSV, see [`records`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+pub struct Entry {
+    /// Id of the fee, `None` when the export has no `id` column or the id is empty.
+    #[serde(rename = "id", default)]
+    pub id: Option<String>,
+    /// Creation time of the fee in UTC as written in the export, `None` when the export has no
+    /// `Created (UTC)` column. It is only parsed when needed, see [`Self::created`].
+    #[serde(rename = "Created (UTC)", default)]
+    pub created: Option<String>,
+    /// Fee in the minor unit of the currency.
     #[serde(rename = "Amount", deserialize_with = "amount_serde")]
     pub amount: i64,
+    /// Refunded amount in the minor unit of the currency, `0` when the export has no
+    /// `Amount Refunded` column or the cell is empty.
+    #[serde(
+        rename = "Amount Refunded",
+        default,
+        deserialize_with = "common::amount_or_zero"
+    )]
+    pub amount_refunded: i64,
     #[serde(rename = "User ID")]
     pub account_id: String,
     #[serde(rename = "User Email")]
     pub email: String,
+    /// Currency as written in the export, `eur` when the export has no `Currency` column.
+    #[serde(rename = "Currency", default = "common::default_currency")]
+    pub currency: String,
 }
 
-#[derive(Debug)]
-struct AccountFees {
+impl Entry {
+    /// Creation time of the fee in UTC, `None` when the export has no `Created (UTC)` column or
+    /// the cell is empty.
+    ///
+    /// # Errors
+    ///
+    /// The timestamp is in none of the formats accepted by [`timestamp_serde::parse`].
+    pub fn created(&self) -> Result<Option<NaiveDateTime>, timestamp_serde::Error> {
+        self.created
+            .as_deref()
+            .filter(|created| !created.is_empty())
+            .map(timestamp_serde::parse)
+            .transpose()
+    }
+
+    /// Trims the whitespace around the account id and email, returns whether either had some.
+    pub fn trim_keys(&mut self) -> bool {
+        let trim = |field: &mut String| {
+            let trimmed = field.trim();
+            if trimmed.len() == field.len() {
+                return false;
+            }
+            *field = trimmed.to_string();
+            true
+        };
+        let account_id = trim(&mut self.account_id);
+        let email = trim(&mut self.email);
+        account_id || email
+    }
+}
+
+/// Whether two emails are the same, ignoring their case with `normalize_email`.
+fn is_same_email(a: &str, b: &str, normalize_email: bool) -> bool {
+    if normalize_email {
+        a.to_lowercase() == b.to_lowercase()
+    } else {
+        a == b
+    }
+}
+
+/// Key of the aggregated fees: the account id, or the month with [`GroupBy::Month`],
+/// or the email with [`GroupBy::Email`], lowercase with [`Options::normalize_email`],
+/// and the lowercase currency.
+pub type AccountKey = (String, String);
+
+/// Fees aggregated for a single account in a single currency.
+#[derive(Debug, PartialEq, Eq)]
+pub struct AccountFees {
     pub account_id: String,
     pub email: String,
+    pub currency: String,
+    /// Month of creation of the fees, e.g. `2025-12`, only set for the fees grouped by month,
+    /// see [`Self::for_month`].
+    pub month: Option<String>,
     pub transaction_count: u32,
+    /// Total fees in the minor unit of the currency.
     pub total_fees: i64,
+    /// Largest single fee in the minor unit of the currency, `0` without any fee.
+    pub max_fee: i64,
+    /// Smallest single fee in the minor unit of the currency, `0` without any fee.
+    pub min_fee: i64,
+    /// Total refunded amount of the fee rows in the minor unit of the currency.
+    pub total_refunded: i64,
 }
 
 impl AccountFees {
-    pub fn new(account_id: &str, email: &str) -> Self {
+    #[must_use]
+    pub fn new(account_id: &str, email: &str, currency: &str) -> Self {
         Self {
             account_id: account_id.to_string(),
             email: email.to_string(),
+            currency: currency.to_string(),
+            month: None,
             transaction_count: 0,
             total_fees: 0,
+            max_fee: 0,
+            min_fee: 0,
+            total_refunded: 0,
+        }
+    }
+
+    /// Fees of every account in a single month and currency, with [`GroupBy::Month`].
+    #[must_use]
+    pub fn for_month(month: &str, currency: &str) -> Self {
+        Self {
+            month: Some(month.to_string()),
+            ..Self::new("", "", currency)
         }
     }
 
+    /// Adds the refunded amount of a fee, see [`Self::net_fees`].
+    pub const fn add_refund(&mut self, refunded: i64) {
+        self.total_refunded += refunded;
+    }
+
+    /// Total fees minus the refunded amounts, what the account actually paid.
+    #[must_use]
+    pub const fn net_fees(&self) -> i64 {
+        self.total_fees - self.total_refunded
+    }
+
     pub const fn add_fee(&mut self, fee: i64) {
+        if self.transaction_count == 0 || fee > self.max_fee {
+            self.max_fee = fee;
+        }
+        if self.transaction_count == 0 || fee < self.min_fee {
+            self.min_fee = fee;
+        }
         self.transaction_count += 1;
         self.total_fees += fee;
     }
 
-    pub const fn csv_header() -> &'static str {
-        "account_id,email,transaction_count,total_fees_eur"
+    /// Adds the transaction count and fees of another aggregate of the same account and currency.
+    pub const fn merge(&mut self, other: &Self) {
+        if other.transaction_count > 0
+            && (self.transaction_count == 0 || other.max_fee > self.max_fee)
+        {
+            self.max_fee = other.max_fee;
+        }
+        if other.transaction_count > 0
+            && (self.transaction_count == 0 || other.min_fee < self.min_fee)
+        {
+            self.min_fee = other.min_fee;
+        }
+        self.transaction_count += other.transaction_count;
+        self.total_fees += other.total_fees;
+        self.total_refunded += other.total_refunded;
     }
-}
 
-impl std::fmt::Display for AccountFees {
-    #[allow(clippy::cast_precision_loss)]
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{},{},{},{:.2}",
-            self.account_id,
-            self.email,
-            self.transaction_count,
-            self.total_fees as f64 / 100.0
-        )
+    /// Sums the transaction counts and fees of the given accounts into a `TOTAL` row per currency,
+    /// ordered by currency.
+    #[must_use]
+    pub fn totals<'a>(statistics: impl IntoIterator<Item = &'a Self>) -> Vec<Self> {
+        let mut totals: BTreeMap<&str, Self> = BTreeMap::new();
+        for statistic in statistics {
+            let total = totals
+                .entry(&statistic.currency)
+                .or_insert_with(|| Self::new("TOTAL", "", &statistic.currency));
+            total.merge(statistic);
+        }
+        totals.into_values().collect()
     }
-}
 
-/// Parse a Stripe fees CSV file located at the given path.
-///
-/// # Errors
-///
-/// File was not found or could not be read.
-/// Unable to parse the CSV file.
-/// Unable to create an Entry from a CSV line.
-/// Output file could not be created or written to.
-pub fn parse(file: PathBuf, output: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
-    if !file.exists() {
-        return Err(Error::FileNotFound(file).into());
-    }
-
-    let output = output.unwrap_or_else(|| {
-        let mut output_path = file.clone();
-        output_path.set_file_name(format!(
-            "{}_out.csv",
-            file.file_stem()
-                .and_then(|s| s.to_str())
-                .unwrap_or("output")
-        ));
-        output_path
-    });
+    /// Values of the default output columns, see [`Self::csv_header`], with the amounts formatted
+    /// with `precision` decimals, or the decimals of the currency.
+    ///
+    /// The currency label only names the fees column, the amounts are in the account currency.
+    #[must_use]
+    pub fn to_record(&self, precision: Option<u32>, currency_label: &str) -> Vec<String> {
+        let columns = default_columns(currency_label, ExtraColumns::default(), GroupBy::Account);
+        default_layout(&columns, currency_label).record(self, precision)
+    }
 
-    println!("parsing fees from file: {}", file.display());
-    let mut csv_reader = csv::Reader::from_path(file)?;
-    let mut statistics: HashMap<String, AccountFees> = HashMap::new();
-    for result in csv_reader.deserialize() {
-        let entry: Entry = result?;
+    /// Total fees formatted in the major unit of the currency.
+    #[must_use]
+    pub fn formatted_total_fees(&self) -> String {
+        amount_serde::format(
+            self.total_fees,
+            amount_serde::currency_exponent(&self.currency),
+        )
+    }
 
-        if !statistics.contains_key(&entry.account_id) {
-            statistics.insert(
-                entry.account_id.clone(),
-                AccountFees::new(&entry.account_id, &entry.email),
-            );
+    /// Average fee per transaction in the minor unit of the currency, `0` without any transaction.
+    ///
+    /// The division is done on integers, halves of a minor unit are rounded away from zero.
+    #[must_use]
+    pub fn average_fee(&self) -> i64 {
+        if self.transaction_count == 0 {
+            return 0;
         }
 
-        statistics
-            .get_mut(&entry.account_id)
-            .ok_or(Error::UnableToStoreEntry)?
-            .add_fee(entry.amount);
+        let count = i64::from(self.transaction_count);
+        let (average, remainder) = (self.total_fees / count, self.total_fees % count);
+        if remainder.abs() * 2 >= count {
+            average + remainder.signum()
+        } else {
+            average
+        }
     }
 
-    if output.exists() {
-        std::fs::remove_file(&output)?;
+    /// Difference between the largest and the smallest single fee in the minor unit of the currency,
+    /// `0` without any fee, to spot the accounts whose fees vary a lot.
+    #[must_use]
+    pub const fn fee_range(&self) -> i64 {
+        self.max_fee - self.min_fee
     }
 
-    if let Some(parent) = output.parent() {
-        std::fs::create_dir_all(parent)?;
+    /// Largest single fee formatted in the major unit of the currency.
+    #[must_use]
+    pub fn formatted_max_fee(&self) -> String {
+        amount_serde::format(
+            self.max_fee,
+            amount_serde::currency_exponent(&self.currency),
+        )
     }
 
-    println!("writing results to file: {}", output.display());
-    let mut output_file = File::create(&output)?;
-    output_file.write_all(AccountFees::csv_header().as_bytes())?;
-    output_file.write_all(b"\n")?;
-    for statistic in statistics.values() {
-        output_file.write_all(statistic.to_string().as_bytes())?;
-        output_file.write_all(b"\n")?;
+    /// CSV header of the output, the fees column is labeled with the given currency.
+    #[must_use]
+    pub fn csv_header(currency_label: &str) -> String {
+        format!(
+            "account_id,email,currency,transaction_count,{}",
+            total_fees_column(currency_label)
+        )
     }
-
-    Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::io::Write;
-    use tempfile::NamedTempFile;
+/// Value of an output column of [`AccountFees`], see [`AccountFees::field`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field<'a> {
+    Text(&'a str),
+    Count(u32),
+    /// Amount in the minor unit of the currency of the account.
+    Amount(i64),
+}
 
-    macro_rules! fees_test {
-        ($name:ident, $data:expr, $($property:ident is $value:expr),+) => {
-            #[test]
-            fn $name() {
-                let mut reader = csv::Reader::from_reader($data.as_bytes());
-                let entry: Entry = reader.deserialize().next().expect("should have entry").expect("should be ok");
+impl AccountFees {
+    /// Names of every output column labeled with the given currency, the default columns in their
+    /// order followed by the optional ones of [`ExtraColumns`].
+    #[must_use]
+    pub fn column_names(currency_label: &str, group_by: GroupBy) -> Vec<String> {
+        let all = ExtraColumns {
+            max_fee: true,
+            average_fee: true,
+            net_fees: true,
+            fee_range: true,
+        };
+        default_columns(currency_label, all, group_by)
+    }
 
-                $(
-                    assert_eq!(entry.$property, $value);
-                )*
+    /// Value of the output column with the given name, `None` for a name which is not one of
+    /// [`Self::column_names`].
+    #[must_use]
+    pub fn field(&self, name: &str, currency_label: &str, group_by: GroupBy) -> Option<Field<'_>> {
+        let field = match (name, group_by) {
+            ("account_id", GroupBy::Account) => Field::Text(&self.account_id),
+            // The `TOTAL` and `OTHERS` rows have no month, they are labeled by their account id.
+            ("month", GroupBy::Month) => {
+                Field::Text(self.month.as_deref().unwrap_or(&self.account_id))
             }
-        };
-        ($name:ident, $data:expr, is_error) => {
-            #[test]
-            fn $name() {
-                #[allow(clippy::string_lit_as_bytes)]
-                let mut reader = csv::Reader::from_reader($data.as_bytes());
-                let entry: Result<Entry, _> = reader.deserialize().next().expect("should have entry");
-                assert!(entry.is_err());
+            // The `TOTAL` rows have no email, they are labeled by their account id instead.
+            ("email", GroupBy::Email) if self.email.is_empty() => Field::Text(&self.account_id),
+            ("email", GroupBy::Account | GroupBy::Email) => Field::Text(&self.email),
+            ("currency", _) => Field::Text(&self.currency),
+            ("transaction_count", _) => Field::Count(self.transaction_count),
+            _ => {
+                let amount = name
+                    .strip_suffix(currency_label)
+                    .and_then(|name| name.strip_suffix('_'))?;
+                Field::Amount(match amount {
+                    "total_fees" => self.total_fees,
+                    "max_fee" => self.max_fee,
+                    "average_fee" => self.average_fee(),
+                    "net_fees" => self.net_fees(),
+                    "min_fee" => self.min_fee,
+                    "fee_range" => self.fee_range(),
+                    _ => return None,
+                })
             }
         };
-        ($name:ident, file, $data:expr, is_error) => {
-            #[test]
-            fn $name() {
-                let mut temp_file = NamedTempFile::new().expect("should create temp file");
-                let out_temp_file = NamedTempFile::new().expect("should create out temp file");
-                writeln!(temp_file, "{}", $data).expect("should write to temp file");
+        Some(field)
+    }
+}
 
-                let result = parse(temp_file.path().to_path_buf(), Some(out_temp_file.path().to_path_buf()));
-                assert!(result.is_err());
+/// Names of the columns written without a selection: the identifying columns, the transaction
+/// count and the total fees labeled with the given currency, followed by the `extra` columns.
+/// Fees grouped by month have the `month` and `currency` columns instead of the account and email,
+/// fees grouped by email have the `email` and `currency` columns.
+fn default_columns(currency_label: &str, extra: ExtraColumns, group_by: GroupBy) -> Vec<String> {
+    let mut columns: Vec<String> = match group_by {
+        GroupBy::Account => AccountFees::csv_header(currency_label)
+            .split(',')
+            .map(str::to_string)
+            .collect(),
+        GroupBy::Month => vec![
+            "month".to_string(),
+            "currency".to_string(),
+            "transaction_count".to_string(),
+            total_fees_column(currency_label),
+        ],
+        GroupBy::Email => vec![
+            "email".to_string(),
+            "currency".to_string(),
+            "transaction_count".to_string(),
+            total_fees_column(currency_label),
+        ],
+    };
+    columns.extend(extra.names(currency_label));
+    columns
+}
+
+/// Name of the total fees column, e.g. `total_fees_eur`.
+fn total_fees_column(currency_label: &str) -> String {
+    format!("total_fees_{currency_label}")
+}
+
+/// Optional columns appended to the fees output.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct ExtraColumns {
+    /// The largest single fee of each account, see [`AccountFees::max_fee`].
+    pub max_fee: bool,
+    /// The average fee per transaction of each account, see [`AccountFees::average_fee`].
+    pub average_fee: bool,
+    /// The fees minus the refunded amounts of each account, see [`AccountFees::net_fees`].
+    pub net_fees: bool,
+    /// The smallest single fee of each account and the range up to its largest one,
+    /// see [`AccountFees::min_fee`] and [`AccountFees::fee_range`].
+    pub fee_range: bool,
+}
+
+impl ExtraColumns {
+    /// Names of the enabled columns labeled with the given currency, e.g. `max_fee_eur`.
+    fn names(self, currency_label: &str) -> Vec<String> {
+        [
+            ("max_fee", self.max_fee),
+            ("average_fee", self.average_fee),
+            ("net_fees", self.net_fees),
+            ("min_fee", self.fee_range),
+            ("fee_range", self.fee_range),
+        ]
+        .into_iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| format!("{name}_{currency_label}"))
+        .collect()
+    }
+}
+
+/// Columns of the fees output and how to look their values up, see [`AccountFees::field`].
+#[derive(Debug, Clone, Copy)]
+struct Layout<'a> {
+    columns: &'a [String],
+    currency_label: &'a str,
+    group_by: GroupBy,
+}
+
+impl<'a> Layout<'a> {
+    /// Names and values of the columns of an account, in the order of the columns.
+    fn fields(self, fees: &AccountFees) -> impl Iterator<Item = (&'a str, Field<'_>)> {
+        self.columns.iter().map(move |name| {
+            let field = fees.field(name, self.currency_label, self.group_by);
+            (name.as_str(), field.unwrap_or(Field::Text("")))
+        })
+    }
+
+    /// Values of the columns of an account, the amounts with `precision` decimals
+    /// or the decimals of the account currency.
+    fn record(self, fees: &AccountFees, precision: Option<u32>) -> Vec<String> {
+        let exponent = amount_serde::currency_exponent(&fees.currency);
+        self.fields(fees)
+            .map(|(_, field)| match field {
+                Field::Text(text) => text.to_string(),
+                Field::Count(count) => count.to_string(),
+                Field::Amount(amount) => precision.map_or_else(
+                    || amount_serde::format(amount, exponent),
+                    |precision| amount_serde::format_with_precision(amount, exponent, precision),
+                ),
+            })
+            .collect()
+    }
+}
+
+/// Serializes [`AccountFees`] with the columns of a layout, the amounts are strings in the major
+/// unit of the currency, e.g. `"12.30"`, so they stay exact.
+struct LabeledFees<'a> {
+    fees: &'a AccountFees,
+    layout: Layout<'a>,
+}
+
+impl serde::Serialize for LabeledFees<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeMap;
+
+        let exponent = amount_serde::currency_exponent(&self.fees.currency);
+        let mut state = serializer.serialize_map(None)?;
+        for (name, field) in self.layout.fields(self.fees) {
+            match field {
+                Field::Text(text) => state.serialize_entry(name, text)?,
+                Field::Count(count) => state.serialize_entry(name, &count)?,
+                Field::Amount(amount) => {
+                    state.serialize_entry(name, &amount_serde::format(amount, exponent))?;
+                }
             }
+        }
+        state.end()
+    }
+}
+
+impl serde::Serialize for AccountFees {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let columns = default_columns(DEFAULT_CURRENCY, ExtraColumns::default(), GroupBy::Account);
+        LabeledFees {
+            fees: self,
+            layout: Layout {
+                columns: &columns,
+                currency_label: DEFAULT_CURRENCY,
+                group_by: GroupBy::Account,
+            },
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Options of the fees analysis.
+#[derive(Debug, Clone)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Options {
+    pub format: OutputFormat,
+    /// Field delimiter of the CSV output, the TSV output always uses tabs.
+    pub delimiter: u8,
+    pub sort_by: SortKey,
+    pub descending: bool,
+    pub totals: bool,
+    /// Accounts whose total fees are below this amount, in cents, are dropped.
+    /// The threshold applies to the major unit of each currency, e.g. `1000` is 10 EUR or 10 JPY.
+    pub min_fees: Option<i64>,
+    /// Only the given number of accounts with the highest fees are kept,
+    /// or with the most transactions when sorting by count.
+    pub top: Option<usize>,
+    /// Only the given number of accounts with the highest fees are written individually,
+    /// the other accounts are summed into an `OTHERS` row per currency, see [`rollup`].
+    pub rollup_after: Option<usize>,
+    /// Only the rows of these account ids are aggregated, every account without an allowlist.
+    pub only_accounts: Option<HashSet<String>>,
+    /// The rows of these account ids are dropped, even if they are in [`Options::only_accounts`].
+    pub exclude_accounts: HashSet<String>,
+    /// Only the given number of data rows are read, over all the input files.
+    pub limit: Option<usize>,
+    /// Malformed rows are skipped and reported instead of aborting the analysis.
+    pub skip_errors: bool,
+    /// CSV report of the rows skipped with [`Options::skip_errors`], created even without any skipped row.
+    pub errors_file: Option<PathBuf>,
+    /// Columns other than the required and optional ones fail the analysis instead of being ignored.
+    pub fail_on_unknown_columns: bool,
+    /// Headline numbers are printed to stdout instead of writing the output file.
+    pub summary: bool,
+    /// The number of distinct emails is added to the [`Options::summary`], to compare it with
+    /// the number of accounts.
+    pub count_unique_emails: bool,
+    /// Rows whose email differs from the previous email of the account are reported,
+    /// and the most recent email of each account is used in the output.
+    pub warn_email_changes: bool,
+    /// Emails are compared case-insensitively, the casing of the first email seen is kept.
+    pub normalize_email: bool,
+    /// Currency used to label the total fees in the output, it does not convert the amounts.
+    pub currency_label: String,
+    /// Number of threads used to aggregate the rows, `1` aggregates them on the current thread.
+    pub jobs: NonZeroUsize,
+    /// How fees with more decimals than their currency are rounded to minor units.
+    pub rounding: Rounding,
+    /// Whether an empty amount is an error, zero or skips its row.
+    pub empty_amount: EmptyAmount,
+    /// Decimal and grouping separators of the amounts, guessed from each amount by default.
+    pub locale: Locale,
+    /// The number of rows and accounts aggregated so far is printed to stderr periodically.
+    pub progress: bool,
+    /// The output is also printed to stdout while being written to the output file.
+    pub tee: bool,
+    /// Names of the amount, account id and email columns in the header,
+    /// or their 1-based positions with [`Options::input_has_no_header`].
+    pub columns: Columns,
+    /// The input files have no header row, their first line is a data row
+    /// and the [`Options::columns`] are positions.
+    pub input_has_no_header: bool,
+    /// The CSV and TSV outputs have no header row, the JSON output is unaffected.
+    pub no_header: bool,
+    /// Optional columns appended to the output.
+    pub extra_columns: ExtraColumns,
+    /// Names of the output columns in their order, among [`AccountFees::column_names`].
+    /// Without a selection the default columns and the enabled [`Options::extra_columns`] are written.
+    pub selected_columns: Option<Vec<String>>,
+    /// Character encoding of the input files, decoded to UTF-8 before being parsed.
+    pub encoding: Encoding,
+    /// Entry read from the `.zip` input files, their only CSV file by default.
+    pub zip_entry: Option<String>,
+    /// Amounts are already in the minor unit of their currency, e.g. `150` is 1.50 EUR.
+    pub amounts_in_cents: bool,
+    /// Amounts are formatted back from minor units and a warning is printed when they differ
+    /// from the input, see [`amount_serde::round_trip`].
+    pub round_trip: bool,
+    /// Rows whose `id` was already seen, in any of the input files, are dropped.
+    pub dedupe: bool,
+    /// The whitespace around the account id and email of each row is trimmed before aggregating it.
+    pub trim_whitespace: bool,
+    /// Rows identical to the header are skipped, e.g. the headers of concatenated exports.
+    pub skip_repeated_headers: bool,
+    /// Only the rows created on or after this UTC date are aggregated.
+    pub from: Option<NaiveDate>,
+    /// Only the rows created on or before this UTC date are aggregated.
+    pub to: Option<NaiveDate>,
+    /// Key of the aggregation, the fees are grouped by account by default.
+    pub group_by: GroupBy,
+    /// Which fields of the CSV and TSV outputs are quoted.
+    pub quote_style: QuoteStyle,
+    /// Line ending of the CSV, TSV and JSON Lines outputs.
+    pub line_ending: LineEnding,
+    /// Number of decimals of the amounts in the CSV, TSV and Excel outputs,
+    /// `None` uses the decimals of each currency.
+    pub output_precision: Option<u32>,
+    /// The input is sorted by account id, each account is written as soon as the next one starts
+    /// instead of keeping every account in memory.
+    pub assume_sorted: bool,
+    /// Field delimiter of the input files, detected from their header with [`InputDelimiter::Auto`].
+    pub input_delimiter: InputDelimiter,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            format: OutputFormat::default(),
+            delimiter: b',',
+            sort_by: SortKey::default(),
+            descending: false,
+            totals: false,
+            min_fees: None,
+            top: None,
+            rollup_after: None,
+            only_accounts: None,
+            exclude_accounts: HashSet::new(),
+            limit: None,
+            skip_errors: false,
+            errors_file: None,
+            fail_on_unknown_columns: false,
+            summary: false,
+            count_unique_emails: false,
+            warn_email_changes: false,
+            normalize_email: false,
+            currency_label: DEFAULT_CURRENCY.to_string(),
+            jobs: NonZeroUsize::MIN,
+            rounding: Rounding::default(),
+            empty_amount: EmptyAmount::default(),
+            locale: Locale::default(),
+            progress: false,
+            tee: false,
+            columns: Columns::default(),
+            input_has_no_header: false,
+            no_header: false,
+            extra_columns: ExtraColumns::default(),
+            selected_columns: None,
+            encoding: Encoding::default(),
+            zip_entry: None,
+            amounts_in_cents: false,
+            round_trip: false,
+            dedupe: false,
+            trim_whitespace: false,
+            skip_repeated_headers: false,
+            from: None,
+            to: None,
+            group_by: GroupBy::default(),
+            quote_style: QuoteStyle::default(),
+            line_ending: LineEnding::default(),
+            output_precision: None,
+            assume_sorted: false,
+            input_delimiter: InputDelimiter::default(),
+        }
+    }
+}
+
+impl From<&Args> for Options {
+    fn from(arguments: &Args) -> Self {
+        Self {
+            format: arguments.format,
+            delimiter: arguments.delimiter,
+            sort_by: arguments.sort_by,
+            descending: arguments.desc,
+            totals: arguments.totals,
+            min_fees: arguments.min_fees,
+            top: arguments.top,
+            rollup_after: arguments.rollup_after,
+            only_accounts: (!arguments.only_accounts.is_empty())
+                .then(|| arguments.only_accounts.iter().cloned().collect()),
+            exclude_accounts: arguments.exclude_accounts.iter().cloned().collect(),
+            limit: arguments.limit,
+            skip_errors: arguments.skip_errors,
+            errors_file: arguments.errors_file.clone(),
+            fail_on_unknown_columns: arguments.fail_on_unknown_columns,
+            summary: arguments.summary,
+            count_unique_emails: arguments.count_unique_emails,
+            warn_email_changes: arguments.warn_email_changes,
+            normalize_email: arguments.normalize_email,
+            currency_label: arguments.currency.clone(),
+            jobs: arguments.jobs,
+            rounding: arguments.rounding.into(),
+            empty_amount: arguments.empty_amount.into(),
+            locale: arguments.locale.into(),
+            progress: arguments.progress,
+            tee: arguments.tee,
+            columns: Columns {
+                amount: arguments.col_amount.clone(),
+                account_id: arguments.col_account_id.clone(),
+                email: arguments.col_email.clone(),
+            },
+            input_has_no_header: arguments.input_has_no_header,
+            no_header: arguments.no_header,
+            extra_columns: ExtraColumns {
+                max_fee: arguments.max_fee,
+                average_fee: arguments.average_fee,
+                net_fees: arguments.net_fees,
+                fee_range: arguments.fee_range,
+            },
+            selected_columns: (!arguments.columns.is_empty()).then(|| arguments.columns.clone()),
+            encoding: arguments.encoding,
+            zip_entry: arguments.zip_entry.clone(),
+            amounts_in_cents: arguments.amounts_in_cents,
+            round_trip: arguments.round_trip,
+            dedupe: arguments.dedupe,
+            trim_whitespace: arguments.trim_whitespace,
+            skip_repeated_headers: arguments.skip_repeated_headers,
+            from: arguments.from.or_else(|| {
+                let today = chrono::Utc::now().date_naive();
+                arguments.since_days.map(|days| days_before(today, days))
+            }),
+            to: arguments.to,
+            group_by: arguments.group_by,
+            quote_style: arguments.quote_style,
+            line_ending: arguments.line_ending,
+            output_precision: arguments.output_precision,
+            assume_sorted: arguments.assume_sorted,
+            input_delimiter: arguments.input_delimiter,
+        }
+    }
+}
+
+impl Options {
+    /// Names of the columns written to the output, in their order.
+    ///
+    /// # Errors
+    ///
+    /// A selected column is not one of [`AccountFees::column_names`].
+    pub fn output_columns(&self) -> Result<Vec<String>, FeesError> {
+        let Some(selected) = &self.selected_columns else {
+            return Ok(default_columns(
+                &self.currency_label,
+                self.extra_columns,
+                self.group_by,
+            ));
         };
-        ($name:ident, file, $data:expr, is_ok) => {
-            #[test]
-            fn $name() {
-                let mut temp_file = NamedTempFile::new().expect("should create temp file");
-                let out_temp_file = NamedTempFile::new().expect("should create out temp file");
-                writeln!(temp_file, "{}", $data).expect("should write to temp file");
 
-                let result = parse(temp_file.path().to_path_buf(), Some(out_temp_file.path().to_path_buf()));
-                assert!(result.is_ok());
-            }
+        let valid = AccountFees::column_names(&self.currency_label, self.group_by);
+        if let Some(name) = selected.iter().find(|name| !valid.contains(name)) {
+            return Err(FeesError::UnknownOutputColumn {
+                name: name.clone(),
+                valid: valid.join(", "),
+            });
+        }
+        Ok(selected.clone())
+    }
+
+    /// Layout of the output with the given columns, see [`Self::output_columns`].
+    fn layout<'a>(&'a self, columns: &'a [String]) -> Layout<'a> {
+        Layout {
+            columns,
+            currency_label: &self.currency_label,
+            group_by: self.group_by,
+        }
+    }
+
+    /// Opens a CSV reader on an input file with the encoding and delimiter of the options.
+    fn reader(&self, file: &std::path::Path) -> Result<csv::Reader<Box<dyn Read>>, csv::Error> {
+        common::reader_with(
+            file,
+            self.encoding,
+            self.input_delimiter,
+            &self.columns.names(),
+            !self.input_has_no_header,
+            self.zip_entry.as_deref(),
+        )
+    }
+}
+
+/// Date the given number of days before `today`, the first day included by `--since-days`.
+fn days_before(today: NaiveDate, days: u32) -> NaiveDate {
+    today
+        .checked_sub_days(chrono::Days::new(u64::from(days)))
+        .unwrap_or(NaiveDate::MIN)
+}
+
+/// Headline numbers of a fees analysis, printed with `--summary`.
+///
+/// The amounts of different currencies are never added together, each currency has its own
+/// [`CurrencySummary`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct Summary {
+    /// Number of data rows read, including the skipped ones.
+    pub rows: usize,
+    /// Number of distinct account ids, an account with fees in several currencies counts once.
+    pub accounts: usize,
+    /// Number of distinct non-empty emails, only counted with [`Options::count_unique_emails`],
+    /// see [`unique_emails`].
+    pub emails: Option<usize>,
+    /// Headline numbers of each currency, sorted by currency.
+    /// Without any account, the labeled currency with no fees.
+    pub currencies: Vec<CurrencySummary>,
+}
+
+impl Summary {
+    /// Computes the summary of the aggregated fees, the fees of an account are summed per currency.
+    /// An input without any account is summarized in the labeled currency.
+    #[must_use]
+    pub fn new<'a>(
+        rows: usize,
+        statistics: impl IntoIterator<Item = &'a AccountFees>,
+        currency_label: &str,
+    ) -> Self {
+        let mut accounts: HashSet<&str> = HashSet::new();
+        let mut currencies: BTreeMap<&str, HashMap<&str, i64>> = BTreeMap::new();
+        for statistic in statistics {
+            accounts.insert(&statistic.account_id);
+            *currencies
+                .entry(&statistic.currency)
+                .or_default()
+                .entry(&statistic.account_id)
+                .or_insert(0) += statistic.total_fees;
+        }
+
+        let currencies = if currencies.is_empty() {
+            vec![CurrencySummary::new(
+                &currency_label.to_lowercase(),
+                &HashMap::new(),
+            )]
+        } else {
+            currencies
+                .into_iter()
+                .map(|(currency, accounts)| CurrencySummary::new(currency, &accounts))
+                .collect()
         };
+        Self {
+            rows,
+            accounts: accounts.len(),
+            emails: None,
+            currencies,
+        }
     }
 
-    fees_test!(
-        entry_deserialize_valid,
-        "Amount,User ID,User Email\n\"0,25\",acct_123,user@example.com",
-        amount is 25,
-        account_id is "acct_123".to_string(),
-        email is "user@example.com".to_string()
-    );
+    /// Whether the fees are in several currencies, the headline numbers are then given per
+    /// currency, each line prefixed with its currency.
+    #[must_use]
+    pub const fn is_mixed_currency(&self) -> bool {
+        self.currencies.len() > 1
+    }
 
-    fees_test!(
-        test_entry_deserialize_zero_amount,
-        "Amount,User ID,User Email\n\"0,00\",acct_000,zero@example.com",
-        amount is 0
-    );
+    /// Writes the summary as a single JSON object on one line, the amounts are numbers in the
+    /// major unit of their currency.
+    ///
+    /// The total fees field is labeled with the currency of a single-currency input, the numbers
+    /// of mixed-currency inputs are in a `currencies` object keyed by currency instead.
+    ///
+    /// # Errors
+    ///
+    /// The output could not be written to.
+    pub fn write_json<W: Write>(&self, output: &mut W) -> Result<(), FeesError> {
+        serde_json::to_writer(&mut *output, self)?;
+        output.write_all(b"\n")?;
 
-    fees_test!(
-        test_entry_deserialize_missing_field_email,
-        "Amount,User ID\n0,25,acct_123",
-        is_error
-    );
+        Ok(())
+    }
+}
 
-    fees_test!(
-        test_entry_deserialize_invalid_amount,
-        "Amount,User ID,User Email\ninvalid,acct_123,user@example.com",
-        is_error
-    );
+impl serde::Serialize for Summary {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeMap;
 
-    fees_test!(
-        test_parse_valid_csv,
-        file,
-        "Amount,User ID,User Email\n\"0,25\",acct_123,user@example.com\n\"1,50\",acct_456,test@example.com",
-        is_ok
-    );
+        let mut state = serializer.serialize_map(None)?;
+        state.serialize_entry("rows", &self.rows)?;
+        state.serialize_entry("accounts", &self.accounts)?;
+        if let Some(emails) = self.emails {
+            state.serialize_entry("emails", &emails)?;
+        }
+        match self.currencies.as_slice() {
+            [currency] => currency.serialize_entries(&mut state, false)?,
+            currencies => {
+                let currencies: BTreeMap<&str, CurrencyEntries> = currencies
+                    .iter()
+                    .map(|currency| (currency.currency.as_str(), CurrencyEntries(currency)))
+                    .collect();
+                state.serialize_entry("currencies", &currencies)?;
+            }
+        }
+        state.end()
+    }
+}
 
-    fees_test!(
-        test_parse_empty_csv,
-        file,
-        "Amount,User ID,User Email",
-        is_ok
-    );
+/// Headline numbers of the fees in a single currency, in its minor unit.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CurrencySummary {
+    /// Currency of the fees, lowercase.
+    pub currency: String,
+    /// Number of accounts with fees in the currency.
+    pub accounts: usize,
+    pub total_fees: i64,
+    /// Total fees divided by the number of accounts, rounded to the minor unit.
+    pub mean_fees: i64,
+    /// Highest total fees of a single account, with its account id.
+    pub max_fees: Option<(String, i64)>,
+    /// Lowest total fees of a single account, with its account id.
+    pub min_fees: Option<(String, i64)>,
+    /// Distribution of the total fees per account, `None` without any account.
+    pub percentiles: Option<Percentiles>,
+}
 
-    fees_test!(
-        test_parse_invalid_csv_format,
-        file,
-        "Amount,User ID,User Email\ninvalid,acct_123,user@example.com",
-        is_error
-    );
+impl CurrencySummary {
+    /// Computes the headline numbers of the total fees of each account in the currency.
+    fn new(currency: &str, accounts: &HashMap<&str, i64>) -> Self {
+        let total_fees: i64 = accounts.values().sum();
+        let mut totals: Vec<i64> = accounts.values().copied().collect();
+        totals.sort_unstable();
+        let max_fees = accounts
+            .iter()
+            .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+            .map(|(account_id, fees)| ((*account_id).to_string(), *fees));
+        let min_fees = accounts
+            .iter()
+            .min_by(|a, b| a.1.cmp(b.1).then_with(|| a.0.cmp(b.0)))
+            .map(|(account_id, fees)| ((*account_id).to_string(), *fees));
+        Self {
+            currency: currency.to_string(),
+            accounts: accounts.len(),
+            total_fees,
+            mean_fees: mean(total_fees, accounts.len()),
+            max_fees,
+            min_fees,
+            percentiles: Percentiles::new(&totals),
+        }
+    }
 
-    fees_test!(
-        test_parse_real_sample_format,
-        file,
-        "id,Created (UTC),Amount,Amount Refunded,Currency,User ID,User Email,Application ID,Transaction ID\nfee_1ABC123XYZ456789DEF,2025-12-31 14:30,\"0,25\",\"0,00\",eur,acct_1TEST001ABC123XYZ,user1@example.com,ca_ABC123XYZ456789DEF,ch_3ABC123XYZ456789DEF",
-        is_ok
-    );
+    /// Formats an amount of the currency in its major unit.
+    fn format(&self, amount: i64) -> String {
+        amount_serde::format(amount, amount_serde::currency_exponent(&self.currency))
+    }
 
-    #[test]
-    fn test_parse_file_not_found() {
-        let non_existent_path = PathBuf::from("/tmp/non_existent_file_12345.csv");
-        let result = parse(non_existent_path, None);
+    /// Adds the numbers of the currency to a JSON object, in the major unit of the currency.
+    /// The total fees field is labeled with the currency unless the object is already the one of
+    /// the currency, which then also has its number of accounts.
+    fn serialize_entries<M: serde::ser::SerializeMap>(
+        &self,
+        state: &mut M,
+        is_nested: bool,
+    ) -> Result<(), M::Error> {
+        let exponent = amount_serde::currency_exponent(&self.currency);
+        let amount = |fees: i64| amount_serde::to_major_units(fees, exponent);
+        if is_nested {
+            state.serialize_entry("accounts", &self.accounts)?;
+            state.serialize_entry("total_fees", &amount(self.total_fees))?;
+        } else {
+            state.serialize_entry(&total_fees_column(&self.currency), &amount(self.total_fees))?;
+        }
+        state.serialize_entry("mean", &amount(self.mean_fees))?;
+        let max = self.max_fees.as_ref().map(|(_, fees)| amount(*fees));
+        state.serialize_entry("max", &max)?;
+        let min = self.min_fees.as_ref().map(|(_, fees)| amount(*fees));
+        state.serialize_entry("min", &min)
+    }
+
+    /// Writes the lines of the summary of the currency, each prefixed with `prefix`.
+    fn write_lines(&self, f: &mut std::fmt::Formatter<'_>, prefix: &str) -> std::fmt::Result {
+        writeln!(f, "{prefix}total fees: {}", self.format(self.total_fees))?;
+        writeln!(
+            f,
+            "{prefix}mean fees per account: {}",
+            self.format(self.mean_fees)
+        )?;
+        match &self.max_fees {
+            Some((account_id, fees)) => writeln!(
+                f,
+                "{prefix}max account fees: {} ({account_id})",
+                self.format(*fees)
+            )?,
+            None => writeln!(f, "{prefix}max account fees: -")?,
+        }
+        match &self.percentiles {
+            Some(percentiles) => writeln!(
+                f,
+                "{prefix}account fees percentiles: p50 {}, p90 {}, p99 {}",
+                self.format(percentiles.p50),
+                self.format(percentiles.p90),
+                self.format(percentiles.p99)
+            ),
+            None => writeln!(f, "{prefix}account fees percentiles: -"),
+        }
+    }
+}
+
+/// Serializes the numbers of a [`CurrencySummary`] as the JSON object of its currency.
+struct CurrencyEntries<'a>(&'a CurrencySummary);
+
+impl serde::Serialize for CurrencyEntries<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeMap;
+
+        let mut state = serializer.serialize_map(None)?;
+        self.0.serialize_entries(&mut state, true)?;
+        state.end()
+    }
+}
+
+/// Number of distinct non-empty emails of the aggregated fees, the emails are compared as written.
+///
+/// Fewer emails than accounts means several accounts share an email, e.g. duplicated accounts.
+#[must_use]
+pub fn unique_emails<'a>(statistics: impl IntoIterator<Item = &'a AccountFees>) -> usize {
+    statistics
+        .into_iter()
+        .map(|statistic| statistic.email.as_str())
+        .filter(|email| !email.is_empty())
+        .collect::<HashSet<&str>>()
+        .len()
+}
+
+/// Percentiles of the total fees per account, in the minor unit of the currency.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Percentiles {
+    pub p50: i64,
+    pub p90: i64,
+    pub p99: i64,
+}
+
+impl Percentiles {
+    /// Computes the percentiles of sorted values, `None` without any value.
+    fn new(sorted: &[i64]) -> Option<Self> {
+        if sorted.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            p50: percentile(sorted, 50),
+            p90: percentile(sorted, 90),
+            p99: percentile(sorted, 99),
+        })
+    }
+}
+
+/// Percentile of non-empty sorted values, linearly interpolated between the two closest ranks
+/// like the default method of spreadsheets, then rounded half away from zero.
+///
+/// The percentile `p` is at the rank `p / 100 * (n - 1)`, e.g. the median of `[1, 2, 3, 4]` is `2.5`.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_precision_loss,
+    clippy::cast_sign_loss
+)]
+fn percentile(sorted: &[i64], p: u8) -> i64 {
+    let rank = f64::from(p) / 100.0 * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    let fraction = rank - rank.floor();
+    (sorted[upper] as f64 - sorted[lower] as f64)
+        .mul_add(fraction, sorted[lower] as f64)
+        .round() as i64
+}
+
+/// Mean of a total over a count, rounded half away from zero.
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+fn mean(total: i64, count: usize) -> i64 {
+    if count == 0 {
+        return 0;
+    }
+
+    (total as f64 / count as f64).round() as i64
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "rows: {}", self.rows)?;
+        writeln!(f, "accounts: {}", self.accounts)?;
+        if let Some(emails) = self.emails {
+            writeln!(f, "emails: {emails}")?;
+        }
+        if let [currency] = self.currencies.as_slice() {
+            return currency.write_lines(f, "");
+        }
+        for currency in &self.currencies {
+            let prefix = format!("{} ", currency.currency.to_uppercase());
+            writeln!(f, "{prefix}accounts: {}", currency.accounts)?;
+            currency.write_lines(f, &prefix)?;
+        }
+        Ok(())
+    }
+}
+
+/// A row which could not be parsed and was skipped.
+#[derive(Debug)]
+pub struct SkippedRow {
+    /// Line of the row in the CSV file, starting at 1 for the header.
+    pub line: u64,
+    /// Fields of the row written as a CSV line, empty when the row could not be read at all.
+    pub content: String,
+    pub error: String,
+}
+
+impl SkippedRow {
+    fn new(record: Option<&csv::StringRecord>, line: u64, error: String) -> Self {
+        Self {
+            line,
+            content: record
+                .map(|record| raw_record(record, InputDelimiter::default()))
+                .unwrap_or_default(),
+            error,
+        }
+    }
+}
+
+/// Writes the fields of a record as a CSV line without its terminator, quoted for the `delimiter`
+/// of the input, a comma for a sniffed delimiter.
+fn raw_record(record: &csv::StringRecord, delimiter: InputDelimiter) -> String {
+    let delimiter = match delimiter {
+        InputDelimiter::Byte(delimiter) => delimiter,
+        InputDelimiter::Auto => b',',
+    };
+    let mut csv_writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .terminator(csv::Terminator::Any(b'\n'))
+        .from_writer(Vec::new());
+    let content = csv_writer
+        .write_record(record)
+        .ok()
+        .and_then(|()| csv_writer.into_inner().ok())
+        .unwrap_or_default();
+    String::from_utf8_lossy(&content)
+        .trim_end_matches('\n')
+        .to_string()
+}
+
+/// CSV report of the rows skipped with [`Options::skip_errors`], see [`Options::errors_file`].
+struct ErrorsReport {
+    csv_writer: csv::Writer<common::Output>,
+    path: PathBuf,
+    rows: usize,
+}
+
+impl ErrorsReport {
+    /// Creates the report with its header row, `None` without [`Options::errors_file`].
+    fn create(options: &Options) -> Result<Option<Self>, FeesError> {
+        let Some(path) = &options.errors_file else {
+            return Ok(None);
+        };
+
+        let mut csv_writer = csv::Writer::from_writer(common::writer(path)?);
+        csv_writer.write_record(["file", "line", "content", "error"])?;
+        Ok(Some(Self {
+            csv_writer,
+            path: path.clone(),
+            rows: 0,
+        }))
+    }
+
+    /// Writes the rows skipped in the given input file.
+    fn write(&mut self, file: &std::path::Path, skipped: &[SkippedRow]) -> csv::Result<()> {
+        for row in skipped {
+            self.csv_writer.write_record([
+                file.display().to_string().as_str(),
+                &row.line.to_string(),
+                &row.content,
+                &row.error,
+            ])?;
+        }
+        self.rows += skipped.len();
+        Ok(())
+    }
+
+    /// Writes the report to its destination.
+    fn commit(self) -> Result<(), FeesError> {
+        eprintln!(
+            "writing {} skipped rows to file: {}",
+            self.rows,
+            self.path.display()
+        );
+        self.csv_writer
+            .into_inner()
+            .map_err(csv::IntoInnerError::into_error)?
+            .commit()?;
+        Ok(())
+    }
+}
+
+/// A row whose email differs from the email previously seen for the same account.
+#[derive(Debug, PartialEq, Eq)]
+pub struct EmailChange {
+    pub account_id: String,
+    pub currency: String,
+    pub previous: String,
+    pub email: String,
+    /// Line of the row in the CSV file, unknown when the change is found while merging aggregations.
+    pub line: Option<u64>,
+}
+
+/// Result of the aggregation of a Stripe fees CSV.
+#[derive(Debug, Default)]
+pub struct Aggregation {
+    pub statistics: HashMap<AccountKey, AccountFees>,
+    /// Number of data rows read, including the skipped ones.
+    pub rows: usize,
+    pub skipped: Vec<SkippedRow>,
+    /// Email changes of the accounts, only tracked with [`Options::warn_email_changes`].
+    pub email_changes: Vec<EmailChange>,
+    /// Number of amounts written without decimals in currencies which have decimals, e.g. `150`.
+    pub whole_amounts: usize,
+    /// Number of amounts written with decimals, e.g. `1,50`.
+    pub fractional_amounts: usize,
+    /// Number of rows dropped because their id was already seen, only with [`Options::dedupe`].
+    pub duplicates: usize,
+    /// Number of rows skipped because they were created outside of [`Options::from`] and [`Options::to`].
+    pub out_of_range: usize,
+    /// Number of rows skipped by [`Options::only_accounts`] and [`Options::exclude_accounts`].
+    pub filtered_accounts: usize,
+    /// Number of rows whose account id or email had whitespace around it,
+    /// only counted with [`Options::trim_whitespace`].
+    pub trimmed_keys: usize,
+    /// Number of rows skipped because they repeat the header, only with [`Options::skip_repeated_headers`].
+    pub repeated_headers: usize,
+    /// Number of rows skipped because their amount is empty, only with [`EmptyAmount::Skip`].
+    pub empty_amounts: usize,
+}
+
+/// Fee of a parsed row, with the key and email under which it is aggregated.
+struct Fee {
+    key: AccountKey,
+    email: String,
+    amount: i64,
+    refunded: i64,
+    line: Option<u64>,
+}
+
+/// Shared state needed to turn a CSV record into an [`Entry`].
+#[allow(clippy::struct_excessive_bools)]
+struct RowContext {
+    headers: csv::StringRecord,
+    currency_column: Option<usize>,
+    skip_errors: bool,
+    track_email_changes: bool,
+    normalize_email: bool,
+    rounding: Rounding,
+    empty_amount: EmptyAmount,
+    locale: Locale,
+    progress: bool,
+    amount_column: Option<usize>,
+    amounts_in_cents: bool,
+    round_trip: bool,
+    trim_whitespace: bool,
+    /// Column of the ids, only set when deduplicating rows.
+    id_column: Option<usize>,
+    /// Header as written in the input, only set when skipping the rows which repeat it.
+    repeated_header: Option<csv::StringRecord>,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    only_accounts: Option<HashSet<String>>,
+    exclude_accounts: HashSet<String>,
+    group_by: GroupBy,
+}
+
+impl RowContext {
+    /// Reads and validates the header of the CSV, the required columns are renamed to the names
+    /// of the Stripe export.
+    fn new<R: Read>(csv_reader: &mut csv::Reader<R>, options: &Options) -> Result<Self, FeesError> {
+        let (headers, repeated_header) = if options.input_has_no_header {
+            // The first row stays a data row, the rows are deserialized with the positional header.
+            let width = csv_reader.headers()?.len();
+            (options.columns.positional_header(width)?, None)
+        } else {
+            let headers = common::strip_bom(csv_reader.headers()?);
+            let repeated_header = options.skip_repeated_headers.then(|| headers.clone());
+            validate_headers(&headers, &options.columns, options.input_delimiter)?;
+            if options.fail_on_unknown_columns {
+                validate_known_headers(&headers, &options.columns)?;
+            }
+            let headers = options.columns.rename(&headers);
+            csv_reader.set_headers(headers.clone());
+            (headers, repeated_header)
+        };
+        let id_column = if options.dedupe {
+            let column = headers.iter().position(|header| header == "id");
+            Some(column.ok_or_else(|| FeesError::MissingColumn("id".to_string()))?)
+        } else {
+            None
+        };
+        if let (Some(from), Some(to)) = (options.from, options.to)
+            && from > to
+        {
+            return Err(FeesError::InvalidDateRange { from, to });
+        }
+        if (options.from.is_some() || options.to.is_some() || options.group_by == GroupBy::Month)
+            && !headers.iter().any(|header| header == "Created (UTC)")
+        {
+            return Err(FeesError::MissingColumn("Created (UTC)".to_string()));
+        }
+
+        Ok(Self {
+            id_column,
+            repeated_header,
+            from: options.from,
+            to: options.to,
+            only_accounts: options.only_accounts.clone(),
+            exclude_accounts: options.exclude_accounts.clone(),
+            group_by: options.group_by,
+            currency_column: headers.iter().position(|header| header == "Currency"),
+            amount_column: headers.iter().position(|header| header == "Amount"),
+            amounts_in_cents: options.amounts_in_cents,
+            round_trip: options.round_trip,
+            trim_whitespace: options.trim_whitespace,
+            headers,
+            skip_errors: options.skip_errors,
+            track_email_changes: options.warn_email_changes && options.group_by == GroupBy::Account,
+            normalize_email: options.normalize_email,
+            rounding: options.rounding,
+            empty_amount: options.empty_amount,
+            locale: options.locale,
+            progress: options.progress,
+        })
+    }
+
+    /// Number of decimals of the currency of a record.
+    fn exponent(&self, record: &csv::StringRecord) -> u32 {
+        self.currency_column
+            .and_then(|column| record.get(column))
+            .map_or(
+                amount_serde::DEFAULT_EXPONENT,
+                amount_serde::currency_exponent,
+            )
+    }
+
+    /// Number of decimals of the amount of a record, `0` when amounts are already in minor units.
+    fn amount_exponent(&self, record: &csv::StringRecord) -> u32 {
+        if self.amounts_in_cents {
+            0
+        } else {
+            self.exponent(record)
+        }
+    }
+
+    /// Prints a warning when the amount of a record does not read back as the same value once
+    /// formatted from minor units, see [`amount_serde::round_trip`].
+    fn check_round_trip(&self, record: &csv::StringRecord, amount: i64) {
+        let Some(input) = self
+            .amount_column
+            .and_then(|column| record.get(column))
+            .filter(|input| !amount_serde::is_blank(input))
+        else {
+            return;
+        };
+        let exponent = self.amount_exponent(record);
+        if let Some(formatted) = amount_serde::with_locale(self.locale, || {
+            amount_serde::round_trip(input, amount, exponent)
+        }) {
+            let line = record.position().map_or(0, csv::Position::line);
+            eprintln!("warning: line {line}: amount '{input}' reads back as '{formatted}'");
+        }
+    }
+
+    /// Whether the amount of a record has no decimals although its currency has some,
+    /// `None` for zero-decimal currencies.
+    fn is_whole_amount(&self, record: &csv::StringRecord) -> Option<bool> {
+        if self.exponent(record) == 0 {
+            return None;
+        }
+
+        let amount = self
+            .amount_column
+            .and_then(|column| record.get(column))
+            .filter(|amount| !amount_serde::is_blank(amount))?;
+        Some(!amount.contains([',', '.']))
+    }
+
+    /// Whether a record is skipped because its amount is empty or whitespace,
+    /// only with [`EmptyAmount::Skip`].
+    fn is_skipped_empty_amount(&self, record: &csv::StringRecord) -> bool {
+        self.empty_amount == EmptyAmount::Skip
+            && self
+                .amount_column
+                .and_then(|column| record.get(column))
+                .is_some_and(amount_serde::is_blank)
+    }
+
+    /// Whether the id of a record was already seen, recording it otherwise.
+    /// Rows without an id and malformed rows are never duplicates, the id of a malformed row is
+    /// not recorded so a valid row with the same id is still aggregated.
+    fn is_duplicate(
+        &self,
+        result: &csv::Result<csv::StringRecord>,
+        seen_ids: &mut HashSet<String>,
+    ) -> bool {
+        let Some((id, record)) = self
+            .id_column
+            .zip(result.as_ref().ok())
+            .and_then(|(column, record)| Some((record.get(column)?, record)))
+            .filter(|(id, _)| !id.is_empty())
+        else {
+            return false;
+        };
+        if seen_ids.contains(id) {
+            return true;
+        }
+        if self.is_skipped_empty_amount(record) || self.entry(record).is_ok() {
+            seen_ids.insert(id.to_string());
+        }
+        false
+    }
+
+    /// Whether a record is identical to the header of the input, ignoring a byte order mark,
+    /// only checked when skipping the repeated headers.
+    fn is_repeated_header(&self, result: &csv::Result<csv::StringRecord>) -> bool {
+        let (Some(header), Ok(record)) = (&self.repeated_header, result) else {
+            return false;
+        };
+
+        record.len() == header.len() && common::strip_bom(record) == *header
+    }
+
+    /// Whether the creation time of an entry is needed, to filter the entries by date or to group
+    /// them by month.
+    fn needs_created(&self) -> bool {
+        self.from.is_some() || self.to.is_some() || self.group_by == GroupBy::Month
+    }
+
+    /// Creation time of an entry on the given line when it is needed, see [`Self::needs_created`],
+    /// `None` otherwise so the timestamps of the exports in other formats are not parsed.
+    fn created(&self, entry: &Entry, line: u64) -> Result<Option<NaiveDateTime>, FeesError> {
+        if !self.needs_created() {
+            return Ok(None);
+        }
+
+        entry
+            .created()
+            .map_err(|source| FeesError::InvalidTimestamp { line, source })
+    }
+
+    /// Whether an entry created at `created` is within the inclusive date range, entries without
+    /// a creation time are outside of any range.
+    fn is_in_range(&self, created: Option<NaiveDateTime>) -> bool {
+        if self.from.is_none() && self.to.is_none() {
+            return true;
+        }
+
+        created.map(|created| created.date()).is_some_and(|date| {
+            self.from.is_none_or(|from| date >= from) && self.to.is_none_or(|to| date <= to)
+        })
+    }
+
+    /// Whether the account of an entry is in the allowlist, if any, and not in the denylist.
+    fn is_selected(&self, entry: &Entry) -> bool {
+        self.only_accounts
+            .as_ref()
+            .is_none_or(|accounts| accounts.contains(&entry.account_id))
+            && !self.exclude_accounts.contains(&entry.account_id)
+    }
+
+    /// Account id, or month with [`GroupBy::Month`], or email with [`GroupBy::Email`], and email
+    /// under which an entry is aggregated.
+    /// `None` when grouping by month an entry without a creation time.
+    fn group(&self, entry: Entry, created: Option<NaiveDateTime>) -> Option<(String, String)> {
+        match self.group_by {
+            GroupBy::Account => Some((entry.account_id, entry.email)),
+            GroupBy::Email if self.normalize_email => {
+                Some((entry.email.to_lowercase(), entry.email))
+            }
+            GroupBy::Email => Some((entry.email.clone(), entry.email)),
+            GroupBy::Month => {
+                created.map(|created| (created.format("%Y-%m").to_string(), String::new()))
+            }
+        }
+    }
+
+    /// Deserializes a record, its amount is converted to the minor unit of its currency
+    /// unless amounts are already in minor units.
+    fn entry(&self, record: &csv::StringRecord) -> csv::Result<Entry> {
+        let exponent = self.amount_exponent(record);
+        amount_serde::with_locale(self.locale, || {
+            amount_serde::with_empty_amount(self.empty_amount, || {
+                amount_serde::with_rounding(self.rounding, || {
+                    amount_serde::with_exponent(exponent, || {
+                        record.deserialize(Some(&self.headers))
+                    })
+                })
+            })
+        })
+    }
+}
+
+impl Aggregation {
+    /// Adds the fee of a CSV record, or records the row as skipped if it is malformed
+    /// and errors are skipped.
+    fn add_record(
+        &mut self,
+        result: csv::Result<csv::StringRecord>,
+        rows: &RowContext,
+    ) -> Result<(), FeesError> {
+        if let Some(fee) = self.parse_record(result, rows)? {
+            self.add_fee(fee, rows);
+        }
+        Ok(())
+    }
+
+    /// Parses the fee of a CSV record, `None` if the row is skipped because it is malformed
+    /// and errors are skipped, or because it is outside of the date range.
+    fn parse_record(
+        &mut self,
+        result: csv::Result<csv::StringRecord>,
+        rows: &RowContext,
+    ) -> Result<Option<Fee>, FeesError> {
+        self.rows += 1;
+        let line = result
+            .as_ref()
+            .ok()
+            .and_then(csv::StringRecord::position)
+            .map(csv::Position::line);
+        if result
+            .as_ref()
+            .is_ok_and(|record| rows.is_skipped_empty_amount(record))
+        {
+            self.empty_amounts += 1;
+            return Ok(None);
+        }
+        let (entry, record) = match result {
+            Ok(record) => (
+                rows.entry(&record)
+                    .map(|entry| (entry, rows.is_whole_amount(&record))),
+                Some(record),
+            ),
+            Err(error) => (Err(error), None),
+        };
+        let mut entry = match entry {
+            Ok((entry, whole)) => {
+                if let Some(record) = record.as_ref().filter(|_| rows.round_trip) {
+                    rows.check_round_trip(record, entry.amount);
+                }
+                match whole {
+                    Some(true) => self.whole_amounts += 1,
+                    Some(false) => self.fractional_amounts += 1,
+                    None => {}
+                }
+                entry
+            }
+            Err(error) if rows.skip_errors => {
+                self.skipped.push(SkippedRow::new(
+                    record.as_ref(),
+                    error.position().map_or(0, csv::Position::line),
+                    error.to_string(),
+                ));
+                return Ok(None);
+            }
+            Err(error) => return Err(error.into()),
+        };
+        if rows.trim_whitespace && entry.trim_keys() {
+            self.trimmed_keys += 1;
+        }
+        let created = match rows.created(&entry, line.unwrap_or(0)) {
+            Ok(created) => created,
+            Err(error) if rows.skip_errors => {
+                self.skipped.push(SkippedRow::new(
+                    record.as_ref(),
+                    line.unwrap_or(0),
+                    error.to_string(),
+                ));
+                return Ok(None);
+            }
+            Err(error) => return Err(error),
+        };
+        if !rows.is_in_range(created) {
+            self.out_of_range += 1;
+            return Ok(None);
+        }
+        if !rows.is_selected(&entry) {
+            self.filtered_accounts += 1;
+            return Ok(None);
+        }
+
+        let (amount, refunded) = (entry.amount, entry.amount_refunded);
+        let currency = entry.currency.to_lowercase();
+        let Some((account_id, email)) = rows.group(entry, created) else {
+            let line = line.unwrap_or(0);
+            if rows.skip_errors {
+                self.skipped.push(SkippedRow::new(
+                    record.as_ref(),
+                    line,
+                    FeesError::MissingTimestamp(line).to_string(),
+                ));
+                return Ok(None);
+            }
+            return Err(FeesError::MissingTimestamp(line));
+        };
+
+        Ok(Some(Fee {
+            key: (account_id, currency),
+            email,
+            amount,
+            refunded,
+            line,
+        }))
+    }
+
+    /// Adds a fee to the statistics of its account.
+    fn add_fee(&mut self, fee: Fee, rows: &RowContext) {
+        let Fee {
+            key,
+            email,
+            amount,
+            refunded,
+            line,
+        } = fee;
+        let statistic = self.statistics.entry(key.clone()).or_insert_with(|| {
+            if rows.group_by == GroupBy::Month {
+                AccountFees::for_month(&key.0, &key.1)
+            } else {
+                AccountFees::new(&key.0, &email, &key.1)
+            }
+        });
+        statistic.add_fee(amount);
+        statistic.add_refund(refunded);
+        if rows.track_email_changes
+            && !is_same_email(&statistic.email, &email, rows.normalize_email)
+        {
+            self.email_changes.push(EmailChange {
+                account_id: key.0,
+                currency: key.1,
+                previous: std::mem::replace(&mut statistic.email, email.clone()),
+                email,
+                line,
+            });
+        }
+    }
+
+    /// Merges an aggregation of later rows into this one, the first email seen for an account is kept.
+    pub fn merge(&mut self, other: Self) {
+        self.merge_with(other, false, false);
+    }
+
+    /// Merges an aggregation of later rows into this one, when tracking email changes the most
+    /// recent email of an account is kept and the changes between both aggregations are recorded,
+    /// ignoring the case of the emails with `normalize_email`.
+    fn merge_with(&mut self, other: Self, track_email_changes: bool, normalize_email: bool) {
+        self.rows += other.rows;
+        self.whole_amounts += other.whole_amounts;
+        self.fractional_amounts += other.fractional_amounts;
+        self.duplicates += other.duplicates;
+        self.out_of_range += other.out_of_range;
+        self.filtered_accounts += other.filtered_accounts;
+        self.trimmed_keys += other.trimmed_keys;
+        self.repeated_headers += other.repeated_headers;
+        self.empty_amounts += other.empty_amounts;
+        self.skipped.extend(other.skipped);
+        if !track_email_changes {
+            merge(&mut self.statistics, other.statistics);
+            return;
+        }
+
+        for (key, fees) in other.statistics {
+            let Some(statistic) = self.statistics.get_mut(&key) else {
+                self.statistics.insert(key, fees);
+                continue;
+            };
+
+            statistic.merge(&fees);
+            let first_email = other
+                .email_changes
+                .iter()
+                .find(|change| change.account_id == key.0 && change.currency == key.1)
+                .map_or(&fees.email, |change| &change.previous);
+            if !is_same_email(&statistic.email, first_email, normalize_email) {
+                self.email_changes.push(EmailChange {
+                    account_id: key.0,
+                    currency: key.1,
+                    previous: statistic.email.clone(),
+                    email: first_email.clone(),
+                    line: None,
+                });
+            }
+            if !is_same_email(&statistic.email, &fees.email, normalize_email) {
+                statistic.email = fees.email;
+            }
+        }
+        self.email_changes.extend(other.email_changes);
+    }
+
+    /// Whether the amounts look like they are already in cents: none of them has decimals
+    /// although their currency has some.
+    #[must_use]
+    pub const fn looks_like_cents(&self) -> bool {
+        self.whole_amounts > 0 && self.fractional_amounts == 0
+    }
+
+    /// Prints the email changes to stderr.
+    pub fn report_email_changes(&self) {
+        for change in &self.email_changes {
+            match change.line {
+                Some(line) => eprintln!(
+                    "warning: account {} changed email from {} to {} on line {line}",
+                    change.account_id, change.previous, change.email
+                ),
+                None => eprintln!(
+                    "warning: account {} changed email from {} to {}",
+                    change.account_id, change.previous, change.email
+                ),
+            }
+        }
+    }
+
+    /// Prints the number of rows and of the given accounts aggregated so far to stderr,
+    /// once every [`PROGRESS_INTERVAL`] rows since `previous_rows`.
+    fn report_progress(&self, previous_rows: usize, accounts: usize) {
+        if self.rows / PROGRESS_INTERVAL > previous_rows / PROGRESS_INTERVAL {
+            eprintln!(
+                "processed {} rows, {} accounts",
+                group_thousands(self.rows),
+                group_thousands(accounts)
+            );
+        }
+    }
+
+    /// Prints the skipped rows to stderr, listing at most [`MAX_REPORTED_ERRORS`] of them.
+    pub fn report_skipped(&self) {
+        if self.skipped.is_empty() {
+            return;
+        }
+
+        eprintln!("skipped {} malformed rows:", self.skipped.len());
+        for skipped in self.skipped.iter().take(MAX_REPORTED_ERRORS) {
+            eprintln!("  line {}: {}", skipped.line, skipped.error);
+        }
+        if self.skipped.len() > MAX_REPORTED_ERRORS {
+            eprintln!("  and {} more", self.skipped.len() - MAX_REPORTED_ERRORS);
+        }
+    }
+}
+
+impl std::fmt::Display for AccountFees {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_record(None, DEFAULT_CURRENCY).join(","))
+    }
+}
+
+/// Parse a Stripe fees CSV file located at the given path, `-` reads from stdin.
+///
+/// # Errors
+///
+/// File was not found or could not be read.
+/// Unable to parse the CSV file.
+/// Unable to create an Entry from a CSV line.
+/// Output file could not be created or written to, `-` writes to stdout.
+pub fn parse(file: PathBuf, output: Option<PathBuf>, options: &Options) -> Result<(), FeesError> {
+    parse_files(&[file], output, options)
+}
+
+/// Parse several Stripe fees CSV files into a single output, see [`parse`].
+///
+/// The fees of an account appearing in several files are summed,
+/// the default output is named after the first file.
+///
+/// # Errors
+///
+/// No file was given, or a file was not found or could not be read.
+/// Unable to parse a CSV file.
+/// Unable to create an Entry from a CSV line.
+/// Output file could not be created or written to, `-` writes to stdout.
+pub fn parse_files(
+    files: &[PathBuf],
+    output: Option<PathBuf>,
+    options: &Options,
+) -> Result<(), FeesError> {
+    let Some(first) = files.first() else {
+        return Err(FeesError::NoInputFiles);
+    };
+    if let Some(file) = files.iter().find(|file| common::is_missing(file)) {
+        return Err(FeesError::FileNotFound(file.clone()));
+    }
+
+    let output = common::output_path(first, output, options.format.extension())?;
+    let columns = options.output_columns()?;
+    match options.format {
+        OutputFormat::Xlsx | OutputFormat::Sqlite if options.tee => {
+            return Err(FeesError::TeeBinary(options.format.extension()));
+        }
+        OutputFormat::Sqlite => check_sqlite(options)?,
+        _ => {}
+    }
+    if options.assume_sorted {
+        return stream_sorted_files(files, &output, options, &columns);
+    }
+
+    let aggregation = aggregate_files(files, options)?;
+    warn_currency_mismatch(aggregation.statistics.values(), &options.currency_label);
+
+    let rows = aggregation.rows;
+    let mut statistics = aggregation.statistics;
+    if let Some(min_fees) = options.min_fees {
+        let accounts = statistics.len();
+        statistics.retain(|_, statistic| is_above_min_fees(statistic, min_fees));
+        report_min_fees(accounts - statistics.len(), min_fees);
+    }
+
+    let statistics: Vec<AccountFees> = match options.top {
+        Some(count) => {
+            let accounts = statistics.len();
+            let top = top(statistics.into_values(), count, options.sort_by);
+            eprintln!(
+                "omitted {} accounts outside of the top {count}",
+                accounts - top.len()
+            );
+            top
+        }
+        None => statistics.into_values().collect(),
+    };
+
+    if options.summary {
+        let mut summary = Summary::new(rows, &statistics, &options.currency_label);
+        if options.count_unique_emails {
+            summary.emails = Some(unique_emails(&statistics));
+        }
+        match options.format {
+            OutputFormat::Json | OutputFormat::Jsonl => {
+                summary.write_json(&mut std::io::stdout().lock())?;
+            }
+            OutputFormat::Csv | OutputFormat::Tsv | OutputFormat::Xlsx | OutputFormat::Sqlite => {
+                print!("{summary}");
+            }
+        }
+        return Ok(());
+    }
+
+    // The months are always in chronological order, --sort-by and --desc only order the accounts.
+    let (sort_by, descending) = match options.group_by {
+        GroupBy::Account | GroupBy::Email => (options.sort_by, options.descending),
+        GroupBy::Month => (SortKey::Account, false),
+    };
+    let mut statistics = match options.rollup_after {
+        Some(count) => rollup(statistics, count),
+        None => sort(statistics, sort_by, descending),
+    };
+    if options.totals {
+        let totals = AccountFees::totals(&statistics);
+        statistics.extend(totals);
+    }
+
+    eprintln!("writing results to file: {}", output.display());
+    let mut output_file = common::writer(&output)?;
+    write_statistics(
+        &statistics,
+        &mut common::Tee::new(&mut output_file, tee_stdout(&output, options)),
+        options,
+        &columns,
+    )?;
+    output_file.commit()?;
+
+    Ok(())
+}
+
+/// Time the input files must stop changing for before a run starts, see [`watch`].
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Runs [`parse_files`] and runs it again whenever an input file changes, until the process is
+/// interrupted, e.g. with Ctrl-C.
+///
+/// The directories of the files are watched with [`notify`], so a file replaced by a rename is
+/// still seen, and a run only starts once the files stopped changing for [`WATCH_DEBOUNCE`], so
+/// a file being written is not read half-way. Each run prints a timestamped line to stderr,
+/// a failed run is reported and the files are watched again.
+///
+/// # Errors
+///
+/// Stdin was given as an input file.
+/// The directories of the input files could not be watched.
+pub fn watch(
+    files: &[PathBuf],
+    output: Option<&PathBuf>,
+    options: &Options,
+) -> Result<(), FeesError> {
+    use notify::Watcher as _;
+
+    if files.iter().any(|file| common::is_stdio(file)) {
+        return Err(FeesError::WatchStdin);
+    }
+
+    let inputs = files
+        .iter()
+        .map(std::path::absolute)
+        .collect::<Result<HashSet<PathBuf>, _>>()?;
+    let (sender, events) = std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    let directories: HashSet<&std::path::Path> =
+        inputs.iter().filter_map(|file| file.parent()).collect();
+    for directory in directories {
+        watcher.watch(directory, notify::RecursiveMode::NonRecursive)?;
+    }
+
+    loop {
+        let result = parse_files(files, output.cloned(), options);
+        let now = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
+        match result {
+            Ok(()) => eprintln!("[{now}] regenerated the fees output"),
+            Err(error) => eprintln!("[{now}] error: {error}"),
+        }
+
+        eprintln!("watching the input files for changes, press Ctrl-C to stop");
+        if !wait_for_change(&events, &inputs, WATCH_DEBOUNCE) {
+            return Ok(());
+        }
+    }
+}
+
+/// Waits for an event changing one of the files, then until no event arrived for `debounce`.
+///
+/// Reads, such as the ones of the analysis itself, and the other files of the watched
+/// directories are ignored. A watcher error counts as a change, as events may have been lost.
+/// Returns false once the watcher has stopped without a change.
+fn wait_for_change(
+    events: &Receiver<notify::Result<notify::Event>>,
+    files: &HashSet<PathBuf>,
+    debounce: Duration,
+) -> bool {
+    use notify::event::{AccessKind, AccessMode, EventKind};
+
+    let is_change = |event: &notify::Result<notify::Event>| {
+        event.as_ref().map_or(true, |event| {
+            !matches!(event.kind, EventKind::Access(kind) if kind != AccessKind::Close(AccessMode::Write))
+                && event.paths.iter().any(|path| files.contains(path))
+        })
+    };
+    loop {
+        match events.recv() {
+            Ok(event) if is_change(&event) => break,
+            Ok(_) => {}
+            Err(_) => return false,
+        }
+    }
+    while events.recv_timeout(debounce).is_ok() {}
+    true
+}
+
+/// Change of the fees of an account in a currency between a previous and a current export,
+/// see [`diff_files`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct AccountDelta {
+    pub account_id: String,
+    pub email: String,
+    pub currency: String,
+    /// Total fees of the previous export in the minor unit of the currency, `0` when the account
+    /// is only in the current export.
+    pub fees_prev: i64,
+    /// Total fees of the current export in the minor unit of the currency, `0` when the account
+    /// is only in the previous export.
+    pub fees_now: i64,
+}
+
+impl AccountDelta {
+    /// Increase of the fees from the previous export, negative when they decreased.
+    #[must_use]
+    pub const fn delta(&self) -> i64 {
+        self.fees_now - self.fees_prev
+    }
+
+    #[must_use]
+    pub const fn csv_header() -> [&'static str; 6] {
+        [
+            "account_id",
+            "email",
+            "currency",
+            "fees_prev",
+            "fees_now",
+            "delta",
+        ]
+    }
+
+    /// Fields of the CSV row, the amounts formatted with `precision` decimals, or the decimals of
+    /// the currency.
+    fn record(&self, precision: Option<u32>) -> [String; 6] {
+        let exponent = amount_serde::currency_exponent(&self.currency);
+        let format = |amount: i64| {
+            precision.map_or_else(
+                || amount_serde::format(amount, exponent),
+                |precision| amount_serde::format_with_precision(amount, exponent, precision),
+            )
+        };
+        [
+            self.account_id.clone(),
+            self.email.clone(),
+            self.currency.clone(),
+            format(self.fees_prev),
+            format(self.fees_now),
+            format(self.delta()),
+        ]
+    }
+}
+
+/// Pairs the fees of each account and currency of two aggregations.
+///
+/// An account missing from one of them has `0` fees on that side. The email is the current one,
+/// or the previous one for the accounts which are only in the previous aggregation.
+/// Sorted by descending absolute delta in minor units, so the biggest movers come first, ties are
+/// broken by ascending account id then currency.
+#[must_use]
+pub fn deltas<S: BuildHasher, T: BuildHasher>(
+    previous: HashMap<AccountKey, AccountFees, S>,
+    current: HashMap<AccountKey, AccountFees, T>,
+) -> Vec<AccountDelta> {
+    let mut deltas: HashMap<AccountKey, AccountDelta> = HashMap::new();
+    let sides = previous
+        .into_values()
+        .map(|statistic| (false, statistic))
+        .chain(current.into_values().map(|statistic| (true, statistic)));
+    for (is_current, statistic) in sides {
+        let delta = deltas
+            .entry((statistic.account_id.clone(), statistic.currency.clone()))
+            .or_insert_with(|| AccountDelta {
+                account_id: statistic.account_id,
+                email: String::new(),
+                currency: statistic.currency,
+                fees_prev: 0,
+                fees_now: 0,
+            });
+        if is_current {
+            delta.fees_now += statistic.total_fees;
+            delta.email = statistic.email;
+        } else {
+            delta.fees_prev += statistic.total_fees;
+            if delta.email.is_empty() {
+                delta.email = statistic.email;
+            }
+        }
+    }
+
+    let mut deltas: Vec<AccountDelta> = deltas.into_values().collect();
+    deltas.sort_by(|a, b| {
+        b.delta()
+            .unsigned_abs()
+            .cmp(&a.delta().unsigned_abs())
+            .then_with(|| a.account_id.cmp(&b.account_id))
+            .then_with(|| a.currency.cmp(&b.currency))
+    });
+    deltas
+}
+
+/// Compares the fees of the files with the fees of a previous export, e.g. last month's,
+/// and writes the change of each account, see [`deltas`].
+///
+/// The output is CSV, or TSV with [`OutputFormat::Tsv`], named after the first file by default.
+/// The options which shape the rows of the fees output, such as `--top` or `--format json`,
+/// are rejected rather than ignored, see [`check_diff`].
+///
+/// # Errors
+///
+/// See [`parse_files`], the previous export was not found or could not be parsed,
+/// an option cannot be applied to the changes.
+pub fn diff_files(
+    files: &[PathBuf],
+    previous: &std::path::Path,
+    output: Option<PathBuf>,
+    options: &Options,
+) -> Result<(), FeesError> {
+    let Some(first) = files.first() else {
+        return Err(FeesError::NoInputFiles);
+    };
+    check_diff(options)?;
+    if let Some(file) = files
+        .iter()
+        .map(PathBuf::as_path)
+        .chain([previous])
+        .find(|file| common::is_missing(file))
+    {
+        return Err(FeesError::FileNotFound(file.to_path_buf()));
+    }
+
+    let extension = if options.format == OutputFormat::Tsv {
+        "tsv"
+    } else {
+        "csv"
+    };
+    let output = common::output_path(first, output, extension)?;
+    let previous = aggregate_files(&[previous.to_path_buf()], options)?;
+    let current = aggregate_files(files, options)?;
+    let deltas = deltas(previous.statistics, current.statistics);
+
+    eprintln!("writing results to file: {}", output.display());
+    let mut output_file = common::writer(&output)?;
+    let mut csv_writer = csv_writer_builder(options).from_writer(&mut output_file);
+    if !options.no_header {
+        csv_writer.write_record(AccountDelta::csv_header())?;
+    }
+    for delta in &deltas {
+        csv_writer.write_record(delta.record(options.output_precision))?;
+    }
+    csv_writer.flush()?;
+    drop(csv_writer);
+    output_file.commit()?;
+
+    Ok(())
+}
+
+/// Aggregates the files one after the other, reporting the rows of each file
+/// and the anomalies of the whole aggregation on stderr.
+fn aggregate_files(files: &[PathBuf], options: &Options) -> Result<Aggregation, FeesError> {
+    let mut aggregation = Aggregation::default();
+    let mut seen_ids = HashSet::new();
+    let mut remaining = options.limit.unwrap_or(usize::MAX);
+    let mut errors = ErrorsReport::create(options)?;
+    for (index, file) in files.iter().enumerate() {
+        eprintln!("parsing fees from file: {}", file.display());
+        let mut csv_reader = options.reader(file)?;
+        let file_aggregation =
+            aggregate_reader(&mut csv_reader, options, &mut seen_ids, remaining)?;
+        eprintln!(
+            "read {} rows from file: {}",
+            file_aggregation.rows,
+            file.display()
+        );
+        remaining -=
+            file_aggregation.rows + file_aggregation.duplicates + file_aggregation.repeated_headers;
+        file_aggregation.report_skipped();
+        if let Some(errors) = &mut errors {
+            errors.write(file, &file_aggregation.skipped)?;
+        }
+        aggregation.merge_with(
+            file_aggregation,
+            options.warn_email_changes,
+            options.normalize_email,
+        );
+        if is_limited(
+            remaining,
+            &mut csv_reader,
+            index + 1 == files.len(),
+            options,
+        ) {
+            break;
+        }
+    }
+    if let Some(errors) = errors {
+        errors.commit()?;
+    }
+    report_aggregation(&aggregation, options)?;
+
+    Ok(aggregation)
+}
+
+/// Whether the [`Options::limit`] is reached with `remaining` rows left to read.
+///
+/// The limit is printed on stderr so the partial output is not mistaken for a full run,
+/// only when the current reader has another record or another file is left to read.
+fn is_limited<R: Read>(
+    remaining: usize,
+    csv_reader: &mut csv::Reader<R>,
+    is_last_file: bool,
+    options: &Options,
+) -> bool {
+    let Some(limit) = options.limit.filter(|_| remaining == 0) else {
+        return false;
+    };
+
+    if !is_last_file || csv_reader.records().next().is_some() {
+        eprintln!("limited to {limit} rows");
+    }
+    true
+}
+
+/// Reports the anomalies of a whole aggregation on stderr.
+///
+/// # Errors
+///
+/// Every row was malformed while skipping errors.
+fn report_aggregation(aggregation: &Aggregation, options: &Options) -> Result<(), FeesError> {
+    aggregation.report_email_changes();
+    if options.dedupe {
+        eprintln!("dropped {} duplicate rows", aggregation.duplicates);
+    }
+    if options.skip_repeated_headers {
+        eprintln!(
+            "skipped {} repeated header rows",
+            aggregation.repeated_headers
+        );
+    }
+    if options.empty_amount == EmptyAmount::Skip {
+        eprintln!(
+            "skipped {} rows with an empty amount",
+            aggregation.empty_amounts
+        );
+    }
+    if options.from.is_some() || options.to.is_some() {
+        eprintln!(
+            "skipped {} rows outside of the date range",
+            aggregation.out_of_range
+        );
+    }
+    if options.only_accounts.is_some() || !options.exclude_accounts.is_empty() {
+        eprintln!(
+            "filtered {} rows by account id",
+            aggregation.filtered_accounts
+        );
+    }
+    if options.trim_whitespace {
+        eprintln!(
+            "trimmed the whitespace around the account id or email of {} rows",
+            aggregation.trimmed_keys
+        );
+    }
+    if !options.amounts_in_cents && aggregation.looks_like_cents() {
+        eprintln!(
+            "warning: none of the {} amounts has decimals, use --amounts-in-cents if they are already in cents",
+            aggregation.whole_amounts
+        );
+    }
+    if aggregation.rows > 0 && aggregation.skipped.len() == aggregation.rows {
+        return Err(FeesError::AllRowsFailed(aggregation.rows));
+    }
+
+    Ok(())
+}
+
+/// Whether the total fees of an account reach `min_fees`, in the major unit of its currency.
+///
+/// `min_fees` is in hundredths of the major unit, both amounts are compared as integers in the
+/// smallest of the two minor units.
+fn is_above_min_fees(statistic: &AccountFees, min_fees: i64) -> bool {
+    let exponent = amount_serde::currency_exponent(&statistic.currency);
+    let precision = exponent.max(amount_serde::DEFAULT_EXPONENT);
+    let scale = |amount: i64, exponent: u32| i128::from(amount) * 10_i128.pow(precision - exponent);
+    scale(statistic.total_fees, exponent) >= scale(min_fees, amount_serde::DEFAULT_EXPONENT)
+}
+
+/// Prints the number of accounts dropped by [`Options::min_fees`] to stderr.
+fn report_min_fees(filtered: usize, min_fees: i64) {
+    eprintln!(
+        "filtered {filtered} accounts below {}",
+        amount_serde::format(min_fees, amount_serde::DEFAULT_EXPONENT)
+    );
+}
+
+/// Checks that the options can be applied while streaming a sorted input,
+/// which excludes the options needing every account before writing the first one.
+fn check_streaming(options: &Options) -> Result<(), FeesError> {
+    let incompatible = [
+        (options.jobs.get() > 1, "--jobs"),
+        (options.top.is_some(), "--top"),
+        (options.rollup_after.is_some(), "--rollup-after"),
+        (options.summary, "--summary"),
+        (options.sort_by != SortKey::Account, "--sort-by"),
+        (options.descending, "--desc"),
+        (options.group_by == GroupBy::Month, "--group-by month"),
+        (options.group_by == GroupBy::Email, "--group-by email"),
+        (options.format == OutputFormat::Json, "--format json"),
+        (options.format == OutputFormat::Jsonl, "--format jsonl"),
+        (options.format == OutputFormat::Xlsx, "--format xlsx"),
+        (options.format == OutputFormat::Sqlite, "--format sqlite"),
+    ];
+    check_incompatible("--assume-sorted", incompatible)
+}
+
+/// Checks that the options can be applied to the change of each account written by [`diff_files`],
+/// which only has the account, email, currency and fees columns.
+fn check_diff(options: &Options) -> Result<(), FeesError> {
+    let extra = options.extra_columns;
+    let incompatible = [
+        (options.top.is_some(), "--top"),
+        (options.rollup_after.is_some(), "--rollup-after"),
+        (options.summary, "--summary"),
+        (options.sort_by != SortKey::Account, "--sort-by"),
+        (options.descending, "--desc"),
+        (options.totals, "--totals"),
+        (options.min_fees.is_some(), "--min-fees"),
+        (options.assume_sorted, "--assume-sorted"),
+        (options.tee, "--tee"),
+        (options.selected_columns.is_some(), "--columns"),
+        (extra.max_fee, "--max-fee"),
+        (extra.average_fee, "--average-fee"),
+        (extra.net_fees, "--net-fees"),
+        (extra.fee_range, "--fee-range"),
+        (options.group_by == GroupBy::Month, "--group-by month"),
+        (options.group_by == GroupBy::Email, "--group-by email"),
+        (options.format == OutputFormat::Json, "--format json"),
+        (options.format == OutputFormat::Jsonl, "--format jsonl"),
+        (options.format == OutputFormat::Xlsx, "--format xlsx"),
+        (options.format == OutputFormat::Sqlite, "--format sqlite"),
+    ];
+    check_incompatible("--diff", incompatible)
+}
+
+/// Checks that the options can be applied to the fixed columns of the [`write_sqlite_records`]
+/// table, grouped by account.
+fn check_sqlite(options: &Options) -> Result<(), FeesError> {
+    let extra = options.extra_columns;
+    let incompatible = [
+        (options.selected_columns.is_some(), "--columns"),
+        (extra.max_fee, "--max-fee"),
+        (extra.average_fee, "--average-fee"),
+        (extra.net_fees, "--net-fees"),
+        (extra.fee_range, "--fee-range"),
+        (options.output_precision.is_some(), "--output-precision"),
+        (options.group_by == GroupBy::Month, "--group-by month"),
+        (options.group_by == GroupBy::Email, "--group-by email"),
+    ];
+    check_incompatible("--format sqlite", incompatible)
+}
+
+/// Fails with the first enabled option of `incompatible`, which cannot be combined with `mode`.
+fn check_incompatible(
+    mode: &'static str,
+    incompatible: impl IntoIterator<Item = (bool, &'static str)>,
+) -> Result<(), FeesError> {
+    match incompatible.into_iter().find(|(enabled, _)| *enabled) {
+        Some((_, option)) => Err(FeesError::IncompatibleOption(mode, option)),
+        None => Ok(()),
+    }
+}
+
+/// Aggregates files sorted by account id in a single pass, each account is written to the output
+/// as soon as the account id changes so only the current account is kept in memory.
+///
+/// # Errors
+///
+/// See [`parse_files`], an account id coming before the previous one fails instead of
+/// producing wrong totals.
+fn stream_sorted_files(
+    files: &[PathBuf],
+    output: &std::path::Path,
+    options: &Options,
+    columns: &[String],
+) -> Result<(), FeesError> {
+    check_streaming(options)?;
+
+    eprintln!("writing results to file: {}", output.display());
+    let mut output_file = common::writer(output)?;
+    let mut stream = SortedStream {
+        csv_writer: csv_writer_builder(options).from_writer(common::Tee::new(
+            &mut output_file,
+            tee_stdout(output, options),
+        )),
+        options,
+        layout: options.layout(columns),
+        aggregation: Aggregation::default(),
+        accounts: 0,
+        filtered: 0,
+        totals: BTreeMap::new(),
+        currencies: BTreeMap::new(),
+    };
+    if !options.no_header {
+        stream.csv_writer.write_record(columns)?;
+    }
+
+    let mut seen_ids = HashSet::new();
+    let mut remaining = options.limit.unwrap_or(usize::MAX);
+    let mut errors = ErrorsReport::create(options)?;
+    for (index, file) in files.iter().enumerate() {
+        eprintln!("parsing fees from file: {}", file.display());
+        let (previous_rows, previous_skipped) =
+            (stream.aggregation.rows, stream.aggregation.skipped.len());
+        let mut csv_reader = options.reader(file)?;
+        let rows = RowContext::new(&mut csv_reader, options)?;
+        for result in csv_reader.records().take(remaining) {
+            remaining -= 1;
+            if rows.is_repeated_header(&result) {
+                stream.aggregation.repeated_headers += 1;
+                continue;
+            }
+            if rows.is_duplicate(&result, &mut seen_ids) {
+                stream.aggregation.duplicates += 1;
+                continue;
+            }
+            stream.add_record(result, &rows)?;
+            if rows.progress {
+                stream
+                    .aggregation
+                    .report_progress(stream.aggregation.rows - 1, stream.accounts);
+            }
+        }
+        eprintln!(
+            "read {} rows from file: {}",
+            stream.aggregation.rows - previous_rows,
+            file.display()
+        );
+        if let Some(errors) = &mut errors {
+            errors.write(file, &stream.aggregation.skipped[previous_skipped..])?;
+        }
+        if is_limited(
+            remaining,
+            &mut csv_reader,
+            index + 1 == files.len(),
+            options,
+        ) {
+            break;
+        }
+    }
+    stream.write_account()?;
+
+    stream.aggregation.report_skipped();
+    if let Some(errors) = errors {
+        errors.commit()?;
+    }
+    report_aggregation(&stream.aggregation, options)?;
+    warn_currency_mismatch(stream.currencies.values(), &options.currency_label);
+    if let Some(min_fees) = options.min_fees {
+        report_min_fees(stream.filtered, min_fees);
+    }
+    if options.totals {
+        for total in stream.totals.values() {
+            write_csv_record(
+                &mut stream.csv_writer,
+                total,
+                stream.layout,
+                options.output_precision,
+            )?;
+        }
+    }
+    stream.csv_writer.flush()?;
+    drop(stream);
+    output_file.commit()?;
+
+    Ok(())
+}
+
+/// State of the single-pass aggregation of a sorted input, see [`stream_sorted_files`].
+struct SortedStream<'a, W: Write> {
+    csv_writer: csv::Writer<W>,
+    options: &'a Options,
+    layout: Layout<'a>,
+    /// Counters of the rows read so far, the statistics only hold the current account.
+    aggregation: Aggregation,
+    /// Number of accounts written or filtered so far.
+    accounts: usize,
+    /// Number of accounts dropped by [`Options::min_fees`].
+    filtered: usize,
+    /// `TOTAL` rows of the written accounts, by currency.
+    totals: BTreeMap<String, AccountFees>,
+    /// Fees of every account by currency, to warn about currencies other than the label.
+    currencies: BTreeMap<String, AccountFees>,
+}
+
+impl<W: Write> SortedStream<'_, W> {
+    /// Adds a CSV record, the current account is written first when the record starts another one.
+    fn add_record(
+        &mut self,
+        result: csv::Result<csv::StringRecord>,
+        rows: &RowContext,
+    ) -> Result<(), FeesError> {
+        let Some(fee) = self.aggregation.parse_record(result, rows)? else {
+            return Ok(());
+        };
+
+        let previous = self.aggregation.statistics.keys().next().map(|key| &key.0);
+        match previous.map(|previous| previous.cmp(&fee.key.0)) {
+            Some(Ordering::Less) => self.write_account()?,
+            Some(Ordering::Greater) => {
+                return Err(FeesError::UnsortedInput {
+                    line: fee.line.unwrap_or(0),
+                    account_id: fee.key.0,
+                    previous: previous.cloned().unwrap_or_default(),
+                });
+            }
+            Some(Ordering::Equal) | None => {}
+        }
+        self.aggregation.add_fee(fee, rows);
+        Ok(())
+    }
+
+    /// Writes the fees of the current account, ordered by currency.
+    fn write_account(&mut self) -> Result<(), FeesError> {
+        if self.aggregation.statistics.is_empty() {
+            return Ok(());
+        }
+
+        let mut statistics: Vec<AccountFees> = self
+            .aggregation
+            .statistics
+            .drain()
+            .map(|(_, fees)| fees)
+            .collect();
+        statistics.sort_unstable_by(|a, b| a.currency.cmp(&b.currency));
+        self.accounts += 1;
+        for statistic in statistics {
+            self.currencies
+                .entry(statistic.currency.clone())
+                .or_insert_with(|| AccountFees::new("", "", &statistic.currency))
+                .merge(&statistic);
+            if self
+                .options
+                .min_fees
+                .is_some_and(|min_fees| !is_above_min_fees(&statistic, min_fees))
+            {
+                self.filtered += 1;
+                continue;
+            }
+
+            self.totals
+                .entry(statistic.currency.clone())
+                .or_insert_with(|| AccountFees::new("TOTAL", "", &statistic.currency))
+                .merge(&statistic);
+            write_csv_record(
+                &mut self.csv_writer,
+                &statistic,
+                self.layout,
+                self.options.output_precision,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Builder of the CSV and TSV outputs with the delimiter, quoting and line ending of the options.
+fn csv_writer_builder(options: &Options) -> csv::WriterBuilder {
+    let delimiter = if options.format == OutputFormat::Tsv {
+        b'\t'
+    } else {
+        options.delimiter
+    };
+    let mut builder = csv::WriterBuilder::new();
+    builder
+        .delimiter(delimiter)
+        .quote_style(options.quote_style.into())
+        .terminator(options.line_ending.into());
+    builder
+}
+
+/// Stdout when the output is also printed with [`Options::tee`], unless it is already written
+/// to stdout.
+fn tee_stdout(output: &std::path::Path, options: &Options) -> Option<std::io::Stdout> {
+    (options.tee && !common::is_stdio(output)).then(std::io::stdout)
+}
+
+/// Writes the aggregated fees to the output in the format, columns and CSV settings of the
+/// options, e.g. without a header row with [`Options::no_header`].
+///
+/// # Errors
+///
+/// A selected column is unknown, see [`Options::output_columns`].
+/// The output could not be written to.
+pub fn write<'a, W: Write>(
+    statistics: impl IntoIterator<Item = &'a AccountFees>,
+    output: &mut W,
+    options: &Options,
+) -> Result<(), FeesError> {
+    write_statistics(statistics, output, options, &options.output_columns()?)
+}
+
+/// Writes the statistics to the output in the format of the options, see [`write`].
+fn write_statistics<'a, W: Write>(
+    statistics: impl IntoIterator<Item = &'a AccountFees>,
+    output: &mut W,
+    options: &Options,
+    columns: &[String],
+) -> Result<(), FeesError> {
+    let layout = options.layout(columns);
+    match options.format {
+        OutputFormat::Csv | OutputFormat::Tsv => {
+            write_csv_records(
+                statistics,
+                output,
+                &csv_writer_builder(options),
+                layout,
+                !options.no_header,
+                options.output_precision,
+            )?;
+        }
+        OutputFormat::Json => write_json_records(statistics, output, layout)?,
+        OutputFormat::Jsonl => {
+            write_jsonl_records(statistics, output, layout, options.line_ending)?;
+        }
+        OutputFormat::Xlsx => {
+            write_xlsx_records(statistics, output, layout, options.output_precision)?;
+        }
+        OutputFormat::Sqlite => write_sqlite_records(statistics, output)?,
+    }
+
+    Ok(())
+}
+
+/// Currencies of the aggregated fees which differ from the currency label, ordered alphabetically.
+fn mismatched_currencies<'a>(
+    statistics: impl IntoIterator<Item = &'a AccountFees>,
+    currency_label: &str,
+) -> Vec<&'a str> {
+    let mut currencies: Vec<&str> = statistics
+        .into_iter()
+        .map(|statistic| statistic.currency.as_str())
+        .filter(|currency| !currency.eq_ignore_ascii_case(currency_label))
+        .collect();
+    currencies.sort_unstable();
+    currencies.dedup();
+    currencies
+}
+
+/// Warns on stderr when the data contains currencies other than the currency label.
+fn warn_currency_mismatch<'a>(
+    statistics: impl IntoIterator<Item = &'a AccountFees>,
+    currency_label: &str,
+) {
+    let currencies = mismatched_currencies(statistics, currency_label);
+    if !currencies.is_empty() {
+        eprintln!(
+            "warning: the data contains fees in {} but the output is labeled {currency_label}",
+            currencies.join(", ")
+        );
+    }
+}
+
+/// Aggregates the fees of every entry of a Stripe fees CSV by account id and currency.
+///
+/// Amounts are converted to the minor unit of the `Currency` column of each row,
+/// or to euro cents when the column is missing.
+///
+/// # Errors
+///
+/// Unable to parse the CSV file.
+/// The header lacks the `Amount`, `User ID` or `User Email` columns.
+/// Unable to create an Entry from a CSV line.
+pub fn aggregate<R: Read>(
+    csv_reader: csv::Reader<R>,
+) -> Result<HashMap<AccountKey, AccountFees>, FeesError> {
+    Ok(aggregate_with(csv_reader, &Options::default())?.statistics)
+}
+
+/// Aggregates the fees of a Stripe fees CSV like [`aggregate`], returning the accounts sorted
+/// in ascending order of the given key, see [`sort`] for the tie-breaking.
+///
+/// Unlike the map returned by [`aggregate`], the order is the same on every run.
+///
+/// # Errors
+///
+/// See [`aggregate`].
+pub fn aggregate_sorted<R: Read>(
+    csv_reader: csv::Reader<R>,
+    sort_by: SortKey,
+) -> Result<Vec<AccountFees>, FeesError> {
+    Ok(sort(aggregate(csv_reader)?.into_values(), sort_by, false))
+}
+
+/// Folds the fees aggregated from later rows into `statistics`, consuming them.
+///
+/// The transaction counts and fees of the accounts present in both are summed and the first email
+/// seen is kept, the other accounts are moved over without copying.
+pub fn merge<S: BuildHasher, T: BuildHasher>(
+    statistics: &mut HashMap<AccountKey, AccountFees, S>,
+    other: HashMap<AccountKey, AccountFees, T>,
+) {
+    for (key, fees) in other {
+        let Some(statistic) = statistics.get_mut(&key) else {
+            statistics.insert(key, fees);
+            continue;
+        };
+
+        statistic.merge(&fees);
+    }
+}
+
+/// Lazily reads the entries of a Stripe fees CSV, to build other aggregations than [`aggregate`].
+///
+/// Amounts are converted to the minor unit of the currency of each row.
+/// When the header is invalid, the iterator only yields that error.
+pub fn records<R: Read>(
+    csv_reader: csv::Reader<R>,
+) -> impl Iterator<Item = Result<Entry, FeesError>> {
+    records_with(csv_reader, &Options::default())
+}
+
+/// Lazily reads the entries of a Stripe fees CSV with the column names and rounding of `options`,
+/// see [`records`].
+pub fn records_with<R: Read>(
+    mut csv_reader: csv::Reader<R>,
+    options: &Options,
+) -> impl Iterator<Item = Result<Entry, FeesError>> + use<R> {
+    let (rows, error) = match RowContext::new(&mut csv_reader, options) {
+        Ok(rows) => (Some((rows, csv_reader)), None),
+        Err(error) => (None, Some(error)),
+    };
+    error
+        .map(Err)
+        .into_iter()
+        .chain(rows.into_iter().flat_map(|(rows, csv_reader)| {
+            csv_reader
+                .into_records()
+                .map(move |result| Ok(rows.entry(&result?)?))
+        }))
+}
+
+/// Aggregates the fees of every entry of a Stripe fees CSV by account id and currency,
+/// see [`aggregate`].
+///
+/// With [`Options::skip_errors`], malformed rows are collected in [`Aggregation::skipped`]
+/// instead of aborting the aggregation.
+///
+/// # Errors
+///
+/// Unable to parse the CSV file.
+/// The header lacks the `Amount`, `User ID` or `User Email` columns, or their [`Options::columns`] names.
+/// Unable to create an Entry from a CSV line, unless errors are skipped.
+/// The header lacks the `id` column while deduplicating rows,
+/// or the `Created (UTC)` column while filtering them by date or grouping them by month.
+pub fn aggregate_with<R: Read>(
+    mut csv_reader: csv::Reader<R>,
+    options: &Options,
+) -> Result<Aggregation, FeesError> {
+    aggregate_reader(
+        &mut csv_reader,
+        options,
+        &mut HashSet::new(),
+        options.limit.unwrap_or(usize::MAX),
+    )
+}
+
+/// Aggregates the first `limit` rows of a Stripe fees CSV, see [`aggregate_with`],
+/// the ids seen by previous readers are shared to deduplicate rows across files.
+fn aggregate_reader<R: Read>(
+    csv_reader: &mut csv::Reader<R>,
+    options: &Options,
+    seen_ids: &mut HashSet<String>,
+    limit: usize,
+) -> Result<Aggregation, FeesError> {
+    let rows = RowContext::new(csv_reader, options)?;
+    let (mut duplicates, mut repeated_headers) = (0, 0);
+    let records = csv_reader.records().take(limit).filter(|result| {
+        if rows.is_repeated_header(result) {
+            repeated_headers += 1;
+            return false;
+        }
+        let is_duplicate = rows.is_duplicate(result, seen_ids);
+        duplicates += usize::from(is_duplicate);
+        !is_duplicate
+    });
+
+    let mut aggregation = if options.jobs.get() > 1 {
+        aggregate_parallel(records, &rows, options.jobs)?
+    } else {
+        let mut aggregation = Aggregation::default();
+        for result in records {
+            aggregation.add_record(result, &rows)?;
+            if rows.progress {
+                aggregation.report_progress(aggregation.rows - 1, aggregation.statistics.len());
+            }
+        }
+        aggregation
+    };
+    aggregation.duplicates = duplicates;
+    aggregation.repeated_headers = repeated_headers;
+
+    Ok(aggregation)
+}
+
+/// Number of rows between two progress lines printed with [`Options::progress`].
+const PROGRESS_INTERVAL: usize = 100_000;
+
+/// Formats a count with commas between groups of thousands, e.g. "1,000,000".
+fn group_thousands(count: usize) -> String {
+    let digits = count.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// Number of records aggregated by a single job at once.
+const PARALLEL_CHUNK_SIZE: usize = 10_000;
+
+/// Aggregates the records in chunks spread over `jobs` threads, the partial aggregations are merged
+/// in the order of the records so the result is identical to the serial aggregation.
+fn aggregate_parallel(
+    mut records: impl Iterator<Item = csv::Result<csv::StringRecord>>,
+    rows: &RowContext,
+    jobs: NonZeroUsize,
+) -> Result<Aggregation, FeesError> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs.get())
+        .build()?;
+    let mut aggregation = Aggregation::default();
+    loop {
+        let chunks: Vec<Vec<_>> = (0..jobs.get())
+            .map(|_| {
+                records
+                    .by_ref()
+                    .take(PARALLEL_CHUNK_SIZE)
+                    .collect::<Vec<_>>()
+            })
+            .take_while(|chunk| !chunk.is_empty())
+            .collect();
+        if chunks.is_empty() {
+            return Ok(aggregation);
+        }
+
+        let partials: Vec<Result<Aggregation, FeesError>> = pool.install(|| {
+            chunks
+                .into_par_iter()
+                .map(|chunk| {
+                    let mut partial = Aggregation::default();
+                    for result in chunk {
+                        partial.add_record(result, rows)?;
+                    }
+                    Ok(partial)
+                })
+                .collect()
+        });
+        let previous_rows = aggregation.rows;
+        for partial in partials {
+            aggregation.merge_with(partial?, rows.track_email_changes, rows.normalize_email);
+        }
+        if rows.progress {
+            aggregation.report_progress(previous_rows, aggregation.statistics.len());
+        }
+    }
+}
+
+/// Sorts the aggregated fees by the given key, ties are broken by ascending account id and currency.
+///
+/// When sorting by email, accounts without an email are always last, in both directions.
+#[must_use]
+pub fn sort(
+    statistics: impl IntoIterator<Item = AccountFees>,
+    sort_by: SortKey,
+    descending: bool,
+) -> Vec<AccountFees> {
+    let mut statistics: Vec<AccountFees> = statistics.into_iter().collect();
+    statistics.sort_by(|a, b| {
+        let empty_emails_last = if sort_by == SortKey::Email {
+            a.email.is_empty().cmp(&b.email.is_empty())
+        } else {
+            Ordering::Equal
+        };
+        let ordering = compare(a, b, sort_by);
+        empty_emails_last
+            .then(if descending {
+                ordering.reverse()
+            } else {
+                ordering
+            })
+            .then_with(|| a.account_id.cmp(&b.account_id))
+            .then_with(|| a.currency.cmp(&b.currency))
+    });
+    statistics
+}
+
+/// Keeps the `count` accounts with the highest total fees, or with the most transactions
+/// when sorting by count, ordered from the highest to the lowest.
+#[must_use]
+pub fn top(
+    statistics: impl IntoIterator<Item = AccountFees>,
+    count: usize,
+    sort_by: SortKey,
+) -> Vec<AccountFees> {
+    let rank_by = match sort_by {
+        SortKey::Count => SortKey::Count,
+        SortKey::Account | SortKey::Email | SortKey::Fees => SortKey::Fees,
+    };
+    let mut statistics = sort(statistics, rank_by, true);
+    statistics.truncate(count);
+    statistics
+}
+
+/// Keeps the `count` accounts with the highest total fees, ordered from the highest to the lowest,
+/// followed by the other accounts summed into an `OTHERS` row per currency, ordered by currency.
+///
+/// Unlike [`top`] no fees are dropped, the `OTHERS` rows add up to the omitted accounts.
+#[must_use]
+pub fn rollup(statistics: impl IntoIterator<Item = AccountFees>, count: usize) -> Vec<AccountFees> {
+    let mut statistics = sort(statistics, SortKey::Fees, true);
+    let omitted = statistics.split_off(count.min(statistics.len()));
+    let mut others: BTreeMap<&str, AccountFees> = BTreeMap::new();
+    for statistic in &omitted {
+        others
+            .entry(&statistic.currency)
+            .or_insert_with(|| AccountFees::new("OTHERS", "", &statistic.currency))
+            .merge(statistic);
+    }
+    if !omitted.is_empty() {
+        eprintln!(
+            "rolled up {} accounts outside of the top {count} into OTHERS",
+            omitted.len()
+        );
+    }
+    statistics.extend(others.into_values());
+    statistics
+}
+
+fn compare(a: &AccountFees, b: &AccountFees, sort_by: SortKey) -> Ordering {
+    match sort_by {
+        SortKey::Account => a
+            .month
+            .cmp(&b.month)
+            .then_with(|| a.account_id.cmp(&b.account_id)),
+        SortKey::Email => a.email.cmp(&b.email),
+        SortKey::Fees => a.total_fees.cmp(&b.total_fees),
+        SortKey::Count => a.transaction_count.cmp(&b.transaction_count),
+    }
+}
+
+/// Layout of the default columns of the fees grouped by account, see [`AccountFees::to_record`].
+const fn default_layout<'a>(columns: &'a [String], currency_label: &'a str) -> Layout<'a> {
+    Layout {
+        columns,
+        currency_label,
+        group_by: GroupBy::Account,
+    }
+}
+
+/// Writes the rows of the aggregated fees with the columns of `layout` and the delimiter and
+/// quoting of `builder`, preceded by the header row of the column names if `header` is set.
+///
+/// The amounts have `precision` decimals, or the decimals of their currency.
+fn write_csv_records<'a, W: Write>(
+    statistics: impl IntoIterator<Item = &'a AccountFees>,
+    output: &mut W,
+    builder: &csv::WriterBuilder,
+    layout: Layout,
+    header: bool,
+    precision: Option<u32>,
+) -> std::io::Result<()> {
+    let mut csv_writer = builder.from_writer(output);
+    if header {
+        csv_writer.write_record(layout.columns)?;
+    }
+    for statistic in statistics {
+        write_csv_record(&mut csv_writer, statistic, layout, precision)?;
+    }
+    csv_writer.flush()?;
+
+    Ok(())
+}
+
+/// Writes the row of an account, see [`write_csv_records`].
+fn write_csv_record<W: Write>(
+    csv_writer: &mut csv::Writer<W>,
+    statistic: &AccountFees,
+    layout: Layout,
+    precision: Option<u32>,
+) -> csv::Result<()> {
+    csv_writer.write_record(layout.record(statistic, precision))
+}
+
+/// Writes the aggregated fees as a JSON array of objects with the fields of `layout`.
+fn write_json_records<'a, W: Write>(
+    statistics: impl IntoIterator<Item = &'a AccountFees>,
+    output: &mut W,
+    layout: Layout,
+) -> Result<(), FeesError> {
+    let statistics: Vec<LabeledFees> = statistics
+        .into_iter()
+        .map(|fees| LabeledFees { fees, layout })
+        .collect();
+    serde_json::to_writer_pretty(&mut *output, &statistics)?;
+    output.write_all(b"\n")?;
+
+    Ok(())
+}
+
+/// Writes the aggregated fees as JSON Lines with the fields of `layout`: one compact JSON object
+/// per line ended by `line_ending`, without an enclosing array, see [`write_json_records`].
+fn write_jsonl_records<'a, W: Write>(
+    statistics: impl IntoIterator<Item = &'a AccountFees>,
+    output: &mut W,
+    layout: Layout,
+    line_ending: LineEnding,
+) -> Result<(), FeesError> {
+    for fees in statistics {
+        serde_json::to_writer(&mut *output, &LabeledFees { fees, layout })?;
+        output.write_all(line_ending.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// Name of the table created by the sqlite output.
+const SQLITE_TABLE_NAME: &str = "account_fees";
+
+/// Writes the aggregated fees as a sqlite database with a [`SQLITE_TABLE_NAME`] table of the
+/// account id, email, transaction count and total fees of each account, inserted in a single
+/// transaction.
+///
+/// The total fees are integers in the minor unit of the currency, e.g. euro cents, so they stay
+/// exact. The table has no currency column, fees in several currencies are an error.
+fn write_sqlite_records<'a, W: Write>(
+    statistics: impl IntoIterator<Item = &'a AccountFees>,
+    output: &mut W,
+) -> Result<(), FeesError> {
+    let statistics: Vec<&AccountFees> = statistics.into_iter().collect();
+    let mut currencies: Vec<&str> = statistics
+        .iter()
+        .map(|statistic| statistic.currency.as_str())
+        .collect();
+    currencies.sort_unstable();
+    currencies.dedup();
+    if currencies.len() > 1 {
+        return Err(FeesError::SqliteCurrencies(currencies.join(", ")));
+    }
+
+    let mut connection = rusqlite::Connection::open_in_memory()?;
+    let transaction = connection.transaction()?;
+    transaction.execute(
+        &format!(
+            "CREATE TABLE \"{SQLITE_TABLE_NAME}\" (\"account_id\" TEXT, \"email\" TEXT, \"transaction_count\" INTEGER, \"total_fees\" INTEGER)"
+        ),
+        (),
+    )?;
+    {
+        let mut insert = transaction.prepare(&format!(
+            "INSERT INTO \"{SQLITE_TABLE_NAME}\" (\"account_id\", \"email\", \"transaction_count\", \"total_fees\") VALUES (?1, ?2, ?3, ?4)"
+        ))?;
+        for statistic in statistics {
+            insert.execute((
+                &statistic.account_id,
+                &statistic.email,
+                statistic.transaction_count,
+                statistic.total_fees,
+            ))?;
+        }
+    }
+    transaction.commit()?;
+    output.write_all(&connection.serialize(rusqlite::MAIN_DB)?)?;
+
+    Ok(())
+}
+
+/// Name of the sheet of the Excel output.
+const XLSX_SHEET_NAME: &str = "Fees";
+
+/// Writes the aggregated fees as an Excel workbook with a bold header row,
+/// the fees are numbers formatted in the currency of each row, see [`write_csv_records`].
+fn write_xlsx_records<'a, W: Write>(
+    statistics: impl IntoIterator<Item = &'a AccountFees>,
+    output: &mut W,
+    layout: Layout,
+    precision: Option<u32>,
+) -> Result<(), FeesError> {
+    let mut workbook = rust_xlsxwriter::Workbook::new();
+    let worksheet = workbook.add_worksheet();
+    worksheet.set_name(XLSX_SHEET_NAME)?;
+
+    let bold = rust_xlsxwriter::Format::new().set_bold();
+    for (column, name) in (0..).zip(layout.columns) {
+        worksheet.write_string_with_format(0, column, name, &bold)?;
+    }
+
+    for (row, statistic) in (1..).zip(statistics) {
+        let exponent = amount_serde::currency_exponent(&statistic.currency);
+        let currency = rust_xlsxwriter::Format::new().set_num_format(currency_format(
+            precision.unwrap_or(exponent),
+            &statistic.currency,
+        ));
+        for (column, (_, field)) in (0..).zip(layout.fields(statistic)) {
+            match field {
+                Field::Text(text) => worksheet.write_string(row, column, text)?,
+                Field::Count(count) => worksheet.write_number(row, column, count)?,
+                Field::Amount(amount) => worksheet.write_number_with_format(
+                    row,
+                    column,
+                    amount_serde::to_major_units(amount, exponent),
+                    &currency,
+                )?,
+            };
+        }
+    }
+    worksheet.autofit();
+
+    output.write_all(&workbook.save_to_buffer()?)?;
+    Ok(())
+}
+
+/// Excel number format of amounts with `exponent` decimals followed by the currency code,
+/// e.g. `#,##0.00 "EUR"`.
+fn currency_format(exponent: u32, currency: &str) -> String {
+    let decimals = if exponent == 0 {
+        String::new()
+    } else {
+        format!(".{}", "0".repeat(exponent as usize))
+    };
+    format!("#,##0{decimals} \"{}\"", currency.to_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    macro_rules! fees_test {
+        ($name:ident, $data:expr, $($property:ident is $value:expr),+) => {
+            #[test]
+            fn $name() {
+                let mut reader = csv::Reader::from_reader($data.as_bytes());
+                let entry: Entry = reader.deserialize().next().expect("should have entry").expect("should be ok");
+
+                $(
+                    assert_eq!(entry.$property, $value);
+                )*
+            }
+        };
+        ($name:ident, $data:expr, is_error) => {
+            #[test]
+            fn $name() {
+                #[allow(clippy::string_lit_as_bytes)]
+                let mut reader = csv::Reader::from_reader($data.as_bytes());
+                let entry: Result<Entry, _> = reader.deserialize().next().expect("should have entry");
+                assert!(entry.is_err());
+            }
+        };
+        ($name:ident, file, $data:expr, is_error) => {
+            #[test]
+            fn $name() {
+                let mut temp_file = NamedTempFile::new().expect("should create temp file");
+                let out_temp_file = NamedTempFile::new().expect("should create out temp file");
+                writeln!(temp_file, "{}", $data).expect("should write to temp file");
+
+                let result = parse(temp_file.path().to_path_buf(), Some(out_temp_file.path().to_path_buf()), &Options::default());
+                assert!(result.is_err());
+            }
+        };
+        ($name:ident, file, skip_errors, $data:expr, $result:ident) => {
+            #[test]
+            fn $name() {
+                let mut temp_file = NamedTempFile::new().expect("should create temp file");
+                let out_temp_file = NamedTempFile::new().expect("should create out temp file");
+                writeln!(temp_file, "{}", $data).expect("should write to temp file");
+
+                let options = Options { skip_errors: true, ..Options::default() };
+                let result = parse(temp_file.path().to_path_buf(), Some(out_temp_file.path().to_path_buf()), &options);
+                assert!(result.$result());
+            }
+        };
+        ($name:ident, file, $data:expr, is_ok) => {
+            #[test]
+            fn $name() {
+                let mut temp_file = NamedTempFile::new().expect("should create temp file");
+                let out_temp_file = NamedTempFile::new().expect("should create out temp file");
+                writeln!(temp_file, "{}", $data).expect("should write to temp file");
+
+                let result = parse(temp_file.path().to_path_buf(), Some(out_temp_file.path().to_path_buf()), &Options::default());
+                assert!(result.is_ok());
+            }
+        };
+    }
+
+    fn key(account_id: &str, currency: &str) -> AccountKey {
+        (account_id.to_string(), currency.to_string())
+    }
+
+    fees_test!(
+        entry_deserialize_valid,
+        "Amount,User ID,User Email\n\"0,25\",acct_123,user@example.com",
+        amount is 25,
+        account_id is "acct_123".to_string(),
+        email is "user@example.com".to_string(),
+        currency is "eur".to_string()
+    );
+
+    fees_test!(
+        test_entry_deserialize_currency,
+        "Amount,Currency,User ID,User Email\n\"0,25\",JPY,acct_123,user@example.com",
+        currency is "JPY".to_string()
+    );
+
+    fees_test!(
+        test_entry_deserialize_zero_amount,
+        "Amount,User ID,User Email\n\"0,00\",acct_000,zero@example.com",
+        amount is 0
+    );
+
+    fees_test!(
+        test_entry_deserialize_missing_field_email,
+        "Amount,User ID\n0,25,acct_123",
+        is_error
+    );
+
+    fees_test!(
+        test_entry_deserialize_invalid_amount,
+        "Amount,User ID,User Email\ninvalid,acct_123,user@example.com",
+        is_error
+    );
+
+    fees_test!(
+        test_parse_valid_csv,
+        file,
+        "Amount,User ID,User Email\n\"0,25\",acct_123,user@example.com\n\"1,50\",acct_456,test@example.com",
+        is_ok
+    );
+
+    fees_test!(
+        test_parse_empty_csv,
+        file,
+        "Amount,User ID,User Email",
+        is_ok
+    );
+
+    fees_test!(
+        test_parse_invalid_csv_format,
+        file,
+        "Amount,User ID,User Email\ninvalid,acct_123,user@example.com",
+        is_error
+    );
+
+    fees_test!(
+        test_parse_real_sample_format,
+        file,
+        "id,Created (UTC),Amount,Amount Refunded,Currency,User ID,User Email,Application ID,Transaction ID\nfee_1ABC123XYZ456789DEF,2025-12-31 14:30,\"0,25\",\"0,00\",eur,acct_1TEST001ABC123XYZ,user1@example.com,ca_ABC123XYZ456789DEF,ch_3ABC123XYZ456789DEF",
+        is_ok
+    );
+
+    #[test]
+    fn test_aggregate_in_memory() {
+        let data = "Amount,User ID,User Email\n\"0,25\",acct_123,user@example.com\n\"1,50\",acct_456,test@example.com\n\"0,75\",acct_123,user@example.com";
+        let statistics =
+            aggregate(csv::Reader::from_reader(data.as_bytes())).expect("should aggregate");
+
+        assert_eq!(statistics.len(), 2);
+        let account = statistics
+            .get(&key("acct_123", "eur"))
+            .expect("should have account");
+        assert_eq!(account.email, "user@example.com");
+        assert_eq!(account.transaction_count, 2);
+        assert_eq!(account.total_fees, 100);
+        assert_eq!(
+            statistics
+                .get(&key("acct_456", "eur"))
+                .expect("should have account")
+                .total_fees,
+            150
+        );
+    }
+
+    #[test]
+    fn test_records() {
+        let data = "Amount,Currency,User ID,User Email\n\"0,25\",eur,acct_123,user@example.com\n500,JPY,acct_456,test@example.com";
+        let entries: Vec<Entry> = records(csv::Reader::from_reader(data.as_bytes()))
+            .collect::<Result<_, _>>()
+            .expect("should read records");
+
+        assert_eq!(
+            entries,
+            [
+                Entry {
+                    id: None,
+                    created: None,
+                    amount: 25,
+                    amount_refunded: 0,
+                    account_id: "acct_123".to_string(),
+                    email: "user@example.com".to_string(),
+                    currency: "eur".to_string(),
+                },
+                Entry {
+                    id: None,
+                    created: None,
+                    amount: 500,
+                    amount_refunded: 0,
+                    account_id: "acct_456".to_string(),
+                    email: "test@example.com".to_string(),
+                    currency: "JPY".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_records_invalid_row() {
+        let data = "Amount,User ID,User Email\ninvalid,acct_123,user@example.com\n\"0,25\",acct_123,user@example.com";
+        let results: Vec<Result<Entry, FeesError>> =
+            records(csv::Reader::from_reader(data.as_bytes())).collect();
+
+        assert_eq!(results.len(), 2);
+        assert!(matches!(results[0], Err(FeesError::Csv(_))));
+        assert_eq!(results[1].as_ref().expect("should be ok").amount, 25);
+    }
+
+    #[test]
+    fn test_records_invalid_header() {
+        let data = "id,Amount\npo_123,\"100,50\"";
+        let results: Vec<Result<Entry, FeesError>> =
+            records(csv::Reader::from_reader(data.as_bytes())).collect();
+
+        assert_eq!(results.len(), 1);
+        assert!(matches!(&results[0], Err(FeesError::MissingColumn(_))));
+    }
+
+    #[test]
+    fn test_aggregate_locale() {
+        let data = "Amount,User ID,User Email\n\"1,000\",acct_123,user@example.com\n\"1.000,50\",acct_456,other@example.com";
+        let aggregate = |locale| {
+            let options = Options {
+                locale,
+                ..Options::default()
+            };
+            aggregate_with(csv::Reader::from_reader(data.as_bytes()), &options)
+        };
+
+        let aggregation = aggregate(Locale::De).expect("should aggregate");
+        assert_eq!(
+            aggregation.statistics[&key("acct_123", "eur")].total_fees,
+            100
+        );
+        assert_eq!(
+            aggregation.statistics[&key("acct_456", "eur")].total_fees,
+            100_050
+        );
+        let aggregation = aggregate(Locale::Auto).expect("should aggregate");
+        assert_eq!(
+            aggregation.statistics[&key("acct_123", "eur")].total_fees,
+            100_000
+        );
+        assert!(aggregate(Locale::En).is_err());
+    }
+
+    #[test]
+    fn test_parse_sqlite() {
+        let mut temp_file = NamedTempFile::new().expect("should create temp file");
+        let out_temp_file = NamedTempFile::new().expect("should create out temp file");
+        writeln!(
+            temp_file,
+            "Amount,User ID,User Email\n\"0,25\",acct_123,o'brien@example.com\n\"0,50\",acct_123,o'brien@example.com\n500,acct_456,test@example.com"
+        )
+        .expect("should write to temp file");
+
+        let options = Options {
+            format: OutputFormat::Sqlite,
+            totals: true,
+            ..Options::default()
+        };
+        parse(
+            temp_file.path().to_path_buf(),
+            Some(out_temp_file.path().to_path_buf()),
+            &options,
+        )
+        .expect("should parse");
+
+        let connection =
+            rusqlite::Connection::open(out_temp_file.path()).expect("should open database");
+        let mut select = connection
+            .prepare("SELECT account_id, email, transaction_count, total_fees FROM account_fees")
+            .expect("should prepare query");
+        let rows: Vec<(String, String, u32, i64)> = select
+            .query_map((), |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })
+            .expect("should query")
+            .collect::<Result<_, _>>()
+            .expect("should read rows");
+        assert_eq!(
+            rows,
+            [
+                (
+                    "acct_123".to_string(),
+                    "o'brien@example.com".to_string(),
+                    2,
+                    75
+                ),
+                (
+                    "acct_456".to_string(),
+                    "test@example.com".to_string(),
+                    1,
+                    50_000
+                ),
+                ("TOTAL".to_string(), String::new(), 3, 50_075),
+            ]
+        );
+
+        let error = parse(
+            temp_file.path().to_path_buf(),
+            Some(out_temp_file.path().to_path_buf()),
+            &Options {
+                group_by: GroupBy::Month,
+                ..options
+            },
+        )
+        .expect_err("should be error");
+        assert!(matches!(
+            error,
+            FeesError::IncompatibleOption("--format sqlite", "--group-by month")
+        ));
+    }
+
+    #[test]
+    fn test_write_sqlite_currencies() {
+        let statistics = [
+            AccountFees::new("acct_123", "user@example.com", "eur"),
+            AccountFees::new("acct_123", "user@example.com", "jpy"),
+        ];
+        let options = Options {
+            format: OutputFormat::Sqlite,
+            ..Options::default()
+        };
+        let error = write(&statistics, &mut Vec::new(), &options).expect_err("should be error");
+        assert_eq!(
+            error.to_string(),
+            "the sqlite output has no currency column but the fees are in eur, jpy, split the export by currency"
+        );
+    }
+
+    #[test]
+    fn test_min_fee_and_fee_range() {
+        let mut single = AccountFees::new("acct_123", "user@example.com", "eur");
+        assert_eq!((single.min_fee, single.fee_range()), (0, 0));
+        single.add_fee(250);
+        assert_eq!(
+            (single.min_fee, single.max_fee, single.fee_range()),
+            (250, 250, 0)
+        );
+
+        let mut multi = AccountFees::new("acct_456", "other@example.com", "eur");
+        for fee in [300, 120, 900, 450] {
+            multi.add_fee(fee);
+        }
+        assert_eq!(
+            (multi.min_fee, multi.max_fee, multi.fee_range()),
+            (120, 900, 780)
+        );
+
+        let mut negative = AccountFees::new("acct_789", "refund@example.com", "eur");
+        negative.add_fee(-50);
+        negative.add_fee(10);
+        assert_eq!((negative.min_fee, negative.fee_range()), (-50, 60));
+
+        let mut merged = AccountFees::new("acct_456", "other@example.com", "eur");
+        merged.merge(&single);
+        assert_eq!(merged.min_fee, 250);
+        merged.merge(&multi);
+        merged.merge(&AccountFees::new("acct_456", "", "eur"));
+        assert_eq!((merged.min_fee, merged.max_fee), (120, 900));
+
+        let columns = default_columns(
+            "eur",
+            ExtraColumns {
+                max_fee: true,
+                fee_range: true,
+                ..ExtraColumns::default()
+            },
+            GroupBy::Account,
+        );
+        assert_eq!(
+            columns[5..],
+            ["max_fee_eur", "min_fee_eur", "fee_range_eur"]
+        );
+        assert_eq!(
+            multi.field("fee_range_eur", "eur", GroupBy::Account),
+            Some(Field::Amount(780))
+        );
+    }
+
+    #[test]
+    fn test_aggregate_net_fees() {
+        let data = "Amount,Amount Refunded,User ID,User Email\n\"1,00\",\"0,00\",acct_123,user@example.com\n\"2,00\",\"0,50\",acct_123,user@example.com\n\"0,30\",,acct_456,other@example.com";
+        let statistics =
+            aggregate(csv::Reader::from_reader(data.as_bytes())).expect("should aggregate");
+
+        let fees = &statistics[&key("acct_123", "eur")];
+        assert_eq!(fees.total_fees, 300);
+        assert_eq!(fees.total_refunded, 50);
+        assert_eq!(fees.net_fees(), 250);
+        assert_eq!(statistics[&key("acct_456", "eur")].net_fees(), 30);
+
+        let columns = default_columns(
+            "eur",
+            ExtraColumns {
+                net_fees: true,
+                ..ExtraColumns::default()
+            },
+            GroupBy::Account,
+        );
+        let layout = Layout {
+            columns: &columns,
+            currency_label: "eur",
+            group_by: GroupBy::Account,
+        };
+        let mut output = Vec::new();
+        write_csv_records(
+            &sort(statistics.into_values(), SortKey::Account, false),
+            &mut output,
+            &csv::WriterBuilder::new(),
+            layout,
+            true,
+            None,
+        )
+        .expect("should write csv");
+        assert_eq!(
+            String::from_utf8(output).expect("should be utf-8"),
+            "account_id,email,currency,transaction_count,total_fees_eur,net_fees_eur\nacct_123,user@example.com,eur,2,3.00,2.50\nacct_456,other@example.com,eur,1,0.30,0.30\n"
+        );
+    }
+
+    #[test]
+    fn test_aggregate_sorted() {
+        let data = "Amount,User ID,User Email\n\"3,00\",acct_c,c@example.com\n\"1,00\",acct_a,a@example.com\n\"2,00\",acct_b,b@example.com\n\"0,50\",acct_a,a@example.com";
+        let account_ids = |sort_by| {
+            aggregate_sorted(csv::Reader::from_reader(data.as_bytes()), sort_by)
+                .expect("should aggregate")
+                .into_iter()
+                .map(|statistic| statistic.account_id)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            account_ids(SortKey::Account),
+            ["acct_a", "acct_b", "acct_c"]
+        );
+        assert_eq!(account_ids(SortKey::Fees), ["acct_a", "acct_b", "acct_c"]);
+        assert_eq!(account_ids(SortKey::Count), ["acct_b", "acct_c", "acct_a"]);
+    }
+
+    #[test]
+    fn test_aggregate_amounts_in_cents() {
+        let data = "Amount,Currency,User ID,User Email\n150,eur,acct_123,user@example.com\n25,eur,acct_123,user@example.com\n500,jpy,acct_456,test@example.com";
+        let options = Options {
+            amounts_in_cents: true,
+            ..Options::default()
+        };
+        let aggregation = aggregate_with(csv::Reader::from_reader(data.as_bytes()), &options)
+            .expect("should aggregate");
+
+        assert_eq!(
+            aggregation.statistics[&key("acct_123", "eur")].total_fees,
+            175
+        );
+        assert_eq!(
+            aggregation.statistics[&key("acct_456", "jpy")].total_fees,
+            500
+        );
+    }
+
+    #[test]
+    fn test_aggregate_round_trip_keeps_totals() {
+        let data = "Amount,Currency,User ID,User Email\n\"0,125\",eur,acct_123,user@example.com\n\"1.234,50\",eur,acct_123,user@example.com\n500,jpy,acct_456,test@example.com";
+        let options = Options {
+            round_trip: true,
+            ..Options::default()
+        };
+        let aggregation = aggregate_with(csv::Reader::from_reader(data.as_bytes()), &options)
+            .expect("should aggregate");
+
+        assert_eq!(
+            aggregation.statistics[&key("acct_123", "eur")].total_fees,
+            123_463
+        );
+        assert_eq!(
+            aggregation.statistics[&key("acct_456", "jpy")].total_fees,
+            500
+        );
+    }
+
+    #[test]
+    fn test_aggregate_looks_like_cents() {
+        let aggregate = |data: &str| {
+            aggregate_with(
+                csv::Reader::from_reader(data.as_bytes()),
+                &Options::default(),
+            )
+            .expect("should aggregate")
+        };
+
+        let cents = aggregate(
+            "Amount,User ID,User Email\n150,acct_123,user@example.com\n25,acct_123,user@example.com",
+        );
+        assert_eq!(cents.whole_amounts, 2);
+        assert!(cents.looks_like_cents());
+
+        let mixed = aggregate(
+            "Amount,User ID,User Email\n150,acct_123,user@example.com\n\"0,25\",acct_123,user@example.com",
+        );
+        assert_eq!(mixed.fractional_amounts, 1);
+        assert!(!mixed.looks_like_cents());
+
+        let yen =
+            aggregate("Amount,Currency,User ID,User Email\n500,jpy,acct_123,user@example.com");
+        assert!(!yen.looks_like_cents());
+    }
+
+    #[test]
+    fn test_aggregate_zero_decimal_currency() {
+        let data = "Amount,Currency,User ID,User Email\n500,jpy,acct_jpy,jpy@example.com\n\"0,25\",eur,acct_eur,eur@example.com\n\"1,234\",bhd,acct_bhd,bhd@example.com";
+        let statistics =
+            aggregate(csv::Reader::from_reader(data.as_bytes())).expect("should aggregate");
+
+        let total = |account: &str, currency: &str| {
+            statistics
+                .get(&key(account, currency))
+                .expect("should have account")
+                .total_fees
+        };
+        assert_eq!(total("acct_jpy", "jpy"), 500);
+        assert_eq!(total("acct_eur", "eur"), 25);
+        assert_eq!(total("acct_bhd", "bhd"), 1234);
+    }
+
+    #[test]
+    fn test_aggregate_half_even_rounding() {
+        let data = "Amount,User ID,User Email\n\"0,125\",acct_123,user@example.com\n\"0,135\",acct_123,user@example.com";
+        let aggregation = aggregate_with(
+            csv::Reader::from_reader(data.as_bytes()),
+            &Options {
+                rounding: Rounding::HalfEven,
+                ..Options::default()
+            },
+        )
+        .expect("should aggregate");
+        let half_up =
+            aggregate(csv::Reader::from_reader(data.as_bytes())).expect("should aggregate");
+
+        let key = key("acct_123", "eur");
+        assert_eq!(aggregation.statistics[&key].total_fees, 26);
+        assert_eq!(half_up[&key].total_fees, 27);
+    }
+
+    #[test]
+    fn test_aggregate_per_currency() {
+        let data = "Amount,Currency,User ID,User Email\n\"0,25\",eur,acct_123,user@example.com\n\"0,50\",usd,acct_123,user@example.com\n\"0,75\",EUR,acct_123,user@example.com";
+        let statistics =
+            aggregate(csv::Reader::from_reader(data.as_bytes())).expect("should aggregate");
+
+        assert_eq!(statistics.len(), 2);
+        let eur = &statistics[&key("acct_123", "eur")];
+        assert_eq!(eur.transaction_count, 2);
+        assert_eq!(eur.total_fees, 100);
+        let usd = &statistics[&key("acct_123", "usd")];
+        assert_eq!(usd.transaction_count, 1);
+        assert_eq!(usd.total_fees, 50);
+    }
+
+    #[test]
+    fn test_aggregate_gzip_stream() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder
+            .write_all(b"Amount,User ID,User Email\n\"0,25\",acct_123,user@example.com\n\"0,75\",acct_123,user@example.com\n")
+            .expect("should compress");
+        let compressed = encoder.finish().expect("should finish compression");
+
+        let statistics = aggregate(csv::Reader::from_reader(flate2::read::GzDecoder::new(
+            compressed.as_slice(),
+        )))
+        .expect("should aggregate");
+        let account = &statistics[&key("acct_123", "eur")];
+        assert_eq!(account.transaction_count, 2);
+        assert_eq!(account.total_fees, 100);
+    }
+
+    /// Reader yielding a single byte per read, which splits the BOM across reads.
+    struct ByteReader<'a>(&'a [u8]);
+
+    impl Read for ByteReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match (self.0.split_first(), buf.first_mut()) {
+                (Some((byte, rest)), Some(first)) => {
+                    *first = *byte;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn test_aggregate_with_bom() {
+        let data = "\u{feff}Amount,User ID,User Email\n\"0,25\",acct_123,user@example.com";
+        let statistics =
+            aggregate(csv::Reader::from_reader(data.as_bytes())).expect("should aggregate");
+        assert_eq!(statistics[&key("acct_123", "eur")].total_fees, 25);
+
+        let statistics = aggregate(csv::Reader::from_reader(ByteReader(data.as_bytes())))
+            .expect("should aggregate");
+        assert_eq!(statistics[&key("acct_123", "eur")].total_fees, 25);
+    }
+
+    fees_test!(
+        test_parse_with_bom,
+        file,
+        "\u{feff}Amount,User ID,User Email\n\"0,25\",acct_123,user@example.com",
+        is_ok
+    );
+
+    #[test]
+    fn test_aggregate_payouts_header() {
+        let data =
+            "id,Amount,Arrival Date (UTC),Status,Currency\npo_123,\"100,50\",2025-12-31,paid,eur";
+        let result = aggregate(csv::Reader::from_reader(data.as_bytes()));
+
+        let error = result.expect_err("should be error");
+        assert!(
+            matches!(&error, FeesError::MissingColumn(columns) if columns == "User ID, User Email")
+        );
+        assert_eq!(
+            error.to_string(),
+            "the CSV is missing the required columns: User ID, User Email"
+        );
+    }
+
+    #[test]
+    fn test_aggregate_without_header() {
+        let data = "\"0,25\",acct_123,user@example.com\n\"1,50\",acct_456,test@example.com";
+        let result = aggregate(csv::Reader::from_reader(data.as_bytes()));
+
+        let error = result.expect_err("should be error");
+        assert!(
+            matches!(&error, FeesError::NoHeader { line, .. } if line == "\"0,25\",acct_123,user@example.com")
+        );
+        assert_eq!(
+            error.to_string(),
+            "no header found, the first line is '\"0,25\",acct_123,user@example.com' but a header with the columns Amount, User ID, User Email was expected"
+        );
+    }
+
+    #[test]
+    fn test_aggregate_without_header_semicolon_delimiter() {
+        let data = "0,25;\"acct;123\";user@example.com";
+        let csv_reader = csv::ReaderBuilder::new()
+            .delimiter(b';')
+            .from_reader(data.as_bytes());
+        let options = Options {
+            input_delimiter: InputDelimiter::Byte(b';'),
+            ..Options::default()
+        };
+
+        let error = aggregate_with(csv_reader, &options).expect_err("should be error");
+        assert!(
+            matches!(&error, FeesError::NoHeader { line, .. } if line == "0,25;\"acct;123\";user@example.com")
+        );
+    }
+
+    #[test]
+    fn test_aggregate_custom_columns() {
+        let data = "Betrag,Konto,E-Mail,Amount\n\"0,25\",acct_123,user@example.com,\n\"0,50\",acct_123,user@example.com,";
+        let options = Options {
+            columns: Columns {
+                amount: "Betrag".to_string(),
+                account_id: "Konto".to_string(),
+                email: "E-Mail".to_string(),
+            },
+            ..Options::default()
+        };
+        let aggregation = aggregate_with(csv::Reader::from_reader(data.as_bytes()), &options)
+            .expect("should aggregate");
+
+        let fees = &aggregation.statistics[&key("acct_123", "eur")];
+        assert_eq!(fees.total_fees, 75);
+        assert_eq!(fees.email, "user@example.com");
+    }
+
+    #[test]
+    fn test_aggregate_custom_columns_missing() {
+        let data = "Betrag,User ID,User Email\n\"0,25\",acct_123,user@example.com";
+        let options = Options {
+            columns: Columns {
+                amount: "Montant".to_string(),
+                ..Columns::default()
+            },
+            ..Options::default()
+        };
+        let error = aggregate_with(csv::Reader::from_reader(data.as_bytes()), &options)
+            .expect_err("should be error");
+
+        assert!(matches!(&error, FeesError::MissingColumn(columns) if columns == "Montant"));
+    }
+
+    #[test]
+    fn test_days_before() {
+        let today = NaiveDate::from_ymd_opt(2026, 3, 1).expect("should be a date");
+        assert_eq!(days_before(today, 0), today);
+        assert_eq!(
+            days_before(today, 30),
+            NaiveDate::from_ymd_opt(2026, 1, 30).expect("should be a date")
+        );
+        assert_eq!(days_before(today, u32::MAX), NaiveDate::MIN);
+    }
+
+    #[test]
+    fn test_options_since_days() {
+        let options = |flags: &[&str]| {
+            let arguments =
+                Args::try_parse_from(["stripe-csv", "fees.csv", "fees"].iter().chain(flags))
+                    .expect("should parse flags");
+            Options::from(&arguments)
+        };
+
+        let today = chrono::Utc::now().date_naive();
+        let since = options(&["--since-days", "30", "--to", "2099-12-31"]);
+        assert_eq!(since.from, Some(days_before(today, 30)));
+        assert_eq!(since.to, NaiveDate::from_ymd_opt(2099, 12, 31));
+        assert_eq!(options(&[]).from, None);
+        assert!(
+            Args::try_parse_from([
+                "stripe-csv",
+                "fees.csv",
+                "fees",
+                "--since-days",
+                "30",
+                "--from",
+                "2025-12-01"
+            ])
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_aggregate_empty_amount() {
+        let data = "Amount,User ID,User Email\n\"0,25\",acct_123,user@example.com\n,acct_123,user@example.com\n\" \t\",acct_456,other@example.com";
+        let aggregate = |empty_amount| {
+            let options = Options {
+                empty_amount,
+                ..Options::default()
+            };
+            aggregate_with(csv::Reader::from_reader(data.as_bytes()), &options)
+        };
+
+        let error = aggregate(EmptyAmount::Error).expect_err("should be error");
+        assert!(error.to_string().contains("empty or whitespace"));
+
+        let aggregation = aggregate(EmptyAmount::Zero).expect("should aggregate");
+        assert_eq!(
+            aggregation.statistics[&key("acct_123", "eur")].total_fees,
+            25
+        );
+        assert_eq!(
+            aggregation.statistics[&key("acct_123", "eur")].transaction_count,
+            2
+        );
+        assert_eq!(
+            aggregation.statistics[&key("acct_456", "eur")].total_fees,
+            0
+        );
+        assert_eq!(aggregation.empty_amounts, 0);
+
+        let aggregation = aggregate(EmptyAmount::Skip).expect("should aggregate");
+        assert_eq!(
+            aggregation.statistics[&key("acct_123", "eur")].transaction_count,
+            1
+        );
+        assert!(!aggregation.statistics.contains_key(&key("acct_456", "eur")));
+        assert_eq!(aggregation.empty_amounts, 2);
+        assert_eq!(aggregation.rows, 3);
+    }
+
+    #[test]
+    fn test_aggregate_input_has_no_header() {
+        let data = "fee_1,acct_123,\"0,25\",user@example.com\nfee_2,acct_123,\"0,50\",user@example.com\nfee_3,acct_456,\"1,00\",other@example.com";
+        let options = Options {
+            columns: Columns {
+                amount: "3".to_string(),
+                account_id: "2".to_string(),
+                email: "4".to_string(),
+            },
+            input_has_no_header: true,
+            ..Options::default()
+        };
+        let reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(data.as_bytes());
+        let aggregation = aggregate_with(reader, &options).expect("should aggregate");
+
+        assert_eq!(
+            aggregation.statistics[&key("acct_123", "eur")].total_fees,
+            75
+        );
+        assert_eq!(
+            aggregation.statistics[&key("acct_456", "eur")].total_fees,
+            100
+        );
+        assert_eq!(
+            aggregation.statistics[&key("acct_456", "eur")].email,
+            "other@example.com"
+        );
+    }
+
+    #[test]
+    fn test_aggregate_input_has_no_header_invalid_position() {
+        let data = "\"0,25\",acct_123,user@example.com";
+        for amount in ["0", "4", "Amount"] {
+            let options = Options {
+                columns: Columns {
+                    amount: amount.to_string(),
+                    account_id: "2".to_string(),
+                    email: "3".to_string(),
+                },
+                input_has_no_header: true,
+                ..Options::default()
+            };
+            let reader = csv::ReaderBuilder::new()
+                .has_headers(false)
+                .from_reader(data.as_bytes());
+            let error = aggregate_with(reader, &options).expect_err("should be error");
+
+            assert!(matches!(
+                &error,
+                FeesError::InvalidColumnPosition { column, width: 3 } if column == amount
+            ));
+            assert_eq!(error.exit_code(), exit::MALFORMED_DATA);
+        }
+    }
+
+    #[test]
+    fn test_aggregate_fail_on_unknown_columns() {
+        let data = "id,Created (UTC),Amount,Currency,User ID,User Email,Description,Type\nfee_1,2025-12-31 14:30,\"0,25\",eur,acct_123,user@example.com,Fee,payment";
+        let options = Options {
+            fail_on_unknown_columns: true,
+            ..Options::default()
+        };
+        let error = aggregate_with(csv::Reader::from_reader(data.as_bytes()), &options)
+            .expect_err("should be error");
+        assert!(
+            matches!(&error, FeesError::UnknownColumns(columns) if columns == "Description, Type")
+        );
+        assert_eq!(error.exit_code(), exit::MALFORMED_DATA);
+
+        aggregate_with(
+            csv::Reader::from_reader(data.as_bytes()),
+            &Options::default(),
+        )
+        .expect("should ignore the unknown columns by default");
+        let data = "id,Created (UTC),Betrag,Currency,User ID,User Email\nfee_1,2025-12-31 14:30,\"0,25\",eur,acct_123,user@example.com";
+        let options = Options {
+            columns: Columns {
+                amount: "Betrag".to_string(),
+                ..Columns::default()
+            },
+            ..options
+        };
+        aggregate_with(csv::Reader::from_reader(data.as_bytes()), &options)
+            .expect("should know the renamed columns");
+        aggregate_with(csv::Reader::from_reader(&b""[..]), &options)
+            .expect("should accept an empty input");
+    }
+
+    #[test]
+    fn test_aggregate_dedupe() {
+        let data = "id,Amount,User ID,User Email\nfee_1,\"0,25\",acct_123,user@example.com\nfee_2,\"1,50\",acct_123,user@example.com\nfee_1,\"0,25\",acct_123,user@example.com\n,\"0,10\",acct_123,user@example.com\n,\"0,10\",acct_123,user@example.com";
+        for jobs in [
+            NonZeroUsize::MIN,
+            NonZeroUsize::new(2).expect("should be non zero"),
+        ] {
+            let options = Options {
+                dedupe: true,
+                jobs,
+                ..Options::default()
+            };
+            let aggregation = aggregate_with(csv::Reader::from_reader(data.as_bytes()), &options)
+                .expect("should aggregate");
+
+            assert_eq!(aggregation.duplicates, 1);
+            assert_eq!(aggregation.rows, 4);
+            let account = &aggregation.statistics[&key("acct_123", "eur")];
+            assert_eq!(account.transaction_count, 4);
+            assert_eq!(account.total_fees, 195);
+        }
+    }
+
+    #[test]
+    fn test_aggregate_dedupe_skip_errors() {
+        let data = "id,Amount,User ID,User Email\nfee_1,invalid,acct_123,user@example.com\nfee_1,\"0,25\",acct_123,user@example.com\nfee_1,\"0,25\",acct_123,user@example.com";
+        let options = Options {
+            dedupe: true,
+            skip_errors: true,
+            ..Options::default()
+        };
+        let aggregation = aggregate_with(csv::Reader::from_reader(data.as_bytes()), &options)
+            .expect("should aggregate");
+
+        assert_eq!(aggregation.duplicates, 1);
+        assert_eq!(aggregation.skipped.len(), 1);
+        let account = &aggregation.statistics[&key("acct_123", "eur")];
+        assert_eq!(account.transaction_count, 1);
+        assert_eq!(account.total_fees, 25);
+    }
+
+    #[test]
+    fn test_aggregate_trim_whitespace() {
+        let data = "Amount,User ID,User Email\n\"0,25\",acct_123,user@example.com\n\"1,50\",acct_123 ,user@example.com\n\"0,10\",\" acct_123\",\" user@example.com \"\n\"0,05\",acct_456,test@example.com";
+        let aggregate = |trim_whitespace| {
+            let options = Options {
+                trim_whitespace,
+                only_accounts: Some(HashSet::from(["acct_123".to_string()])),
+                ..Options::default()
+            };
+            aggregate_with(csv::Reader::from_reader(data.as_bytes()), &options)
+                .expect("should aggregate")
+        };
+
+        let aggregation = aggregate(true);
+        assert_eq!(aggregation.trimmed_keys, 2);
+        assert_eq!(aggregation.statistics.len(), 1);
+        let account = &aggregation.statistics[&key("acct_123", "eur")];
+        assert_eq!(account.transaction_count, 3);
+        assert_eq!(account.total_fees, 185);
+        assert_eq!(account.email, "user@example.com");
+
+        let aggregation = aggregate(false);
+        assert_eq!(aggregation.trimmed_keys, 0);
+        assert_eq!(
+            aggregation.statistics[&key("acct_123", "eur")].total_fees,
+            25
+        );
+    }
+
+    #[test]
+    fn test_aggregate_skip_repeated_headers() {
+        let data = "\u{feff}Amount,User ID,User Email\n\"0,25\",acct_123,user@example.com\n\u{feff}Amount,User ID,User Email\n\"1,50\",acct_123,user@example.com\nAmount,User ID,User Email\n";
+        let options = Options {
+            skip_repeated_headers: true,
+            ..Options::default()
+        };
+        let aggregation = aggregate_with(csv::Reader::from_reader(data.as_bytes()), &options)
+            .expect("should aggregate");
+
+        assert_eq!(aggregation.repeated_headers, 2);
+        assert_eq!(aggregation.rows, 2);
+        assert_eq!(
+            aggregation.statistics[&key("acct_123", "eur")].total_fees,
+            175
+        );
+
+        assert!(
+            aggregate_with(
+                csv::Reader::from_reader(data.as_bytes()),
+                &Options::default()
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_aggregate_dedupe_without_id_column() {
+        let data = "Amount,User ID,User Email\n\"0,25\",acct_123,user@example.com";
+        let options = Options {
+            dedupe: true,
+            ..Options::default()
+        };
+        let error = aggregate_with(csv::Reader::from_reader(data.as_bytes()), &options)
+            .expect_err("should be error");
+        assert!(matches!(&error, FeesError::MissingColumn(column) if column == "id"));
+    }
+
+    fn date(date: &str) -> NaiveDate {
+        crate::timestamp_serde::parse_date(date).expect("should be a valid date")
+    }
+
+    #[test]
+    fn test_aggregate_date_range() {
+        let data = "Created (UTC),Amount,User ID,User Email\n2025-11-30 23:59,\"0,10\",acct_123,user@example.com\n2025-12-01 00:00,\"0,25\",acct_123,user@example.com\n2025-12-31 23:59,\"1,50\",acct_123,user@example.com\n2026-01-01 00:00,\"2,00\",acct_123,user@example.com\n,\"3,00\",acct_123,user@example.com";
+        let options = Options {
+            from: Some(date("2025-12-01")),
+            to: Some(date("2025-12-31")),
+            ..Options::default()
+        };
+        let aggregation = aggregate_with(csv::Reader::from_reader(data.as_bytes()), &options)
+            .expect("should aggregate");
+
+        assert_eq!(aggregation.out_of_range, 3);
+        let account = &aggregation.statistics[&key("acct_123", "eur")];
+        assert_eq!(account.transaction_count, 2);
+        assert_eq!(account.total_fees, 175);
+    }
+
+    #[test]
+    fn test_aggregate_account_filters() {
+        let data = "Amount,User ID,User Email\n\"0,10\",acct_1,a@example.com\n\"0,25\",acct_2,b@example.com\n\"1,50\",acct_3,c@example.com\n\"2,00\",acct_12,d@example.com\n\"3,00\",acct_1,a@example.com";
+        let accounts = |ids: &[&str]| ids.iter().map(ToString::to_string).collect::<HashSet<_>>();
+        let aggregate_accounts = |options: &Options| {
+            let aggregation = aggregate_with(csv::Reader::from_reader(data.as_bytes()), options)
+                .expect("should aggregate");
+            let mut ids: Vec<String> = aggregation
+                .statistics
+                .into_keys()
+                .map(|(account_id, _)| account_id)
+                .collect();
+            ids.sort_unstable();
+            (ids, aggregation.filtered_accounts)
+        };
+
+        let only = Options {
+            only_accounts: Some(accounts(&["acct_1", "acct_2"])),
+            ..Options::default()
+        };
+        assert_eq!(
+            aggregate_accounts(&only),
+            (vec!["acct_1".to_string(), "acct_2".to_string()], 2)
+        );
+        let excluded = Options {
+            exclude_accounts: accounts(&["acct_1"]),
+            ..Options::default()
+        };
+        assert_eq!(
+            aggregate_accounts(&excluded),
+            (
+                vec![
+                    "acct_12".to_string(),
+                    "acct_2".to_string(),
+                    "acct_3".to_string()
+                ],
+                2
+            )
+        );
+        let both = Options {
+            exclude_accounts: accounts(&["acct_2"]),
+            ..only
+        };
+        assert_eq!(aggregate_accounts(&both), (vec!["acct_1".to_string()], 3));
+    }
+
+    #[test]
+    fn test_aggregate_open_date_range() {
+        let data = "Created (UTC),Amount,User ID,User Email\n2025-11-30 23:59,\"0,10\",acct_123,user@example.com\n2025-12-01 00:00,\"0,25\",acct_123,user@example.com";
+        let options = Options {
+            from: Some(date("2025-12-01")),
+            ..Options::default()
+        };
+        let aggregation = aggregate_with(csv::Reader::from_reader(data.as_bytes()), &options)
+            .expect("should aggregate");
+
+        assert_eq!(aggregation.out_of_range, 1);
+        assert_eq!(
+            aggregation.statistics[&key("acct_123", "eur")].total_fees,
+            25
+        );
+    }
+
+    #[test]
+    fn test_aggregate_invalid_timestamp() {
+        let data = "Created (UTC),Amount,User ID,User Email\n31/12/2025 14:30,\"0,25\",acct_123,user@example.com";
+        let aggregation = aggregate_with(
+            csv::Reader::from_reader(data.as_bytes()),
+            &Options::default(),
+        )
+        .expect("should not parse the timestamps without a date filter");
+        assert_eq!(
+            aggregation.statistics[&key("acct_123", "eur")].total_fees,
+            25
+        );
+
+        let options = Options {
+            from: Some(date("2025-12-01")),
+            ..Options::default()
+        };
+        let error = aggregate_with(csv::Reader::from_reader(data.as_bytes()), &options)
+            .expect_err("should be error");
+        assert_eq!(
+            error.to_string(),
+            "line 2: invalid timestamp '31/12/2025 14:30', expected YYYY-MM-DD HH:MM, YYYY-MM-DD HH:MM:SS or ISO 8601 such as 2025-12-31T14:30:05Z"
+        );
+        assert_eq!(error.exit_code(), exit::MALFORMED_DATA);
+
+        let options = Options {
+            skip_errors: true,
+            group_by: GroupBy::Month,
+            ..Options::default()
+        };
+        let data = format!("{data}\n2025-12-31 14:30,\"0,50\",acct_123,user@example.com");
+        let aggregation = aggregate_with(csv::Reader::from_reader(data.as_bytes()), &options)
+            .expect("should skip the invalid timestamp");
+        assert_eq!(aggregation.skipped.len(), 1);
+        assert_eq!(
+            aggregation.statistics[&key("2025-12", "eur")].total_fees,
+            50
+        );
+    }
+
+    #[test]
+    fn test_aggregate_empty_date_range() {
+        let data = "Created (UTC),Amount,User ID,User Email\n2025-12-31 14:30,\"0,25\",acct_123,user@example.com";
+        let options = Options {
+            from: Some(date("2025-12-31")),
+            to: Some(date("2025-12-01")),
+            ..Options::default()
+        };
+        let error = aggregate_with(csv::Reader::from_reader(data.as_bytes()), &options)
+            .expect_err("should be error");
+        assert!(matches!(error, FeesError::InvalidDateRange { .. }));
+        assert_eq!(
+            error.to_string(),
+            "--from 2025-12-31 is after --to 2025-12-01, the date range is empty"
+        );
+    }
+
+    #[test]
+    fn test_aggregate_date_range_without_created_column() {
+        let data = "Amount,User ID,User Email\n\"0,25\",acct_123,user@example.com";
+        let options = Options {
+            to: Some(date("2025-12-31")),
+            ..Options::default()
+        };
+        let error = aggregate_with(csv::Reader::from_reader(data.as_bytes()), &options)
+            .expect_err("should be error");
+        assert!(matches!(&error, FeesError::MissingColumn(column) if column == "Created (UTC)"));
+    }
+
+    #[test]
+    fn test_aggregate_group_by_month() {
+        let data = "Created (UTC),Amount,Currency,User ID,User Email\n2025-12-31 14:30,\"0,25\",eur,acct_123,user@example.com\n2025-11-02 10:00,\"1,50\",eur,acct_456,test@example.com\n2025-12-01 00:00,\"0,75\",eur,acct_456,test@example.com\n2025-12-15 09:00,500,jpy,acct_789,other@example.com";
+        let options = Options {
+            group_by: GroupBy::Month,
+            ..Options::default()
+        };
+        let aggregation = aggregate_with(csv::Reader::from_reader(data.as_bytes()), &options)
+            .expect("should aggregate");
+
+        assert_eq!(aggregation.statistics.len(), 3);
+        let december = &aggregation.statistics[&key("2025-12", "eur")];
+        assert_eq!(december.month.as_deref(), Some("2025-12"));
+        assert_eq!(december.account_id, "");
+        assert_eq!(december.transaction_count, 2);
+        assert_eq!(december.total_fees, 100);
+        assert_eq!(december.email, "");
+        assert_eq!(
+            aggregation.statistics[&key("2025-11", "eur")].total_fees,
+            150
+        );
+        assert_eq!(
+            aggregation.statistics[&key("2025-12", "jpy")].total_fees,
+            500
+        );
+    }
+
+    #[test]
+    fn test_aggregate_group_by_month_without_timestamp() {
+        let data = "Created (UTC),Amount,User ID,User Email\n2025-12-31 14:30,\"0,25\",acct_123,user@example.com\n,\"1,50\",acct_456,test@example.com";
+        let options = Options {
+            group_by: GroupBy::Month,
+            ..Options::default()
+        };
+        let error = aggregate_with(csv::Reader::from_reader(data.as_bytes()), &options)
+            .expect_err("should be error");
+        assert!(matches!(error, FeesError::MissingTimestamp(3)));
+
+        let options = Options {
+            skip_errors: true,
+            ..options
+        };
+        let aggregation = aggregate_with(csv::Reader::from_reader(data.as_bytes()), &options)
+            .expect("should aggregate");
+        assert_eq!(aggregation.skipped.len(), 1);
+        assert_eq!(aggregation.skipped[0].line, 3);
+        assert_eq!(aggregation.statistics.len(), 1);
+    }
+
+    #[test]
+    fn test_aggregate_empty_input() {
+        let statistics = aggregate(csv::Reader::from_reader(&b""[..])).expect("should aggregate");
+        assert!(statistics.is_empty());
+    }
+
+    fees_test!(test_parse_empty_file, file, "", is_ok);
+
+    #[test]
+    fn test_aggregate_invalid_entry() {
+        let data = "Amount,User ID,User Email\ninvalid,acct_123,user@example.com";
+        assert!(aggregate(csv::Reader::from_reader(data.as_bytes())).is_err());
+    }
+
+    fn accounts() -> Vec<AccountFees> {
+        let mut first = AccountFees::new("acct_b", "b@example.com", "eur");
+        first.add_fee(100);
+        let mut second = AccountFees::new("acct_a", "a@example.com", "eur");
+        second.add_fee(50);
+        second.add_fee(50);
+        let mut third = AccountFees::new("acct_c", "c@example.com", "eur");
+        third.add_fee(300);
+        vec![first, second, third]
+    }
+
+    macro_rules! sort_test {
+        ($name:ident, $sort_by:expr, $descending:expr, $expected:expr) => {
+            #[test]
+            fn $name() {
+                let sorted: Vec<String> = sort(accounts(), $sort_by, $descending)
+                    .into_iter()
+                    .map(|account| account.account_id)
+                    .collect();
+                assert_eq!(sorted, $expected);
+            }
+        };
+    }
+
+    fn accounts_with_emails() -> Vec<AccountFees> {
+        let mut statistics = accounts();
+        statistics.push(AccountFees::new("acct_d", "", "eur"));
+        statistics.push(AccountFees::new("acct_0", "b@example.com", "eur"));
+        statistics
+    }
+
+    #[test]
+    fn test_sort_by_email() {
+        let sorted: Vec<String> = sort(accounts_with_emails(), SortKey::Email, false)
+            .into_iter()
+            .map(|account| account.account_id)
+            .collect();
+        assert_eq!(sorted, ["acct_a", "acct_0", "acct_b", "acct_c", "acct_d"]);
+    }
+
+    #[test]
+    fn test_sort_by_email_desc() {
+        let sorted: Vec<String> = sort(accounts_with_emails(), SortKey::Email, true)
+            .into_iter()
+            .map(|account| account.account_id)
+            .collect();
+        assert_eq!(sorted, ["acct_c", "acct_0", "acct_b", "acct_a", "acct_d"]);
+    }
+
+    sort_test!(
+        test_sort_by_account,
+        SortKey::Account,
+        false,
+        ["acct_a", "acct_b", "acct_c"]
+    );
+    sort_test!(
+        test_sort_by_account_desc,
+        SortKey::Account,
+        true,
+        ["acct_c", "acct_b", "acct_a"]
+    );
+    sort_test!(
+        test_sort_by_fees,
+        SortKey::Fees,
+        false,
+        ["acct_a", "acct_b", "acct_c"]
+    );
+    sort_test!(
+        test_sort_by_fees_desc,
+        SortKey::Fees,
+        true,
+        ["acct_c", "acct_a", "acct_b"]
+    );
+    sort_test!(
+        test_sort_by_count,
+        SortKey::Count,
+        false,
+        ["acct_b", "acct_c", "acct_a"]
+    );
+    sort_test!(
+        test_sort_by_count_desc,
+        SortKey::Count,
+        true,
+        ["acct_a", "acct_b", "acct_c"]
+    );
+
+    macro_rules! top_test {
+        ($name:ident, $count:expr, $sort_by:expr, $expected:expr) => {
+            #[test]
+            fn $name() {
+                let top: Vec<String> = top(accounts(), $count, $sort_by)
+                    .into_iter()
+                    .map(|account| account.account_id)
+                    .collect();
+                assert_eq!(top, $expected);
+            }
+        };
+    }
+
+    top_test!(test_top_by_fees, 2, SortKey::Fees, ["acct_c", "acct_a"]);
+    top_test!(
+        test_top_by_account_ranks_by_fees,
+        1,
+        SortKey::Account,
+        ["acct_c"]
+    );
+    top_test!(test_top_by_count, 1, SortKey::Count, ["acct_a"]);
+    top_test!(
+        test_top_more_than_accounts,
+        10,
+        SortKey::Fees,
+        ["acct_c", "acct_a", "acct_b"]
+    );
+    top_test!(test_top_zero, 0, SortKey::Fees, Vec::<String>::new());
+
+    #[test]
+    fn test_rollup() {
+        let mut statistics = accounts();
+        let mut yen = AccountFees::new("acct_d", "d@example.com", "jpy");
+        yen.add_fee(500);
+        statistics.push(yen);
+
+        let rows: Vec<String> = rollup(statistics, 2)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                "acct_d,d@example.com,jpy,1,500",
+                "acct_c,c@example.com,eur,1,3.00",
+                "OTHERS,,eur,3,2.00",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rollup_more_than_accounts() {
+        let accounts: Vec<String> = rollup(accounts(), 10)
+            .into_iter()
+            .map(|account| account.account_id)
+            .collect();
+        assert_eq!(accounts, ["acct_c", "acct_a", "acct_b"]);
+    }
+
+    #[test]
+    fn test_parse_with_top() {
+        let mut temp_file = NamedTempFile::new().expect("should create temp file");
+        let out_temp_file = NamedTempFile::new().expect("should create out temp file");
+        writeln!(
+            temp_file,
+            "Amount,User ID,User Email\n\"5,00\",acct_123,user@example.com\n\"9,99\",acct_456,test@example.com\n\"1,00\",acct_789,other@example.com"
+        )
+        .expect("should write to temp file");
+
+        parse(
+            temp_file.path().to_path_buf(),
+            Some(out_temp_file.path().to_path_buf()),
+            &Options {
+                top: Some(2),
+                ..Options::default()
+            },
+        )
+        .expect("should parse");
+
+        let output = std::fs::read_to_string(out_temp_file.path()).expect("should read output");
+        assert_eq!(
+            output,
+            "account_id,email,currency,transaction_count,total_fees_eur\nacct_123,user@example.com,eur,1,5.00\nacct_456,test@example.com,eur,1,9.99\n"
+        );
+    }
+
+    #[test]
+    fn test_deltas() {
+        let fees = |account_id: &str, email: &str, currency: &str, fee: i64| {
+            let mut statistic = AccountFees::new(account_id, email, currency);
+            statistic.add_fee(fee);
+            ((account_id.to_string(), currency.to_string()), statistic)
+        };
+        let previous = HashMap::from([
+            fees("acct_a", "old@example.com", "eur", 100),
+            fees("acct_b", "b@example.com", "eur", 500),
+            fees("acct_c", "c@example.com", "eur", 200),
+            fees("acct_e", "e@example.com", "usd", 100),
+        ]);
+        let current = HashMap::from([
+            fees("acct_a", "a@example.com", "eur", 150),
+            fees("acct_c", "c@example.com", "eur", 200),
+            fees("acct_d", "d@example.com", "jpy", 50),
+            fees("acct_e", "e@example.com", "usd", 1100),
+        ]);
+
+        let rows: Vec<[String; 6]> = deltas(previous, current)
+            .iter()
+            .map(|delta| delta.record(None))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                ["acct_e", "e@example.com", "usd", "1.00", "11.00", "10.00"],
+                ["acct_b", "b@example.com", "eur", "5.00", "0.00", "-5.00"],
+                ["acct_a", "a@example.com", "eur", "1.00", "1.50", "0.50"],
+                ["acct_d", "d@example.com", "jpy", "0", "50", "50"],
+                ["acct_c", "c@example.com", "eur", "2.00", "2.00", "0.00"],
+            ]
+            .map(|row| row.map(ToString::to_string))
+        );
+    }
+
+    #[test]
+    fn test_diff_files() {
+        let mut previous = NamedTempFile::new().expect("should create temp file");
+        writeln!(
+            previous,
+            "Amount,User ID,User Email\n\"1,00\",acct_123,user@example.com\n\"2,00\",acct_456,test@example.com"
+        )
+        .expect("should write to temp file");
+        let mut current = NamedTempFile::new().expect("should create temp file");
+        writeln!(
+            current,
+            "Amount,User ID,User Email\n\"1,25\",acct_123,user@example.com\n\"0,40\",acct_789,other@example.com"
+        )
+        .expect("should write to temp file");
+        let out_temp_file = NamedTempFile::new().expect("should create out temp file");
+
+        diff_files(
+            &[current.path().to_path_buf()],
+            previous.path(),
+            Some(out_temp_file.path().to_path_buf()),
+            &Options::default(),
+        )
+        .expect("should diff");
+
+        let output = std::fs::read_to_string(out_temp_file.path()).expect("should read output");
+        assert_eq!(
+            output,
+            "account_id,email,currency,fees_prev,fees_now,delta\nacct_456,test@example.com,eur,2.00,0.00,-2.00\nacct_789,other@example.com,eur,0.00,0.40,0.40\nacct_123,user@example.com,eur,1.00,1.25,0.25\n"
+        );
+
+        for (options, option) in [
+            (
+                Options {
+                    format: OutputFormat::Json,
+                    ..Options::default()
+                },
+                "--format json",
+            ),
+            (
+                Options {
+                    top: Some(1),
+                    ..Options::default()
+                },
+                "--top",
+            ),
+            (
+                Options {
+                    min_fees: Some(100),
+                    ..Options::default()
+                },
+                "--min-fees",
+            ),
+        ] {
+            let error = diff_files(
+                &[current.path().to_path_buf()],
+                previous.path(),
+                Some(out_temp_file.path().to_path_buf()),
+                &options,
+            )
+            .expect_err("should be error");
+            assert!(
+                matches!(error, FeesError::IncompatibleOption("--diff", rejected) if rejected == option)
+            );
+        }
+
+        let error = diff_files(
+            &[current.path().to_path_buf()],
+            std::path::Path::new("/tmp/non_existent_previous_fees_12345.csv"),
+            Some(out_temp_file.path().to_path_buf()),
+            &Options::default(),
+        )
+        .expect_err("should be error");
+        assert!(matches!(error, FeesError::FileNotFound(_)));
+    }
+
+    #[test]
+    fn test_parse_with_rollup_after() {
+        let mut temp_file = NamedTempFile::new().expect("should create temp file");
+        let out_temp_file = NamedTempFile::new().expect("should create out temp file");
+        writeln!(
+            temp_file,
+            "Amount,User ID,User Email\n\"5,00\",acct_123,user@example.com\n\"9,99\",acct_456,test@example.com\n\"1,00\",acct_789,other@example.com\n\"0,50\",acct_789,other@example.com"
+        )
+        .expect("should write to temp file");
+
+        parse(
+            temp_file.path().to_path_buf(),
+            Some(out_temp_file.path().to_path_buf()),
+            &Options {
+                rollup_after: Some(1),
+                totals: true,
+                ..Options::default()
+            },
+        )
+        .expect("should parse");
+
+        let output = std::fs::read_to_string(out_temp_file.path()).expect("should read output");
+        assert_eq!(
+            output,
+            "account_id,email,currency,transaction_count,total_fees_eur\nacct_456,test@example.com,eur,1,9.99\nOTHERS,,eur,3,6.50\nTOTAL,,eur,4,16.49\n"
+        );
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut statistics = accounts();
+        statistics.push(AccountFees::new("acct_a", "a@example.com", "eur"));
+        statistics[3].add_fee(5);
+        let summary = Summary::new(6, &statistics, "eur");
+
+        assert_eq!(summary.rows, 6);
+        assert_eq!(summary.accounts, 3);
+        let [eur] = summary.currencies.as_slice() else {
+            panic!("should have a single currency");
+        };
+        assert_eq!(eur.currency, "eur");
+        assert_eq!(eur.accounts, 3);
+        assert_eq!(eur.total_fees, 505);
+        assert_eq!(eur.mean_fees, 168);
+        assert_eq!(eur.max_fees, Some(("acct_c".to_string(), 300)));
+        assert_eq!(eur.min_fees, Some(("acct_b".to_string(), 100)));
+        assert_eq!(
+            eur.percentiles,
+            Some(Percentiles {
+                p50: 105,
+                p90: 261,
+                p99: 296
+            })
+        );
+        assert_eq!(
+            summary.to_string(),
+            "rows: 6\naccounts: 3\ntotal fees: 5.05\nmean fees per account: 1.68\nmax account fees: 3.00 (acct_c)\naccount fees percentiles: p50 1.05, p90 2.61, p99 2.96\n"
+        );
+    }
+
+    #[test]
+    fn test_summary_mixed_currencies() {
+        let mut statistics = accounts();
+        statistics.push(AccountFees::new("acct_a", "a@example.com", "jpy"));
+        statistics[3].add_fee(1000);
+        statistics.push(AccountFees::new("acct_d", "d@example.com", "usd"));
+        statistics[4].add_fee(5);
+        let summary = Summary::new(7, &statistics, "eur");
+
+        assert!(summary.is_mixed_currency());
+        assert_eq!(summary.accounts, 4);
+        let totals: Vec<(&str, usize, i64)> = summary
+            .currencies
+            .iter()
+            .map(|currency| {
+                (
+                    currency.currency.as_str(),
+                    currency.accounts,
+                    currency.total_fees,
+                )
+            })
+            .collect();
+        assert_eq!(totals, [("eur", 3, 500), ("jpy", 1, 1000), ("usd", 1, 5)]);
+        assert_eq!(
+            summary.to_string(),
+            "rows: 7\naccounts: 4\n\
+             EUR accounts: 3\nEUR total fees: 5.00\nEUR mean fees per account: 1.67\nEUR max account fees: 3.00 (acct_c)\nEUR account fees percentiles: p50 1.00, p90 2.60, p99 2.96\n\
+             JPY accounts: 1\nJPY total fees: 1000\nJPY mean fees per account: 1000\nJPY max account fees: 1000 (acct_a)\nJPY account fees percentiles: p50 1000, p90 1000, p99 1000\n\
+             USD accounts: 1\nUSD total fees: 0.05\nUSD mean fees per account: 0.05\nUSD max account fees: 0.05 (acct_d)\nUSD account fees percentiles: p50 0.05, p90 0.05, p99 0.05\n"
+        );
+
+        let mut output = Vec::new();
+        summary.write_json(&mut output).expect("should write json");
+        let json: serde_json::Value =
+            serde_json::from_slice(&output).expect("should be valid json");
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "rows": 7,
+                "accounts": 4,
+                "currencies": {
+                    "eur": {"accounts": 3, "total_fees": 5.0, "mean": 1.67, "max": 3.0, "min": 1.0},
+                    "jpy": {"accounts": 1, "total_fees": 1000.0, "mean": 1000.0, "max": 1000.0, "min": 1000.0},
+                    "usd": {"accounts": 1, "total_fees": 0.05, "mean": 0.05, "max": 0.05, "min": 0.05}
+                }
+            })
+        );
+
+        let summary = Summary::new(6, &accounts(), "eur");
+        assert!(!summary.is_mixed_currency());
+        assert!(!summary.to_string().contains("EUR"));
+    }
+
+    #[test]
+    fn test_summary_single_currency_exponent() {
+        let mut statistics = vec![AccountFees::new("acct_a", "a@example.com", "jpy")];
+        statistics[0].add_fee(1200);
+        let summary = Summary::new(1, &statistics, "eur");
+
+        assert_eq!(
+            summary.to_string(),
+            "rows: 1\naccounts: 1\ntotal fees: 1200\nmean fees per account: 1200\nmax account fees: 1200 (acct_a)\naccount fees percentiles: p50 1200, p90 1200, p99 1200\n"
+        );
+        let mut output = Vec::new();
+        summary.write_json(&mut output).expect("should write json");
+        assert_eq!(
+            String::from_utf8(output).expect("should be utf-8"),
+            "{\"rows\":1,\"accounts\":1,\"total_fees_jpy\":1200.0,\"mean\":1200.0,\"max\":1200.0,\"min\":1200.0}\n"
+        );
+    }
+
+    #[test]
+    fn test_watch_stdin() {
+        let error =
+            watch(&[PathBuf::from("-")], None, &Options::default()).expect_err("should be error");
+        assert!(matches!(error, FeesError::WatchStdin));
+    }
+
+    #[test]
+    fn test_wait_for_change() {
+        use notify::event::{AccessKind, AccessMode, CreateKind, EventKind, ModifyKind};
+
+        let files = HashSet::from([PathBuf::from("/tmp/fees.csv")]);
+        let event =
+            |kind: EventKind, path: &str| Ok(notify::Event::new(kind).add_path(path.into()));
+
+        let (sender, events) = std::sync::mpsc::channel();
+        for sent in [
+            event(
+                EventKind::Access(AccessKind::Open(AccessMode::Read)),
+                "/tmp/fees.csv",
+            ),
+            event(EventKind::Modify(ModifyKind::Any), "/tmp/fees_out.csv"),
+            event(EventKind::Modify(ModifyKind::Any), "/tmp/fees.csv"),
+            event(EventKind::Create(CreateKind::File), "/tmp/fees.csv"),
+        ] {
+            sender.send(sent).expect("should send");
+        }
+        assert!(wait_for_change(&events, &files, Duration::from_millis(10)));
+        assert!(events.try_recv().is_err());
+
+        sender
+            .send(event(
+                EventKind::Access(AccessKind::Close(AccessMode::Write)),
+                "/tmp/fees.csv",
+            ))
+            .expect("should send");
+        assert!(wait_for_change(&events, &files, Duration::from_millis(10)));
+
+        sender
+            .send(event(EventKind::Access(AccessKind::Read), "/tmp/fees.csv"))
+            .expect("should send");
+        drop(sender);
+        assert!(!wait_for_change(&events, &files, Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn test_summary_unique_emails() {
+        let mut statistics = accounts();
+        statistics.push(AccountFees::new("acct_d", "a@example.com", "eur"));
+        statistics.push(AccountFees::new("acct_e", "", "eur"));
+        statistics.push(AccountFees::new("acct_a", "a@example.com", "usd"));
+        assert_eq!(unique_emails(&statistics), 3);
+
+        let mut summary = Summary::new(6, &statistics, "eur");
+        assert_eq!(summary.emails, None);
+        assert!(!summary.to_string().contains("emails"));
+
+        summary.emails = Some(unique_emails(&statistics));
+        assert!(
+            summary
+                .to_string()
+                .starts_with("rows: 6\naccounts: 5\nemails: 3\n")
+        );
+        let mut output = Vec::new();
+        summary.write_json(&mut output).expect("should write json");
+        let json: serde_json::Value =
+            serde_json::from_slice(&output).expect("should be valid json");
+        assert_eq!(json["accounts"], 5);
+        assert_eq!(json["emails"], 3);
+    }
+
+    #[test]
+    fn test_summary_without_accounts() {
+        assert_eq!(
+            Summary::new(0, &[], "eur").to_string(),
+            "rows: 0\naccounts: 0\ntotal fees: 0.00\nmean fees per account: 0.00\nmax account fees: -\naccount fees percentiles: -\n"
+        );
+    }
+
+    #[test]
+    fn test_summary_json() {
+        let mut statistics = accounts();
+        statistics.push(AccountFees::new("acct_a", "a@example.com", "eur"));
+        statistics[3].add_fee(5);
+        let mut output = Vec::new();
+        Summary::new(6, &statistics, "eur")
+            .write_json(&mut output)
+            .expect("should write json");
+
+        assert_eq!(
+            String::from_utf8(output).expect("should be utf-8"),
+            "{\"rows\":6,\"accounts\":3,\"total_fees_eur\":5.05,\"mean\":1.68,\"max\":3.0,\"min\":1.0}\n"
+        );
+
+        let mut output = Vec::new();
+        Summary::new(0, &[], "USD")
+            .write_json(&mut output)
+            .expect("should write json");
+        let json: serde_json::Value =
+            serde_json::from_slice(&output).expect("should be valid json");
+        assert_eq!(
+            json,
+            serde_json::json!({"rows": 0, "accounts": 0, "total_fees_usd": 0.0, "mean": 0.0, "max": null, "min": null})
+        );
+    }
+
+    #[test]
+    fn test_percentile() {
+        assert_eq!(percentile(&[1, 2, 3, 4], 50), 3);
+        assert_eq!(percentile(&[10, 20, 30, 40], 50), 25);
+        assert_eq!(percentile(&[42], 99), 42);
+        assert_eq!(percentile(&[0, 100], 90), 90);
+        assert_eq!(percentile(&[-300, -100], 50), -200);
+        assert_eq!(Percentiles::new(&[]), None);
+    }
+
+    #[test]
+    fn test_parse_summary_skips_output() {
+        let mut temp_file = NamedTempFile::new().expect("should create temp file");
+        writeln!(
+            temp_file,
+            "Amount,User ID,User Email\n\"0,25\",acct_123,user@example.com"
+        )
+        .expect("should write to temp file");
+        let directory = tempfile::tempdir().expect("should create temp dir");
+        let output = directory.path().join("fees_out.csv");
+
+        parse(
+            temp_file.path().to_path_buf(),
+            Some(output.clone()),
+            &Options {
+                summary: true,
+                ..Options::default()
+            },
+        )
+        .expect("should parse");
+        assert!(!output.exists());
+    }
+
+    #[test]
+    fn test_sort_ties_by_currency() {
+        let sorted: Vec<String> = sort(
+            [
+                AccountFees::new("acct_a", "a@example.com", "usd"),
+                AccountFees::new("acct_a", "a@example.com", "eur"),
+            ],
+            SortKey::Account,
+            false,
+        )
+        .into_iter()
+        .map(|account| account.currency)
+        .collect();
+        assert_eq!(sorted, ["eur", "usd"]);
+    }
+
+    #[test]
+    fn test_to_record() {
+        let mut fees = AccountFees::new("acct_123", "user@example.com", "eur");
+        fees.add_fee(125);
+        assert_eq!(
+            fees.to_record(None, "usd"),
+            ["acct_123", "user@example.com", "eur", "1", "1.25"]
+        );
+        assert_eq!(
+            fees.to_record(Some(0), "eur"),
+            ["acct_123", "user@example.com", "eur", "1", "1"]
+        );
+        assert_eq!(fees.to_string(), fees.to_record(None, "eur").join(","));
+
+        let mut yen = AccountFees::new("acct_456", "", "jpy");
+        yen.add_fee(500);
+        assert_eq!(
+            yen.to_record(Some(2), "eur"),
+            ["acct_456", "", "jpy", "1", "500.00"]
+        );
+    }
+
+    #[test]
+    fn test_totals() {
+        let totals = AccountFees::totals(&accounts());
+
+        assert_eq!(totals.len(), 1);
+        let total = &totals[0];
+        assert_eq!(total.account_id, "TOTAL");
+        assert_eq!(total.email, "");
+        assert_eq!(total.currency, "eur");
+        assert_eq!(total.transaction_count, 4);
+        assert_eq!(total.total_fees, 500);
+        assert_eq!(total.to_string(), "TOTAL,,eur,4,5.00");
+    }
+
+    #[test]
+    fn test_totals_per_currency() {
+        let mut yen = AccountFees::new("acct_a", "a@example.com", "jpy");
+        yen.add_fee(500);
+        let mut accounts = accounts();
+        accounts.push(yen);
+
+        let totals: Vec<String> = AccountFees::totals(&accounts)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(totals, ["TOTAL,,eur,4,5.00", "TOTAL,,jpy,1,500"]);
+    }
+
+    #[test]
+    fn test_totals_without_accounts() {
+        assert!(AccountFees::totals(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_max_fee() {
+        let mut fees = AccountFees::new("acct_123", "user@example.com", "eur");
+        assert_eq!(fees.max_fee, 0);
+
+        fees.add_fee(-50);
+        assert_eq!(fees.max_fee, -50);
+        fees.add_fee(25);
+        assert_eq!(fees.max_fee, 25);
+        fees.add_fee(300);
+        fees.add_fee(100);
+        assert_eq!(fees.max_fee, 300);
+        assert_eq!(fees.formatted_max_fee(), "3.00");
+
+        let mut other = AccountFees::new("acct_123", "user@example.com", "eur");
+        fees.merge(&other);
+        assert_eq!(fees.max_fee, 300);
+        other.add_fee(450);
+        fees.merge(&other);
+        assert_eq!(fees.max_fee, 450);
+        assert_eq!(AccountFees::totals(&accounts())[0].max_fee, 300);
+    }
+
+    #[test]
+    fn test_merge_statistics() {
+        let statistics = |accounts: &[(&str, &str, i64)]| {
+            let mut statistics = HashMap::new();
+            for &(account_id, email, fee) in accounts {
+                let mut fees = AccountFees::new(account_id, email, "eur");
+                fees.add_fee(fee);
+                statistics.insert((account_id.to_string(), "eur".to_string()), fees);
+            }
+            statistics
+        };
+        let key = |account_id: &str| (account_id.to_string(), "eur".to_string());
+
+        let mut merged = statistics(&[("acct_123", "first@example.com", 100)]);
+        merge(
+            &mut merged,
+            statistics(&[
+                ("acct_123", "second@example.com", 50),
+                ("acct_456", "other@example.com", 25),
+            ]),
+        );
+
+        assert_eq!(merged.len(), 2);
+        let overlapping = &merged[&key("acct_123")];
+        assert_eq!(overlapping.transaction_count, 2);
+        assert_eq!(overlapping.total_fees, 150);
+        assert_eq!(overlapping.email, "first@example.com");
+        let disjoint = &merged[&key("acct_456")];
+        assert_eq!(disjoint.transaction_count, 1);
+        assert_eq!(disjoint.total_fees, 25);
+        assert_eq!(disjoint.email, "other@example.com");
+
+        merge(&mut merged, HashMap::new());
+        assert_eq!(merged.len(), 2);
+    }
+
+    #[test]
+    fn test_average_fee() {
+        let mut fees = AccountFees::new("acct_123", "user@example.com", "eur");
+        assert_eq!(fees.average_fee(), 0);
+
+        fees.total_fees = 100;
+        fees.transaction_count = 3;
+        assert_eq!(fees.average_fee(), 33);
+        assert_eq!(
+            amount_serde::format(fees.average_fee(), amount_serde::DEFAULT_EXPONENT),
+            "0.33"
+        );
+
+        fees.total_fees = 200;
+        assert_eq!(fees.average_fee(), 67);
+        fees.total_fees = 5;
+        fees.transaction_count = 2;
+        assert_eq!(fees.average_fee(), 3);
+        fees.total_fees = -5;
+        assert_eq!(fees.average_fee(), -3);
+        fees.total_fees = i64::MAX;
+        fees.transaction_count = 1;
+        assert_eq!(fees.average_fee(), i64::MAX);
+    }
+
+    #[test]
+    fn test_parse_with_average_and_max_fee() {
+        let mut temp_file = NamedTempFile::new().expect("should create temp file");
+        let out_temp_file = NamedTempFile::new().expect("should create out temp file");
+        writeln!(
+            temp_file,
+            "Amount,User ID,User Email\n\"0,10\",acct_123,user@example.com\n\"0,50\",acct_123,user@example.com\n\"0,40\",acct_123,user@example.com"
+        )
+        .expect("should write to temp file");
+
+        parse(
+            temp_file.path().to_path_buf(),
+            Some(out_temp_file.path().to_path_buf()),
+            &Options {
+                format: OutputFormat::Json,
+                extra_columns: ExtraColumns {
+                    max_fee: true,
+                    average_fee: true,
+                    net_fees: false,
+                    fee_range: false,
+                },
+                ..Options::default()
+            },
+        )
+        .expect("should parse");
+
+        let output = std::fs::read_to_string(out_temp_file.path()).expect("should read output");
+        let json: serde_json::Value = serde_json::from_str(&output).expect("should be valid json");
+        assert_eq!(json[0]["max_fee_eur"], "0.50");
+        assert_eq!(json[0]["average_fee_eur"], "0.33");
+    }
+
+    #[test]
+    fn test_parse_with_max_fee() {
+        let mut temp_file = NamedTempFile::new().expect("should create temp file");
+        let out_temp_file = NamedTempFile::new().expect("should create out temp file");
+        writeln!(
+            temp_file,
+            "Amount,User ID,User Email\n\"0,10\",acct_123,user@example.com\n\"2,50\",acct_123,user@example.com\n\"0,05\",acct_123,user@example.com"
+        )
+        .expect("should write to temp file");
+
+        parse(
+            temp_file.path().to_path_buf(),
+            Some(out_temp_file.path().to_path_buf()),
+            &Options {
+                extra_columns: ExtraColumns {
+                    max_fee: true,
+                    ..ExtraColumns::default()
+                },
+                ..Options::default()
+            },
+        )
+        .expect("should parse");
+
+        let output = std::fs::read_to_string(out_temp_file.path()).expect("should read output");
+        assert_eq!(
+            output,
+            "account_id,email,currency,transaction_count,total_fees_eur,max_fee_eur\nacct_123,user@example.com,eur,3,2.65,2.50\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_with_selected_columns() {
+        let mut temp_file = NamedTempFile::new().expect("should create temp file");
+        let out_temp_file = NamedTempFile::new().expect("should create out temp file");
+        writeln!(
+            temp_file,
+            "Amount,User ID,User Email\n\"0,10\",acct_123,user@example.com\n\"2,50\",acct_123,user@example.com"
+        )
+        .expect("should write to temp file");
+
+        parse(
+            temp_file.path().to_path_buf(),
+            Some(out_temp_file.path().to_path_buf()),
+            &Options {
+                selected_columns: Some(vec![
+                    "total_fees_eur".to_string(),
+                    "account_id".to_string(),
+                    "max_fee_eur".to_string(),
+                ]),
+                ..Options::default()
+            },
+        )
+        .expect("should parse");
+
+        let output = std::fs::read_to_string(out_temp_file.path()).expect("should read output");
+        assert_eq!(
+            output,
+            "total_fees_eur,account_id,max_fee_eur\n2.60,acct_123,2.50\n"
+        );
+    }
+
+    #[test]
+    fn test_output_columns() {
+        let options = Options {
+            extra_columns: ExtraColumns {
+                average_fee: true,
+                ..ExtraColumns::default()
+            },
+            ..Options::default()
+        };
+        assert_eq!(
+            options.output_columns().expect("should be valid"),
+            [
+                "account_id",
+                "email",
+                "currency",
+                "transaction_count",
+                "total_fees_eur",
+                "average_fee_eur"
+            ]
+        );
+
+        let options = Options {
+            selected_columns: Some(vec!["account_id".to_string(), "total_fees".to_string()]),
+            ..Options::default()
+        };
+        let error = options.output_columns().expect_err("should be error");
+        assert!(matches!(
+            &error,
+            FeesError::UnknownOutputColumn { name, valid }
+                if name == "total_fees" && valid.starts_with("account_id, email, currency")
+        ));
+
+        let options = Options {
+            group_by: GroupBy::Month,
+            selected_columns: Some(vec!["account_id".to_string()]),
+            ..Options::default()
+        };
+        assert!(options.output_columns().is_err());
+    }
+
+    #[test]
+    fn test_field() {
+        let mut fees = AccountFees::new("acct_123", "user@example.com", "eur");
+        fees.add_fee(150);
+        fees.add_fee(50);
+
+        let field = |name: &str| fees.field(name, "eur", GroupBy::Account);
+        assert_eq!(field("email"), Some(Field::Text("user@example.com")));
+        assert_eq!(field("transaction_count"), Some(Field::Count(2)));
+        assert_eq!(field("total_fees_eur"), Some(Field::Amount(200)));
+        assert_eq!(field("max_fee_eur"), Some(Field::Amount(150)));
+        assert_eq!(field("average_fee_eur"), Some(Field::Amount(100)));
+        assert_eq!(field("total_fees_usd"), None);
+        assert_eq!(field("month"), None);
+        assert_eq!(
+            AccountFees::for_month("2025-12", "eur").field("month", "eur", GroupBy::Month),
+            Some(Field::Text("2025-12"))
+        );
+        assert_eq!(
+            AccountFees::new("TOTAL", "", "eur").field("month", "eur", GroupBy::Month),
+            Some(Field::Text("TOTAL"))
+        );
+        assert_eq!(
+            AccountFees::column_names("eur", GroupBy::Account)
+                .iter()
+                .filter(|name| field(name).is_none())
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_parse_with_totals() {
+        let mut temp_file = NamedTempFile::new().expect("should create temp file");
+        let out_temp_file = NamedTempFile::new().expect("should create out temp file");
+        writeln!(
+            temp_file,
+            "Amount,User ID,User Email\n\"0,10\",acct_123,user@example.com\n\"0,20\",acct_456,test@example.com\n\"0,05\",acct_123,user@example.com"
+        )
+        .expect("should write to temp file");
+
+        parse(
+            temp_file.path().to_path_buf(),
+            Some(out_temp_file.path().to_path_buf()),
+            &Options {
+                totals: true,
+                ..Options::default()
+            },
+        )
+        .expect("should parse");
+
+        let output = std::fs::read_to_string(out_temp_file.path()).expect("should read output");
+        assert_eq!(
+            output,
+            "account_id,email,currency,transaction_count,total_fees_eur\nacct_123,user@example.com,eur,2,0.15\nacct_456,test@example.com,eur,1,0.20\nTOTAL,,eur,3,0.35\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_with_min_fees() {
+        let mut temp_file = NamedTempFile::new().expect("should create temp file");
+        let out_temp_file = NamedTempFile::new().expect("should create out temp file");
+        writeln!(
+            temp_file,
+            "Amount,User ID,User Email\n\"5,00\",acct_123,user@example.com\n\"9,99\",acct_456,test@example.com\n\"5,00\",acct_123,user@example.com"
+        )
+        .expect("should write to temp file");
+
+        parse(
+            temp_file.path().to_path_buf(),
+            Some(out_temp_file.path().to_path_buf()),
+            &Options {
+                min_fees: Some(1000),
+                ..Options::default()
+            },
+        )
+        .expect("should parse");
+
+        let output = std::fs::read_to_string(out_temp_file.path()).expect("should read output");
+        assert_eq!(
+            output,
+            "account_id,email,currency,transaction_count,total_fees_eur\nacct_123,user@example.com,eur,2,10.00\n"
+        );
+    }
+
+    #[test]
+    fn test_is_above_min_fees() {
+        let account = |currency: &str, total_fees: i64| AccountFees {
+            total_fees,
+            ..AccountFees::new("acct_123", "user@example.com", currency)
+        };
+
+        assert!(is_above_min_fees(&account("eur", 1000), 1000));
+        assert!(!is_above_min_fees(&account("eur", 999), 1000));
+        assert!(is_above_min_fees(&account("jpy", 10), 1000));
+        assert!(!is_above_min_fees(&account("jpy", 9), 1000));
+        assert!(is_above_min_fees(&account("kwd", 10_000), 1000));
+        assert!(!is_above_min_fees(&account("kwd", 9_999), 1000));
+        assert!(is_above_min_fees(&account("eur", i64::MAX), i64::MAX));
+    }
+
+    #[test]
+    fn test_aggregate_skip_errors() {
+        let data = "Amount,User ID,User Email\n\"0,25\",acct_123,user@example.com\ninvalid,acct_123,user@example.com\n\"1,50\",acct_456,test@example.com\n\"abc\",acct_456,test@example.com";
+        let aggregation = aggregate_with(
+            csv::Reader::from_reader(data.as_bytes()),
+            &Options {
+                skip_errors: true,
+                ..Options::default()
+            },
+        )
+        .expect("should aggregate");
+
+        assert_eq!(aggregation.rows, 4);
+        assert_eq!(aggregation.statistics.len(), 2);
+        assert_eq!(
+            aggregation.statistics[&key("acct_123", "eur")].total_fees,
+            25
+        );
+        assert_eq!(
+            aggregation.statistics[&key("acct_456", "eur")].total_fees,
+            150
+        );
+        let lines: Vec<u64> = aggregation.skipped.iter().map(|row| row.line).collect();
+        assert_eq!(lines, [3, 5]);
+    }
+
+    /// Fees CSV with rows of several accounts and currencies in a pseudo-random order.
+    fn shuffled_fees(rows: usize) -> String {
+        let mut lines = vec!["Amount,Currency,User ID,User Email".to_string()];
+        let mut seed: u64 = 42;
+        for _ in 0..rows {
+            seed = seed
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            let account = (seed >> 33) % 50;
+            let currency =
+                ["eur", "usd", "jpy"][usize::try_from((seed >> 20) % 3).expect("should fit")];
+            let amount = (seed >> 40) % 1000;
+            lines.push(format!(
+                "\"{},{:02}\",{currency},acct_{account},user{account}@example.com",
+                amount / 100,
+                amount % 100
+            ));
+        }
+        lines.join("\n") + "\n"
+    }
+
+    #[test]
+    fn test_group_thousands() {
+        assert_eq!(group_thousands(0), "0");
+        assert_eq!(group_thousands(999), "999");
+        assert_eq!(group_thousands(1000), "1,000");
+        assert_eq!(group_thousands(123_456), "123,456");
+        assert_eq!(group_thousands(1_000_000), "1,000,000");
+    }
+
+    #[test]
+    fn test_aggregate_with_progress() {
+        let data = shuffled_fees(PROGRESS_INTERVAL + 1);
+        let options = Options {
+            progress: true,
+            ..Options::default()
+        };
+        let aggregation = aggregate_with(csv::Reader::from_reader(data.as_bytes()), &options)
+            .expect("should aggregate");
+
+        assert_eq!(aggregation.rows, PROGRESS_INTERVAL + 1);
+    }
+
+    #[test]
+    fn test_aggregate_parallel_matches_serial() {
+        let data = shuffled_fees(3 * PARALLEL_CHUNK_SIZE + 123);
+        let serial =
+            aggregate(csv::Reader::from_reader(data.as_bytes())).expect("should aggregate");
+        let parallel = aggregate_with(
+            csv::Reader::from_reader(data.as_bytes()),
+            &Options {
+                jobs: NonZeroUsize::new(4).expect("should be non zero"),
+                ..Options::default()
+            },
+        )
+        .expect("should aggregate");
+
+        assert_eq!(parallel.rows, 3 * PARALLEL_CHUNK_SIZE + 123);
+        assert_eq!(parallel.statistics, serial);
+    }
+
+    #[test]
+    fn test_aggregate_parallel_skip_errors() {
+        let mut data = shuffled_fees(PARALLEL_CHUNK_SIZE + 10);
+        data.push_str("invalid,eur,acct_1,user1@example.com\n");
+        let options = Options {
+            skip_errors: true,
+            ..Options::default()
+        };
+        let serial = aggregate_with(csv::Reader::from_reader(data.as_bytes()), &options)
+            .expect("should aggregate");
+        let parallel = aggregate_with(
+            csv::Reader::from_reader(data.as_bytes()),
+            &Options {
+                jobs: NonZeroUsize::new(3).expect("should be non zero"),
+                ..options
+            },
+        )
+        .expect("should aggregate");
+
+        assert_eq!(parallel.statistics, serial.statistics);
+        let lines: Vec<u64> = parallel.skipped.iter().map(|row| row.line).collect();
+        assert_eq!(
+            lines,
+            [u64::try_from(PARALLEL_CHUNK_SIZE).expect("should fit") + 12]
+        );
+    }
+
+    #[test]
+    fn test_aggregate_parallel_invalid_entry() {
+        let mut data = shuffled_fees(100);
+        data.push_str("invalid,eur,acct_1,user1@example.com\n");
+        let result = aggregate_with(
+            csv::Reader::from_reader(data.as_bytes()),
+            &Options {
+                jobs: NonZeroUsize::new(2).expect("should be non zero"),
+                ..Options::default()
+            },
+        );
+        assert!(matches!(result, Err(FeesError::Csv(_))));
+    }
+
+    #[test]
+    fn test_aggregate_email_changes() {
+        let data = "Amount,User ID,User Email\n\"0,25\",acct_123,old@example.com\n\"0,50\",acct_123,new@example.com\n\"0,75\",acct_123,new@example.com\n\"1,00\",acct_456,test@example.com";
+        let aggregation = aggregate_with(
+            csv::Reader::from_reader(data.as_bytes()),
+            &Options {
+                warn_email_changes: true,
+                ..Options::default()
+            },
+        )
+        .expect("should aggregate");
+
+        let account = &aggregation.statistics[&key("acct_123", "eur")];
+        assert_eq!(account.email, "new@example.com");
+        assert_eq!(account.transaction_count, 3);
+        assert_eq!(account.total_fees, 150);
+        assert_eq!(
+            aggregation.email_changes,
+            [EmailChange {
+                account_id: "acct_123".to_string(),
+                currency: "eur".to_string(),
+                previous: "old@example.com".to_string(),
+                email: "new@example.com".to_string(),
+                line: Some(3),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_merge_email_changes_per_currency() {
+        let aggregate = |data: &str| {
+            aggregate_with(
+                csv::Reader::from_reader(data.as_bytes()),
+                &Options {
+                    warn_email_changes: true,
+                    ..Options::default()
+                },
+            )
+            .expect("should aggregate")
+        };
+        let mut aggregation = aggregate(
+            "Amount,Currency,User ID,User Email\n\"0,25\",eur,acct_123,eur@example.com\n\"0,25\",usd,acct_123,old@example.com",
+        );
+        let other = aggregate(
+            "Amount,Currency,User ID,User Email\n\"0,25\",usd,acct_123,old@example.com\n\"0,25\",usd,acct_123,new@example.com\n\"0,25\",eur,acct_123,eur@example.com",
+        );
+
+        aggregation.merge_with(other, true, false);
+        let changes: Vec<(&str, &str, &str)> = aggregation
+            .email_changes
+            .iter()
+            .map(|change| {
+                (
+                    change.currency.as_str(),
+                    change.previous.as_str(),
+                    change.email.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(changes, [("usd", "old@example.com", "new@example.com")]);
+    }
+
+    #[test]
+    fn test_aggregate_email_changes_untracked_keeps_first_email() {
+        let data = "Amount,User ID,User Email\n\"0,25\",acct_123,old@example.com\n\"0,50\",acct_123,new@example.com";
+        let aggregation = aggregate_with(
+            csv::Reader::from_reader(data.as_bytes()),
+            &Options::default(),
+        )
+        .expect("should aggregate");
+
+        let account = &aggregation.statistics[&key("acct_123", "eur")];
+        assert_eq!(account.email, "old@example.com");
+        assert_eq!(account.total_fees, 75);
+        assert!(aggregation.email_changes.is_empty());
+    }
+
+    #[test]
+    fn test_aggregate_normalized_email_changes() {
+        let data = "Amount,User ID,User Email\n\"0,25\",acct_123,User@Example.com\n\"0,50\",acct_123,user@example.com\n\"0,75\",acct_123,new@example.com";
+        for jobs in [
+            NonZeroUsize::MIN,
+            NonZeroUsize::new(2).expect("should be non zero"),
+        ] {
+            let aggregation = aggregate_with(
+                csv::Reader::from_reader(data.as_bytes()),
+                &Options {
+                    warn_email_changes: true,
+                    normalize_email: true,
+                    jobs,
+                    ..Options::default()
+                },
+            )
+            .expect("should aggregate");
+
+            assert_eq!(aggregation.email_changes.len(), 1);
+            assert_eq!(aggregation.email_changes[0].previous, "User@Example.com");
+            assert_eq!(
+                aggregation.statistics[&key("acct_123", "eur")].email,
+                "new@example.com"
+            );
+        }
+    }
+
+    #[test]
+    fn test_aggregate_group_by_normalized_email() {
+        let data = "Amount,User ID,User Email\n\"0,25\",acct_123,User@Example.com\n\"0,50\",acct_456,user@example.com\n\"0,75\",acct_789,test@example.com";
+        let aggregate = |normalize_email| {
+            aggregate_with(
+                csv::Reader::from_reader(data.as_bytes()),
+                &Options {
+                    group_by: GroupBy::Email,
+                    normalize_email,
+                    ..Options::default()
+                },
+            )
+            .expect("should aggregate")
+            .statistics
+        };
+
+        let statistics = aggregate(true);
+        assert_eq!(statistics.len(), 2);
+        let merged = &statistics[&key("user@example.com", "eur")];
+        assert_eq!(merged.email, "User@Example.com");
+        assert_eq!(merged.transaction_count, 2);
+        assert_eq!(merged.total_fees, 75);
+
+        let statistics = aggregate(false);
+        assert_eq!(statistics.len(), 3);
+        assert_eq!(statistics[&key("User@Example.com", "eur")].total_fees, 25);
+        assert_eq!(statistics[&key("user@example.com", "eur")].total_fees, 50);
+    }
+
+    #[test]
+    fn test_aggregate_parallel_email_changes() {
+        let rows = PARALLEL_CHUNK_SIZE + 1;
+        let mut lines = vec!["Amount,User ID,User Email".to_string()];
+        lines.extend((0..rows).map(|row| {
+            let email = if row < PARALLEL_CHUNK_SIZE {
+                "old"
+            } else {
+                "new"
+            };
+            format!("\"0,01\",acct_123,{email}@example.com")
+        }));
+        let data = lines.join("\n");
+
+        let aggregation = aggregate_with(
+            csv::Reader::from_reader(data.as_bytes()),
+            &Options {
+                warn_email_changes: true,
+                jobs: NonZeroUsize::new(2).expect("should be non zero"),
+                ..Options::default()
+            },
+        )
+        .expect("should aggregate");
+
+        let account = &aggregation.statistics[&key("acct_123", "eur")];
+        assert_eq!(account.email, "new@example.com");
+        assert_eq!(account.transaction_count, 10_001);
+        let changes: Vec<(&str, &str)> = aggregation
+            .email_changes
+            .iter()
+            .map(|change| (change.previous.as_str(), change.email.as_str()))
+            .collect();
+        assert_eq!(changes, [("old@example.com", "new@example.com")]);
+    }
+
+    fees_test!(
+        test_parse_skip_errors_partial_failure,
+        file,
+        skip_errors,
+        "Amount,User ID,User Email\n\"0,25\",acct_123,user@example.com\ninvalid,acct_123,user@example.com",
+        is_ok
+    );
+
+    fees_test!(
+        test_parse_skip_errors_all_rows_failed,
+        file,
+        skip_errors,
+        "Amount,User ID,User Email\ninvalid,acct_123,user@example.com\nabc,acct_456,test@example.com",
+        is_err
+    );
+
+    fees_test!(
+        test_parse_skip_errors_empty_csv,
+        file,
+        skip_errors,
+        "Amount,User ID,User Email",
+        is_ok
+    );
+
+    #[test]
+    fn test_write_csv_semicolon_delimiter() {
+        let mut account = AccountFees::new("acct_123", "user@example.com", "eur");
+        account.add_fee(175);
+
+        let mut output = Vec::new();
+        let options = Options {
+            delimiter: b';',
+            ..Options::default()
+        };
+        write([&account], &mut output, &options).expect("should write");
+        assert_eq!(
+            String::from_utf8(output).expect("should be utf-8"),
+            "account_id;email;currency;transaction_count;total_fees_eur\nacct_123;user@example.com;eur;1;1.75\n"
+        );
+    }
+
+    #[test]
+    fn test_write_csv_quotes_fields_with_commas() {
+        let mut account = AccountFees::new("acct,123", "user@example.com", "eur");
+        account.add_fee(25);
+
+        let mut output = Vec::new();
+        write([&account], &mut output, &Options::default()).expect("should write");
+        let output = String::from_utf8(output).expect("should be utf-8");
+        assert_eq!(
+            output.lines().nth(1),
+            Some("\"acct,123\",user@example.com,eur,1,0.25")
+        );
+
+        let mut reader = csv::Reader::from_reader(output.as_bytes());
+        let record = reader
+            .records()
+            .next()
+            .expect("should have record")
+            .expect("should be ok");
+        assert_eq!(record.len(), 5);
+        assert_eq!(&record[0], "acct,123");
+    }
+
+    #[test]
+    fn test_write_csv_escapes_quotes() {
+        let mut account = AccountFees::new("acct_123", "\"user\"@example.com", "eur");
+        account.add_fee(25);
+
+        let mut output = Vec::new();
+        write([&account], &mut output, &Options::default()).expect("should write");
+        assert_eq!(
+            String::from_utf8(output)
+                .expect("should be utf-8")
+                .lines()
+                .nth(1),
+            Some("acct_123,\"\"\"user\"\"@example.com\",eur,1,0.25")
+        );
+    }
+
+    #[test]
+    fn test_write_csv_tab_delimiter_quotes_tabs() {
+        let mut account = AccountFees::new("acct_123", "user\t@example.com", "eur");
+        account.add_fee(25);
+
+        let mut output = Vec::new();
+        let options = Options {
+            format: OutputFormat::Tsv,
+            ..Options::default()
+        };
+        write([&account], &mut output, &options).expect("should write");
+        let output = String::from_utf8(output).expect("should be utf-8");
+        assert_eq!(
+            output,
+            "account_id\temail\tcurrency\ttransaction_count\ttotal_fees_eur\nacct_123\t\"user\t@example.com\"\teur\t1\t0.25\n"
+        );
+
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(b'\t')
+            .from_reader(output.as_bytes());
+        let record = reader
+            .records()
+            .next()
+            .expect("should have record")
+            .expect("should be ok");
+        assert_eq!(&record[1], "user\t@example.com");
+    }
+
+    #[test]
+    fn test_parse_tsv_output() {
+        let mut temp_file = NamedTempFile::new().expect("should create temp file");
+        writeln!(
+            temp_file,
+            "Amount,User ID,User Email\n\"0,25\",acct_123,user@example.com"
+        )
+        .expect("should write to temp file");
+        let directory = tempfile::tempdir().expect("should create temp dir");
+
+        parse(
+            temp_file.path().to_path_buf(),
+            Some(directory.path().to_path_buf()),
+            &Options {
+                format: OutputFormat::Tsv,
+                delimiter: b';',
+                ..Options::default()
+            },
+        )
+        .expect("should parse");
+
+        let stem = temp_file
+            .path()
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .expect("should have stem");
+        let output = std::fs::read_to_string(directory.path().join(format!("{stem}_out.tsv")))
+            .expect("should read output");
+        assert_eq!(
+            output,
+            "account_id\temail\tcurrency\ttransaction_count\ttotal_fees_eur\nacct_123\tuser@example.com\teur\t1\t0.25\n"
+        );
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let mut account = AccountFees::new("acct_123", "user@example.com", "eur");
+        account.add_fee(25);
+        account.add_fee(150);
+
+        let mut output = Vec::new();
+        write([&account], &mut output, &Options::default()).expect("should write");
+        assert_eq!(
+            String::from_utf8(output).expect("should be utf-8"),
+            "account_id,email,currency,transaction_count,total_fees_eur\nacct_123,user@example.com,eur,2,1.75\n"
+        );
+    }
+
+    #[test]
+    fn test_write_no_header() {
+        let mut account = AccountFees::new("acct_123", "user@example.com", "eur");
+        account.add_fee(175);
+
+        let mut output = Vec::new();
+        let options = Options {
+            no_header: true,
+            ..Options::default()
+        };
+        write([&account], &mut output, &options).expect("should write");
+        assert_eq!(
+            String::from_utf8(output).expect("should be utf-8"),
+            "acct_123,user@example.com,eur,1,1.75\n"
+        );
+    }
+
+    fn parse_no_header(format: OutputFormat) -> String {
+        let mut temp_file = NamedTempFile::new().expect("should create temp file");
+        let out_temp_file = NamedTempFile::new().expect("should create out temp file");
+        writeln!(
+            temp_file,
+            "Amount,User ID,User Email\n\"0,25\",acct_123,user@example.com"
+        )
+        .expect("should write to temp file");
+
+        parse(
+            temp_file.path().to_path_buf(),
+            Some(out_temp_file.path().to_path_buf()),
+            &Options {
+                format,
+                no_header: true,
+                ..Options::default()
+            },
+        )
+        .expect("should parse");
+        std::fs::read_to_string(out_temp_file.path()).expect("should read output")
+    }
+
+    #[test]
+    fn test_parse_no_header() {
+        assert_eq!(
+            parse_no_header(OutputFormat::Csv),
+            "acct_123,user@example.com,eur,1,0.25\n"
+        );
+        assert_eq!(
+            parse_no_header(OutputFormat::Tsv),
+            "acct_123\tuser@example.com\teur\t1\t0.25\n"
+        );
+        let json: serde_json::Value = serde_json::from_str(&parse_no_header(OutputFormat::Json))
+            .expect("should be valid json");
+        assert_eq!(json[0]["total_fees_eur"], "0.25");
+    }
+
+    #[test]
+    fn test_parse_gzip_output() {
+        let mut temp_file = NamedTempFile::new().expect("should create temp file");
+        writeln!(
+            temp_file,
+            "Amount,User ID,User Email\n\"0,25\",acct_123,user@example.com\n\"1,50\",acct_456,test@example.com"
+        )
+        .expect("should write to temp file");
+        let directory = tempfile::tempdir().expect("should create temp dir");
+        let output = directory.path().join("fees_out.csv.gz");
+
+        parse(
+            temp_file.path().to_path_buf(),
+            Some(output.clone()),
+            &Options::default(),
+        )
+        .expect("should parse");
+
+        let mut reader = common::reader(&output).expect("should open output");
+        let rows: Vec<Vec<String>> = reader
+            .records()
+            .map(|record| {
+                record
+                    .expect("should be ok")
+                    .iter()
+                    .map(str::to_string)
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                ["acct_123", "user@example.com", "eur", "1", "0.25"],
+                ["acct_456", "test@example.com", "eur", "1", "1.50"]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_json_output() {
+        let mut temp_file = NamedTempFile::new().expect("should create temp file");
+        let out_temp_file = NamedTempFile::new().expect("should create out temp file");
+        writeln!(
+            temp_file,
+            "Amount,User ID,User Email\n\"0,25\",acct_123,user@example.com\n\"1,50\",acct_123,user@example.com"
+        )
+        .expect("should write to temp file");
+
+        parse(
+            temp_file.path().to_path_buf(),
+            Some(out_temp_file.path().to_path_buf()),
+            &Options {
+                format: OutputFormat::Json,
+                ..Options::default()
+            },
+        )
+        .expect("should parse");
+
+        let output = std::fs::read_to_string(out_temp_file.path()).expect("should read output");
+        let json: serde_json::Value = serde_json::from_str(&output).expect("should be valid json");
+        assert_eq!(
+            json,
+            serde_json::json!([{
+                "account_id": "acct_123",
+                "email": "user@example.com",
+                "currency": "eur",
+                "transaction_count": 2,
+                "total_fees_eur": "1.75"
+            }])
+        );
+    }
+
+    #[test]
+    fn test_parse_with_totals_per_currency() {
+        let mut temp_file = NamedTempFile::new().expect("should create temp file");
+        let out_temp_file = NamedTempFile::new().expect("should create out temp file");
+        writeln!(
+            temp_file,
+            "Amount,Currency,User ID,User Email\n\"0,10\",eur,acct_123,user@example.com\n500,jpy,acct_123,user@example.com\n\"0,20\",eur,acct_456,test@example.com"
+        )
+        .expect("should write to temp file");
+
+        parse(
+            temp_file.path().to_path_buf(),
+            Some(out_temp_file.path().to_path_buf()),
+            &Options {
+                totals: true,
+                ..Options::default()
+            },
+        )
+        .expect("should parse");
+
+        let output = std::fs::read_to_string(out_temp_file.path()).expect("should read output");
+        assert_eq!(
+            output,
+            "account_id,email,currency,transaction_count,total_fees_eur\nacct_123,user@example.com,eur,1,0.10\nacct_123,user@example.com,jpy,1,500\nacct_456,test@example.com,eur,1,0.20\nTOTAL,,eur,2,0.30\nTOTAL,,jpy,1,500\n"
+        );
+    }
+
+    #[test]
+    fn test_write_csv_currency_label() {
+        let mut account = AccountFees::new("acct_123", "user@example.com", "usd");
+        account.add_fee(25);
+
+        let mut output = Vec::new();
+        let options = Options {
+            currency_label: "usd".to_string(),
+            ..Options::default()
+        };
+        write([&account], &mut output, &options).expect("should write");
+        assert_eq!(
+            String::from_utf8(output).expect("should be utf-8"),
+            "account_id,email,currency,transaction_count,total_fees_usd\nacct_123,user@example.com,usd,1,0.25\n"
+        );
+    }
+
+    #[test]
+    fn test_write_json_currency_label() {
+        let mut account = AccountFees::new("acct_123", "user@example.com", "usd");
+        account.add_fee(1230);
+
+        let mut output = Vec::new();
+        let options = Options {
+            format: OutputFormat::Json,
+            currency_label: "usd".to_string(),
+            ..Options::default()
+        };
+        write([&account], &mut output, &options).expect("should write");
+        let json: serde_json::Value =
+            serde_json::from_slice(&output).expect("should be valid json");
+        assert_eq!(json[0]["total_fees_usd"], "12.30");
+        assert!(json[0].get("total_fees_eur").is_none());
+    }
+
+    #[test]
+    fn test_write_jsonl() {
+        let options = Options {
+            format: OutputFormat::Jsonl,
+            ..Options::default()
+        };
+        let mut output = Vec::new();
+        write(&accounts(), &mut output, &options).expect("should write");
+
+        let output = String::from_utf8(output).expect("should be utf-8");
+        let lines: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("should be valid json"))
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["account_id"], "acct_b");
+        assert_eq!(lines[0]["total_fees_eur"], "1.00");
+        assert_eq!(lines[2]["account_id"], "acct_c");
+        assert_eq!(
+            output.lines().next(),
+            Some(
+                r#"{"account_id":"acct_b","email":"b@example.com","currency":"eur","transaction_count":1,"total_fees_eur":"1.00"}"#
+            )
+        );
+
+        let mut output = Vec::new();
+        write(&[], &mut output, &options).expect("should write");
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn test_mismatched_currencies() {
+        let mut statistics = accounts();
+        statistics.push(AccountFees::new("acct_a", "a@example.com", "usd"));
+        statistics.push(AccountFees::new("acct_b", "b@example.com", "jpy"));
+        statistics.push(AccountFees::new("acct_c", "c@example.com", "usd"));
+
+        assert_eq!(mismatched_currencies(&statistics, "eur"), ["jpy", "usd"]);
+        assert_eq!(
+            mismatched_currencies(&accounts(), "EUR"),
+            Vec::<&str>::new()
+        );
+    }
+
+    #[test]
+    fn test_parse_files_sums_accounts() {
+        let mut first = NamedTempFile::new().expect("should create temp file");
+        writeln!(
+            first,
+            "Amount,User ID,User Email\n\"0,25\",acct_123,user@example.com\n\"1,00\",acct_456,test@example.com"
+        )
+        .expect("should write to temp file");
+        let mut second = NamedTempFile::new().expect("should create temp file");
+        writeln!(
+            second,
+            "Amount,User ID,User Email\n\"0,50\",acct_123,user@example.com\n\"2,00\",acct_789,other@example.com"
+        )
+        .expect("should write to temp file");
+        let out_temp_file = NamedTempFile::new().expect("should create out temp file");
+
+        parse_files(
+            &[first.path().to_path_buf(), second.path().to_path_buf()],
+            Some(out_temp_file.path().to_path_buf()),
+            &Options::default(),
+        )
+        .expect("should parse");
+
+        let output = std::fs::read_to_string(out_temp_file.path()).expect("should read output");
+        assert_eq!(
+            output,
+            "account_id,email,currency,transaction_count,total_fees_eur\nacct_123,user@example.com,eur,2,0.75\nacct_456,test@example.com,eur,1,1.00\nacct_789,other@example.com,eur,1,2.00\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_files_dedupe_overlapping_exports() {
+        let mut first = NamedTempFile::new().expect("should create temp file");
+        writeln!(
+            first,
+            "id,Amount,User ID,User Email\nfee_1,\"0,25\",acct_123,user@example.com\nfee_2,\"1,00\",acct_456,test@example.com"
+        )
+        .expect("should write to temp file");
+        let mut second = NamedTempFile::new().expect("should create temp file");
+        writeln!(
+            second,
+            "id,Amount,User ID,User Email\nfee_2,\"1,00\",acct_456,test@example.com\nfee_3,\"0,50\",acct_123,user@example.com"
+        )
+        .expect("should write to temp file");
+        let out_temp_file = NamedTempFile::new().expect("should create out temp file");
+        let options = Options {
+            dedupe: true,
+            ..Options::default()
+        };
+
+        parse_files(
+            &[first.path().to_path_buf(), second.path().to_path_buf()],
+            Some(out_temp_file.path().to_path_buf()),
+            &options,
+        )
+        .expect("should parse");
+
+        let output = std::fs::read_to_string(out_temp_file.path()).expect("should read output");
+        assert_eq!(
+            output,
+            "account_id,email,currency,transaction_count,total_fees_eur\nacct_123,user@example.com,eur,2,0.75\nacct_456,test@example.com,eur,1,1.00\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_group_by_month() {
+        let mut temp_file = NamedTempFile::new().expect("should create temp file");
+        writeln!(
+            temp_file,
+            "Created (UTC),Amount,Currency,User ID,User Email\n2025-12-31 14:30,\"0,25\",eur,acct_123,user@example.com\n2025-10-02 10:00,\"1,50\",eur,acct_456,test@example.com\n2025-12-01 00:00,\"0,75\",eur,acct_456,test@example.com\n2024-12-15 09:00,\"2,00\",eur,acct_123,user@example.com\n2025-12-15 09:00,500,jpy,acct_789,other@example.com"
+        )
+        .expect("should write to temp file");
+        let out_temp_file = NamedTempFile::new().expect("should create out temp file");
+        let options = Options {
+            group_by: GroupBy::Month,
+            sort_by: SortKey::Fees,
+            descending: true,
+            ..Options::default()
+        };
+
+        parse(
+            temp_file.path().to_path_buf(),
+            Some(out_temp_file.path().to_path_buf()),
+            &options,
+        )
+        .expect("should parse");
+
+        let output = std::fs::read_to_string(out_temp_file.path()).expect("should read output");
+        assert_eq!(
+            output,
+            "month,currency,transaction_count,total_fees_eur\n2024-12,eur,1,2.00\n2025-10,eur,1,1.50\n2025-12,eur,2,1.00\n2025-12,jpy,1,500\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_group_by_email_with_totals() {
+        let mut temp_file = NamedTempFile::new().expect("should create temp file");
+        writeln!(
+            temp_file,
+            "Amount,User ID,User Email\n\"0,25\",acct_123,User@Example.com\n\"0,50\",acct_456,user@example.com\n\"0,75\",acct_789,test@example.com"
+        )
+        .expect("should write to temp file");
+        let out_temp_file = NamedTempFile::new().expect("should create out temp file");
+        let options = Options {
+            group_by: GroupBy::Email,
+            normalize_email: true,
+            totals: true,
+            ..Options::default()
+        };
+
+        parse(
+            temp_file.path().to_path_buf(),
+            Some(out_temp_file.path().to_path_buf()),
+            &options,
+        )
+        .expect("should parse");
+
+        let output = std::fs::read_to_string(out_temp_file.path()).expect("should read output");
+        assert_eq!(
+            output,
+            "email,currency,transaction_count,total_fees_eur\ntest@example.com,eur,1,0.75\nUser@Example.com,eur,2,0.75\nTOTAL,eur,3,1.50\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_group_by_month_json() {
+        let mut temp_file = NamedTempFile::new().expect("should create temp file");
+        writeln!(
+            temp_file,
+            "Created (UTC),Amount,User ID,User Email\n2025-12-31 14:30,\"0,25\",acct_123,user@example.com"
+        )
+        .expect("should write to temp file");
+        let out_temp_file = NamedTempFile::new().expect("should create out temp file");
+        let options = Options {
+            group_by: GroupBy::Month,
+            format: OutputFormat::Json,
+            ..Options::default()
+        };
+
+        parse(
+            temp_file.path().to_path_buf(),
+            Some(out_temp_file.path().to_path_buf()),
+            &options,
+        )
+        .expect("should parse");
+
+        let output = std::fs::read_to_string(out_temp_file.path()).expect("should read output");
+        let json: serde_json::Value = serde_json::from_str(&output).expect("should be valid JSON");
+        assert_eq!(
+            json,
+            serde_json::json!([{"month": "2025-12", "currency": "eur", "transaction_count": 1, "total_fees_eur": "0.25"}])
+        );
+    }
+
+    #[test]
+    fn test_parse_xlsx_output() {
+        let mut temp_file = NamedTempFile::new().expect("should create temp file");
+        writeln!(
+            temp_file,
+            "Amount,Currency,User ID,User Email\n\"0,25\",eur,acct_123,user@example.com\n500,jpy,acct_456,test@example.com"
+        )
+        .expect("should write to temp file");
+        let out_temp_file = NamedTempFile::new().expect("should create out temp file");
+        let options = Options {
+            format: OutputFormat::Xlsx,
+            ..Options::default()
+        };
+
+        parse(
+            temp_file.path().to_path_buf(),
+            Some(out_temp_file.path().to_path_buf()),
+            &options,
+        )
+        .expect("should parse");
+
+        let file = std::fs::File::open(out_temp_file.path()).expect("should open output");
+        let mut workbook = zip::ZipArchive::new(file).expect("should be a workbook");
+        let mut part = |name: &str| {
+            let mut content = String::new();
+            std::io::Read::read_to_string(
+                &mut workbook.by_name(name).expect("should have part"),
+                &mut content,
+            )
+            .expect("should read part");
+            content
+        };
+        assert!(part("xl/workbook.xml").contains("name=\"Fees\""));
+        let strings = part("xl/sharedStrings.xml");
+        assert!(strings.contains("total_fees_eur"));
+        assert!(strings.contains("acct_123"));
+        let sheet = part("xl/worksheets/sheet1.xml");
+        assert!(sheet.contains("<v>0.25</v>"));
+        assert!(sheet.contains("<v>500</v>"));
+        let styles = part("xl/styles.xml");
+        assert!(styles.contains("<b/>"));
+        assert!(styles.contains("formatCode=\"#,##0.00 &quot;EUR&quot;\""));
+        assert!(styles.contains("formatCode=\"#,##0 &quot;JPY&quot;\""));
+    }
+
+    #[test]
+    fn test_currency_format() {
+        assert_eq!(currency_format(2, "eur"), "#,##0.00 \"EUR\"");
+        assert_eq!(currency_format(0, "jpy"), "#,##0 \"JPY\"");
+        assert_eq!(currency_format(3, "bhd"), "#,##0.000 \"BHD\"");
+    }
+
+    fn parse_quoted(quote_style: QuoteStyle) -> String {
+        let mut temp_file = NamedTempFile::new().expect("should create temp file");
+        writeln!(
+            temp_file,
+            "Amount,User ID,User Email\n\"0,25\",acct_123,user@example.com"
+        )
+        .expect("should write to temp file");
+        let out_temp_file = NamedTempFile::new().expect("should create out temp file");
+        let options = Options {
+            quote_style,
+            ..Options::default()
+        };
+
+        parse(
+            temp_file.path().to_path_buf(),
+            Some(out_temp_file.path().to_path_buf()),
+            &options,
+        )
+        .expect("should parse");
+        std::fs::read_to_string(out_temp_file.path()).expect("should read output")
+    }
+
+    #[test]
+    fn test_parse_output_precision() {
+        let mut temp_file = NamedTempFile::new().expect("should create temp file");
+        writeln!(
+            temp_file,
+            "Amount,User ID,User Email,Currency\n\"0,25\",acct_123,user@example.com,eur\n\"1,50\",acct_123,user@example.com,eur\n500,acct_456,test@example.com,jpy"
+        )
+        .expect("should write to temp file");
+        let out_temp_file = NamedTempFile::new().expect("should create out temp file");
+        for (precision, expected) in [
+            (
+                0,
+                "acct_123,user@example.com,eur,2,2,2\nacct_456,test@example.com,jpy,1,500,500\n",
+            ),
+            (
+                3,
+                "acct_123,user@example.com,eur,2,1.750,1.500\nacct_456,test@example.com,jpy,1,500.000,500.000\n",
+            ),
+        ] {
+            let options = Options {
+                output_precision: Some(precision),
+                extra_columns: ExtraColumns {
+                    max_fee: true,
+                    ..ExtraColumns::default()
+                },
+                no_header: true,
+                ..Options::default()
+            };
+            parse(
+                temp_file.path().to_path_buf(),
+                Some(out_temp_file.path().to_path_buf()),
+                &options,
+            )
+            .expect("should parse");
+
+            let output = std::fs::read_to_string(out_temp_file.path()).expect("should read output");
+            assert_eq!(output, expected);
+        }
+    }
+
+    #[test]
+    fn test_parse_line_ending() {
+        let data = "Amount,User ID,User Email\n\"0,25\",acct_123,user@example.com\n\"0,10\",acct_456,test@example.com";
+        for (format, assume_sorted, expected) in [
+            (
+                OutputFormat::Csv,
+                false,
+                &b"account_id,email,currency,transaction_count,total_fees_eur\r\nacct_123,user@example.com,eur,1,0.25\r\nacct_456,test@example.com,eur,1,0.10\r\n"[..],
+            ),
+            (
+                OutputFormat::Csv,
+                true,
+                &b"account_id,email,currency,transaction_count,total_fees_eur\r\nacct_123,user@example.com,eur,1,0.25\r\nacct_456,test@example.com,eur,1,0.10\r\n"[..],
+            ),
+            (
+                OutputFormat::Jsonl,
+                false,
+                &b"{\"account_id\":\"acct_123\",\"email\":\"user@example.com\",\"currency\":\"eur\",\"transaction_count\":1,\"total_fees_eur\":\"0.25\"}\r\n{\"account_id\":\"acct_456\",\"email\":\"test@example.com\",\"currency\":\"eur\",\"transaction_count\":1,\"total_fees_eur\":\"0.10\"}\r\n"[..],
+            ),
+        ] {
+            let options = Options {
+                format,
+                assume_sorted,
+                line_ending: LineEnding::Crlf,
+                ..Options::default()
+            };
+            let output = parse_sorted(data, &options).expect("should parse");
+            assert_eq!(output.as_bytes(), expected);
+        }
+
+        let output = parse_sorted(data, &Options::default()).expect("should parse");
+        assert!(output.ends_with("0.10\n") && !output.contains('\r'));
+    }
+
+    #[test]
+    fn test_parse_quote_style() {
+        assert_eq!(
+            parse_quoted(QuoteStyle::Necessary),
+            "account_id,email,currency,transaction_count,total_fees_eur\nacct_123,user@example.com,eur,1,0.25\n"
+        );
+        assert_eq!(
+            parse_quoted(QuoteStyle::Always),
+            "\"account_id\",\"email\",\"currency\",\"transaction_count\",\"total_fees_eur\"\n\"acct_123\",\"user@example.com\",\"eur\",\"1\",\"0.25\"\n"
+        );
+        assert_eq!(
+            parse_quoted(QuoteStyle::NonNumeric),
+            "\"account_id\",\"email\",\"currency\",\"transaction_count\",\"total_fees_eur\"\n\"acct_123\",\"user@example.com\",\"eur\",1,0.25\n"
+        );
+    }
+
+    fn parse_sorted(data: &str, options: &Options) -> Result<String, FeesError> {
+        let mut temp_file = NamedTempFile::new().expect("should create temp file");
+        writeln!(temp_file, "{data}").expect("should write to temp file");
+        let out_temp_file = NamedTempFile::new().expect("should create out temp file");
+
+        parse(
+            temp_file.path().to_path_buf(),
+            Some(out_temp_file.path().to_path_buf()),
+            options,
+        )?;
+        Ok(std::fs::read_to_string(out_temp_file.path()).expect("should read output"))
+    }
+
+    #[test]
+    fn test_parse_assume_sorted_matches_aggregation() {
+        let data = "Amount,Currency,User ID,User Email\n\"0,25\",eur,acct_123,user@example.com\n500,jpy,acct_123,user@example.com\n\"0,75\",eur,acct_123,user@example.com\n\"15,00\",eur,acct_456,test@example.com\n\"0,10\",eur,acct_789,other@example.com\n\"20,00\",usd,acct_789,other@example.com";
+        let options = Options {
+            totals: true,
+            min_fees: Some(100),
+            extra_columns: ExtraColumns {
+                max_fee: true,
+                average_fee: true,
+                net_fees: false,
+                fee_range: false,
+            },
+            ..Options::default()
+        };
+        let sorted = Options {
+            assume_sorted: true,
+            ..options.clone()
+        };
+
+        let output = parse_sorted(data, &sorted).expect("should parse");
+        assert_eq!(output, parse_sorted(data, &options).expect("should parse"));
+        assert_eq!(
+            output.lines().nth(1),
+            Some("acct_123,user@example.com,eur,2,1.00,0.75,0.50")
+        );
+    }
+
+    #[test]
+    fn test_parse_assume_sorted_unsorted_input() {
+        let data = "Amount,User ID,User Email\n\"0,25\",acct_123,user@example.com\n\"1,50\",acct_456,test@example.com\n\"0,75\",acct_123,user@example.com";
+        let options = Options {
+            assume_sorted: true,
+            ..Options::default()
+        };
+
+        let error = parse_sorted(data, &options).expect_err("should be error");
+        assert!(matches!(
+            &error,
+            FeesError::UnsortedInput { line: 4, account_id, previous }
+                if account_id == "acct_123" && previous == "acct_456"
+        ));
+    }
+
+    #[test]
+    fn test_parse_tee_writes_the_output_file() {
+        let data = "Amount,User ID,User Email\n\"0,25\",acct_123,user@example.com";
+        let expected = parse_sorted(data, &Options::default()).expect("should parse");
+        for assume_sorted in [false, true] {
+            let options = Options {
+                tee: true,
+                assume_sorted,
+                ..Options::default()
+            };
+            assert_eq!(
+                parse_sorted(data, &options).expect("should parse"),
+                expected
+            );
+        }
+
+        let options = Options {
+            tee: true,
+            format: OutputFormat::Xlsx,
+            ..Options::default()
+        };
+        let error = parse_sorted(data, &options).expect_err("should be error");
+        assert!(matches!(error, FeesError::TeeBinary("xlsx")));
+    }
+
+    #[test]
+    fn test_parse_assume_sorted_incompatible_option() {
+        let options = Options {
+            assume_sorted: true,
+            top: Some(1),
+            ..Options::default()
+        };
+
+        let error =
+            parse_sorted("Amount,User ID,User Email", &options).expect_err("should be error");
+        assert!(matches!(
+            error,
+            FeesError::IncompatibleOption("--assume-sorted", "--top")
+        ));
+    }
+
+    #[test]
+    fn test_parse_files_assume_sorted() {
+        let mut first = NamedTempFile::new().expect("should create temp file");
+        writeln!(
+            first,
+            "Amount,User ID,User Email\n\"0,25\",acct_123,user@example.com\n\"1,00\",acct_456,test@example.com"
+        )
+        .expect("should write to temp file");
+        let mut second = NamedTempFile::new().expect("should create temp file");
+        writeln!(
+            second,
+            "Amount,User ID,User Email\n\"0,50\",acct_456,test@example.com\n\"2,00\",acct_789,other@example.com"
+        )
+        .expect("should write to temp file");
+        let out_temp_file = NamedTempFile::new().expect("should create out temp file");
+        let options = Options {
+            assume_sorted: true,
+            ..Options::default()
+        };
+
+        parse_files(
+            &[first.path().to_path_buf(), second.path().to_path_buf()],
+            Some(out_temp_file.path().to_path_buf()),
+            &options,
+        )
+        .expect("should parse");
+
+        let output = std::fs::read_to_string(out_temp_file.path()).expect("should read output");
+        assert_eq!(
+            output,
+            "account_id,email,currency,transaction_count,total_fees_eur\nacct_123,user@example.com,eur,1,0.25\nacct_456,test@example.com,eur,2,1.50\nacct_789,other@example.com,eur,1,2.00\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_files_input_delimiter() {
+        let mut semicolons = NamedTempFile::new().expect("should create temp file");
+        writeln!(
+            semicolons,
+            "Amount;User ID;User Email\n0,25;acct_123;user@example.com\n1,00;acct_456;test@example.com"
+        )
+        .expect("should write to temp file");
+        let mut tabs = NamedTempFile::new().expect("should create temp file");
+        writeln!(
+            tabs,
+            "Amount\tUser ID\tUser Email\n0,50\tacct_123\tuser@example.com"
+        )
+        .expect("should write to temp file");
+        let files = [semicolons.path().to_path_buf(), tabs.path().to_path_buf()];
+        let expected = "account_id,email,currency,transaction_count,total_fees_eur\nacct_123,user@example.com,eur,2,0.75\nacct_456,test@example.com,eur,1,1.00\n";
+
+        let out_temp_file = NamedTempFile::new().expect("should create out temp file");
+        let options = Options {
+            input_delimiter: InputDelimiter::Auto,
+            ..Options::default()
+        };
+        parse_files(&files, Some(out_temp_file.path().to_path_buf()), &options)
+            .expect("should parse");
+        let output = std::fs::read_to_string(out_temp_file.path()).expect("should read output");
+        assert_eq!(output, expected);
+
+        let options = Options {
+            input_delimiter: InputDelimiter::Byte(b';'),
+            ..Options::default()
+        };
+        parse_files(
+            &files[..1],
+            Some(out_temp_file.path().to_path_buf()),
+            &options,
+        )
+        .expect("should parse");
+        let output = std::fs::read_to_string(out_temp_file.path()).expect("should read output");
+        assert!(output.ends_with("acct_456,test@example.com,eur,1,1.00\n"));
+        assert!(parse_files(&files[1..], None, &options).is_err());
+    }
+
+    #[test]
+    fn test_parse_files_limit() {
+        let mut first = NamedTempFile::new().expect("should create temp file");
+        writeln!(
+            first,
+            "Amount,User ID,User Email\n\"0,25\",acct_123,user@example.com\n\"1,00\",acct_456,test@example.com"
+        )
+        .expect("should write to temp file");
+        let mut second = NamedTempFile::new().expect("should create temp file");
+        writeln!(
+            second,
+            "Amount,User ID,User Email\n\"0,50\",acct_456,test@example.com\n\"2,00\",acct_789,other@example.com"
+        )
+        .expect("should write to temp file");
+        let files = [first.path().to_path_buf(), second.path().to_path_buf()];
+        let out_temp_file = NamedTempFile::new().expect("should create out temp file");
+
+        for (limit, assume_sorted, expected) in [
+            (1, false, "acct_123,user@example.com,eur,1,0.25\n"),
+            (
+                3,
+                false,
+                "acct_123,user@example.com,eur,1,0.25\nacct_456,test@example.com,eur,2,1.50\n",
+            ),
+            (
+                3,
+                true,
+                "acct_123,user@example.com,eur,1,0.25\nacct_456,test@example.com,eur,2,1.50\n",
+            ),
+            (
+                4,
+                true,
+                "acct_123,user@example.com,eur,1,0.25\nacct_456,test@example.com,eur,2,1.50\nacct_789,other@example.com,eur,1,2.00\n",
+            ),
+            (
+                10,
+                false,
+                "acct_123,user@example.com,eur,1,0.25\nacct_456,test@example.com,eur,2,1.50\nacct_789,other@example.com,eur,1,2.00\n",
+            ),
+        ] {
+            let options = Options {
+                limit: Some(limit),
+                assume_sorted,
+                no_header: true,
+                ..Options::default()
+            };
+            parse_files(&files, Some(out_temp_file.path().to_path_buf()), &options)
+                .expect("should parse");
+
+            let output = std::fs::read_to_string(out_temp_file.path()).expect("should read output");
+            assert_eq!(output, expected);
+        }
+    }
+
+    #[test]
+    fn test_parse_files_errors_file() {
+        let mut first = NamedTempFile::new().expect("should create temp file");
+        writeln!(
+            first,
+            "Amount,User ID,User Email\ninvalid,acct_123,user@example.com\n\"1,00\",acct_456,test@example.com"
+        )
+        .expect("should write to temp file");
+        let mut second = NamedTempFile::new().expect("should create temp file");
+        writeln!(
+            second,
+            "Amount,User ID,User Email\n\"0,50\",acct_456,test@example.com\n\"x,y\",acct_789,\"Other, Inc\""
+        )
+        .expect("should write to temp file");
+        let files = [first.path().to_path_buf(), second.path().to_path_buf()];
+        let directory = tempfile::tempdir().expect("should create temp dir");
+        let errors_file = directory.path().join("errors.csv");
+
+        for assume_sorted in [false, true] {
+            let options = Options {
+                skip_errors: true,
+                errors_file: Some(errors_file.clone()),
+                assume_sorted,
+                ..Options::default()
+            };
+            parse_files(
+                &files,
+                Some(directory.path().join("fees_out.csv")),
+                &options,
+            )
+            .expect("should parse");
+
+            let errors = std::fs::read_to_string(&errors_file).expect("should read errors");
+            let lines: Vec<&str> = errors.lines().collect();
+            assert_eq!(lines.len(), 3);
+            assert_eq!(lines[0], "file,line,content,error");
+            assert!(lines[1].starts_with(&format!(
+                "{},2,\"invalid,acct_123,user@example.com\",",
+                first.path().display()
+            )));
+            assert!(lines[1].contains("invalid amount 'invalid'"));
+            assert!(lines[2].starts_with(&format!(
+                "{},3,\"\"\"x,y\"\",acct_789,\"\"Other, Inc\"\"\",",
+                second.path().display()
+            )));
+        }
+
+        let options = Options {
+            errors_file: Some(errors_file.clone()),
+            ..Options::default()
+        };
+        std::fs::write(
+            second.path(),
+            "Amount,User ID,User Email\n\"0,50\",acct_456,test@example.com\n",
+        )
+        .expect("should write to temp file");
+        parse_files(
+            &files[1..2],
+            Some(directory.path().join("fees_out.csv")),
+            &options,
+        )
+        .expect("should parse");
+        let errors = std::fs::read_to_string(&errors_file).expect("should read errors");
+        assert_eq!(errors, "file,line,content,error\n");
+    }
+
+    #[test]
+    fn test_parse_files_missing_file() {
+        let mut first = NamedTempFile::new().expect("should create temp file");
+        writeln!(first, "Amount,User ID,User Email").expect("should write to temp file");
+        let missing = PathBuf::from("/tmp/non_existent_file_12345.csv");
+
+        let result = parse_files(
+            &[first.path().to_path_buf(), missing.clone()],
+            None,
+            &Options::default(),
+        );
+        assert!(matches!(result, Err(FeesError::FileNotFound(path)) if path == missing));
+    }
+
+    #[test]
+    fn test_exit_code() {
+        let missing = parse(
+            PathBuf::from("/tmp/non_existent_file_12345.csv"),
+            None,
+            &Options::default(),
+        )
+        .expect_err("should be error");
+        assert_eq!(missing.exit_code(), exit::NOT_FOUND);
+
+        let malformed = aggregate(csv::Reader::from_reader(
+            "Amount,User ID,User Email\ninvalid,acct_123,user@example.com".as_bytes(),
+        ))
+        .expect_err("should be error");
+        assert_eq!(malformed.exit_code(), exit::MALFORMED_DATA);
+
+        let missing_column = aggregate(csv::Reader::from_reader(&b"Amount,User ID\n"[..]))
+            .expect_err("should be error");
+        assert_eq!(missing_column.exit_code(), exit::MALFORMED_DATA);
+
+        let io = FeesError::Io(std::io::Error::other("disk full"));
+        assert_eq!(io.exit_code(), exit::IO);
+    }
+
+    #[test]
+    fn test_parse_files_without_files() {
+        let result = parse_files(&[], None, &Options::default());
+        assert!(matches!(result, Err(FeesError::NoInputFiles)));
+    }
+
+    #[test]
+    fn test_parse_file_not_found() {
+        let non_existent_path = PathBuf::from("/tmp/non_existent_file_12345.csv");
+        let result = parse(non_existent_path.clone(), None, &Options::default());
+
+        let error = result.expect_err("should be error");
+        assert!(error.to_string().contains("does not exist"));
+        assert!(matches!(error, FeesError::FileNotFound(path) if path == non_existent_path));
+    }
+
+    #[test]
+    fn test_parse_invalid_row_is_csv_error() {
+        let mut temp_file = NamedTempFile::new().expect("should create temp file");
+        let out_temp_file = NamedTempFile::new().expect("should create out temp file");
+        writeln!(
+            temp_file,
+            "Amount,User ID,User Email\ninvalid,acct_123,user@example.com"
+        )
+        .expect("should write to temp file");
 
-        assert!(result.is_err());
-        let err_msg = result.expect_err("should be error").to_string();
-        assert!(err_msg.contains("does not exist"));
+        let result = parse(
+            temp_file.path().to_path_buf(),
+            Some(out_temp_file.path().to_path_buf()),
+            &Options::default(),
+        );
+        assert!(matches!(result, Err(FeesError::Csv(_))));
     }
 }
@@ -0,0 +1,229 @@
+use super::common;
+use crate::amount_serde::{self, deserialize as amount_serde};
+use std::{collections::BTreeMap, path::PathBuf};
+
+#[derive(Debug, serde::Deserialize)]
+struct Entry {
+    #[serde(rename = "Total", deserialize_with = "amount_serde")]
+    pub total: i64,
+    #[serde(rename = "Amount Paid", deserialize_with = "amount_serde")]
+    pub amount_paid: i64,
+    #[serde(rename = "Amount Due", deserialize_with = "amount_serde")]
+    pub amount_due: i64,
+    #[serde(rename = "Customer ID")]
+    pub customer_id: String,
+    #[serde(rename = "Customer Email")]
+    pub email: String,
+    #[serde(rename = "Status")]
+    pub status: String,
+    /// Currency as written in the export, `eur` when the export has no `Currency` column.
+    #[serde(rename = "Currency", default = "common::default_currency")]
+    pub currency: String,
+}
+
+impl Entry {
+    /// Whether the invoice was finalized, draft and void invoices are not owed by the customer.
+    pub fn is_finalized(&self) -> bool {
+        !matches!(self.status.to_ascii_lowercase().as_str(), "draft" | "void")
+    }
+
+    /// Whether more than the invoice total was paid.
+    pub const fn is_overpaid(&self) -> bool {
+        self.amount_paid > self.total
+    }
+
+    /// Amount still owed on the invoice, nothing is outstanding once it is paid.
+    pub fn outstanding(&self) -> i64 {
+        if self.status.eq_ignore_ascii_case("paid") {
+            0
+        } else {
+            self.amount_due - self.amount_paid
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CustomerInvoices {
+    pub customer_id: String,
+    pub email: String,
+    pub currency: String,
+    pub invoice_count: u32,
+    pub total_paid: i64,
+    pub total_due: i64,
+}
+
+impl CustomerInvoices {
+    pub fn new(customer_id: &str, email: &str, currency: &str) -> Self {
+        Self {
+            customer_id: customer_id.to_string(),
+            email: email.to_string(),
+            currency: currency.to_string(),
+            invoice_count: 0,
+            total_paid: 0,
+            total_due: 0,
+        }
+    }
+
+    /// Adds an invoice, splitting its amount between what was paid and what is still open.
+    pub fn add_invoice(&mut self, entry: &Entry) {
+        self.invoice_count += 1;
+        self.total_paid += entry.amount_paid;
+        self.total_due += entry.outstanding();
+    }
+
+    pub const fn csv_header() -> &'static str {
+        "customer_id,email,currency,invoice_count,total_paid,total_due"
+    }
+
+    pub fn record(&self) -> [String; 6] {
+        let exponent = amount_serde::currency_exponent(&self.currency);
+        [
+            self.customer_id.clone(),
+            self.email.clone(),
+            self.currency.clone(),
+            self.invoice_count.to_string(),
+            amount_serde::format(self.total_paid, exponent),
+            amount_serde::format(self.total_due, exponent),
+        ]
+    }
+}
+
+/// Parse a Stripe invoices CSV file located at the given path, `-` reads from stdin.
+///
+/// Invoices are grouped by customer and currency, sorted by customer id, with the amount paid
+/// and the amount still due, amounts having the decimals of their currency,
+/// draft and void invoices are ignored as they are not owed.
+/// A warning is printed for every invoice paid more than its total.
+///
+/// # Errors
+///
+/// File was not found or could not be read.
+/// Unable to parse the CSV file.
+/// Unable to create an Entry from a CSV line.
+/// Output file could not be created or written to, `-` writes to stdout.
+pub fn parse(file: PathBuf, output: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut csv_reader, output) = common::open(file, output, "invoices")?;
+    let mut statistics: BTreeMap<(String, String), CustomerInvoices> = BTreeMap::new();
+    for result in common::entries(&mut csv_reader)? {
+        let entry: Entry = result?;
+        if !entry.is_finalized() {
+            continue;
+        }
+        if entry.is_overpaid() {
+            let exponent = amount_serde::currency_exponent(&entry.currency);
+            eprintln!(
+                "warning: an invoice of the customer {} was paid {} which is more than its total {}",
+                entry.customer_id,
+                amount_serde::format(entry.amount_paid, exponent),
+                amount_serde::format(entry.total, exponent)
+            );
+        }
+
+        statistics
+            .entry((entry.customer_id.clone(), entry.currency.to_lowercase()))
+            .or_insert_with_key(|(customer_id, currency)| {
+                CustomerInvoices::new(customer_id, &entry.email, currency)
+            })
+            .add_invoice(&entry);
+    }
+
+    common::write_csv(
+        &output,
+        CustomerInvoices::csv_header(),
+        statistics.values().map(CustomerInvoices::record),
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::common::parser_test;
+
+    const HEADER: &str = "id,Customer ID,Customer Email,Status,Total,Amount Paid,Amount Due";
+
+    parser_test!(
+        entry_deserialize_valid,
+        format!("{HEADER}\nin_123,cus_123,user@example.com,open,\"100,00\",\"25,00\",\"100,00\""),
+        total is 10000,
+        amount_paid is 2500,
+        amount_due is 10000,
+        customer_id is "cus_123".to_string(),
+        email is "user@example.com".to_string(),
+        status is "open".to_string()
+    );
+
+    parser_test!(
+        test_entry_deserialize_missing_amount_due,
+        "Customer ID,Customer Email,Status,Total,Amount Paid\ncus_123,user@example.com,open,\"100,00\",\"25,00\"",
+        is_error
+    );
+
+    parser_test!(
+        test_entry_deserialize_invalid_total,
+        format!("{HEADER}\nin_123,cus_123,user@example.com,open,invalid,\"25,00\",\"100,00\""),
+        is_error
+    );
+
+    #[test]
+    fn test_entry_outstanding() {
+        let data = format!(
+            "{HEADER}\nin_1,cus_123,user@example.com,open,\"100,00\",\"25,00\",\"100,00\"\nin_2,cus_123,user@example.com,Paid,\"50,00\",\"50,00\",\"50,00\"\nin_3,cus_123,user@example.com,uncollectible,\"20,00\",\"0,00\",\"20,00\""
+        );
+        let mut reader = csv::Reader::from_reader(data.as_bytes());
+        let outstanding: Vec<i64> = reader
+            .deserialize::<Entry>()
+            .map(|entry| entry.expect("should be ok").outstanding())
+            .collect();
+        assert_eq!(outstanding, [7500, 0, 2000]);
+    }
+
+    parser_test!(
+        test_parse_groups_by_customer,
+        file,
+        format!(
+            "{HEADER}\nin_1,cus_123,user@example.com,paid,\"100,00\",\"100,00\",\"100,00\"\nin_2,cus_123,user@example.com,open,\"40,00\",\"10,00\",\"40,00\"\nin_3,cus_456,test@example.com,open,\"12,50\",\"0,00\",\"12,50\"\nin_4,cus_456,test@example.com,void,\"99,00\",\"0,00\",\"99,00\"\nin_5,cus_789,draft@example.com,draft,\"5,00\",\"0,00\",\"5,00\""
+        ),
+        [
+            CustomerInvoices::csv_header(),
+            "cus_123,user@example.com,eur,2,110.00,30.00",
+            "cus_456,test@example.com,eur,1,0.00,12.50"
+        ]
+    );
+
+    parser_test!(
+        test_parse_currency_exponent,
+        file,
+        "Customer ID,Customer Email,Status,Total,Amount Paid,Amount Due,Currency\ncus_123,user@example.com,open,1000,250,1000,jpy\ncus_123,user@example.com,open,\"10,000\",\"0,000\",\"10,000\",kwd",
+        [
+            CustomerInvoices::csv_header(),
+            "cus_123,user@example.com,jpy,1,250,750",
+            "cus_123,user@example.com,kwd,1,0.000,10.000"
+        ]
+    );
+
+    parser_test!(
+        test_parse_empty_csv,
+        file,
+        HEADER,
+        [CustomerInvoices::csv_header()]
+    );
+
+    parser_test!(
+        test_parse_invalid_csv_format,
+        file,
+        format!("{HEADER}\nin_1,cus_123,user@example.com,open,invalid,\"0,00\",\"1,00\""),
+        is_error
+    );
+
+    #[test]
+    fn test_parse_file_not_found() {
+        let non_existent_path = PathBuf::from("/tmp/non_existent_invoices_12345.csv");
+        let result = parse(non_existent_path, None);
+
+        assert!(result.is_err());
+        let err_msg = result.expect_err("should be error").to_string();
+        assert!(err_msg.contains("does not exist"));
+    }
+}
@@ -0,0 +1,255 @@
+use super::common;
+use crate::amount_serde::{self, deserialize as amount_serde};
+use std::{collections::BTreeMap, path::PathBuf};
+
+#[derive(Debug, serde::Deserialize)]
+struct Entry {
+    #[serde(rename = "Amount", deserialize_with = "amount_serde")]
+    pub amount: i64,
+    #[serde(rename = "Status")]
+    pub status: String,
+    #[serde(rename = "User ID")]
+    pub account_id: String,
+    #[serde(rename = "User Email")]
+    pub email: String,
+    /// Currency as written in the export, `eur` when the export has no `Currency` column.
+    #[serde(rename = "Currency", default = "common::default_currency")]
+    pub currency: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Paid,
+    Failed,
+    Refunded,
+}
+
+impl Entry {
+    /// Status of the charge, `None` for statuses which are not analyzed such as pending charges.
+    pub fn status(&self) -> Option<Status> {
+        match self.status.to_ascii_lowercase().as_str() {
+            "paid" => Some(Status::Paid),
+            "failed" => Some(Status::Failed),
+            "refunded" => Some(Status::Refunded),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct AccountPayments {
+    pub account_id: String,
+    pub email: String,
+    pub currency: String,
+    pub paid_count: u32,
+    pub failed_count: u32,
+    pub refunded_count: u32,
+    pub total_captured: i64,
+}
+
+impl AccountPayments {
+    pub fn new(account_id: &str, email: &str, currency: &str) -> Self {
+        Self {
+            account_id: account_id.to_string(),
+            email: email.to_string(),
+            currency: currency.to_string(),
+            paid_count: 0,
+            failed_count: 0,
+            refunded_count: 0,
+            total_captured: 0,
+        }
+    }
+
+    /// Adds a charge, paid and refunded charges were captured and count towards the total.
+    pub const fn add_payment(&mut self, amount: i64, status: Status) {
+        match status {
+            Status::Paid => self.paid_count += 1,
+            Status::Failed => {
+                self.failed_count += 1;
+                return;
+            }
+            Status::Refunded => self.refunded_count += 1,
+        }
+        self.total_captured += amount;
+    }
+
+    /// Percentage of the charges which did not fail, refunded charges succeeded before being refunded.
+    pub fn success_rate(&self) -> f64 {
+        let succeeded = self.paid_count + self.refunded_count;
+        let total = succeeded + self.failed_count;
+        if total == 0 {
+            return 0.0;
+        }
+
+        f64::from(succeeded) * 100.0 / f64::from(total)
+    }
+
+    pub const fn csv_header() -> &'static str {
+        "account_id,email,currency,paid_count,failed_count,refunded_count,total_captured,success_rate"
+    }
+
+    pub fn record(&self) -> [String; 8] {
+        [
+            self.account_id.clone(),
+            self.email.clone(),
+            self.currency.clone(),
+            self.paid_count.to_string(),
+            self.failed_count.to_string(),
+            self.refunded_count.to_string(),
+            amount_serde::format(
+                self.total_captured,
+                amount_serde::currency_exponent(&self.currency),
+            ),
+            format!("{:.2}", self.success_rate()),
+        ]
+    }
+}
+
+/// Parse a Stripe payments CSV file located at the given path, `-` reads from stdin.
+///
+/// Charges are grouped by account and currency, sorted by account id, with a count per status, the captured amount of the paid
+/// and refunded charges, and the percentage of charges which did not fail.
+/// Charges with another status, such as pending ones, are ignored.
+///
+/// # Errors
+///
+/// File was not found or could not be read.
+/// Unable to parse the CSV file.
+/// Unable to create an Entry from a CSV line.
+/// Output file could not be created or written to, `-` writes to stdout.
+pub fn parse(file: PathBuf, output: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut csv_reader, output) = common::open(file, output, "payments")?;
+    let mut statistics: BTreeMap<(String, String), AccountPayments> = BTreeMap::new();
+    for result in common::entries(&mut csv_reader)? {
+        let entry: Entry = result?;
+        let Some(status) = entry.status() else {
+            continue;
+        };
+
+        statistics
+            .entry((entry.account_id.clone(), entry.currency.to_lowercase()))
+            .or_insert_with_key(|(account_id, currency)| {
+                AccountPayments::new(account_id, &entry.email, currency)
+            })
+            .add_payment(entry.amount, status);
+    }
+
+    common::write_csv(
+        &output,
+        AccountPayments::csv_header(),
+        statistics.values().map(AccountPayments::record),
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::common::parser_test;
+
+    const HEADER: &str = "id,Created (UTC),Amount,Status,User ID,User Email";
+
+    parser_test!(
+        entry_deserialize_valid,
+        format!("{HEADER}\nch_123,2025-12-31 14:30,\"10,50\",Paid,acct_123,user@example.com"),
+        amount is 1050,
+        status is "Paid".to_string(),
+        account_id is "acct_123".to_string(),
+        email is "user@example.com".to_string()
+    );
+
+    parser_test!(
+        test_entry_deserialize_missing_status,
+        "Amount,User ID,User Email\n\"10,50\",acct_123,user@example.com",
+        is_error
+    );
+
+    parser_test!(
+        test_entry_deserialize_invalid_amount,
+        format!("{HEADER}\nch_123,2025-12-31 14:30,invalid,Paid,acct_123,user@example.com"),
+        is_error
+    );
+
+    #[test]
+    fn test_entry_status() {
+        let data = format!(
+            "{HEADER}\nch_1,2025-12-31 14:30,\"1,00\",Paid,acct_123,user@example.com\nch_2,2025-12-31 14:30,\"1,00\",FAILED,acct_123,user@example.com\nch_3,2025-12-31 14:30,\"1,00\",refunded,acct_123,user@example.com\nch_4,2025-12-31 14:30,\"1,00\",Pending,acct_123,user@example.com"
+        );
+        let mut reader = csv::Reader::from_reader(data.as_bytes());
+        let statuses: Vec<Option<Status>> = reader
+            .deserialize::<Entry>()
+            .map(|entry| entry.expect("should be ok").status())
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                Some(Status::Paid),
+                Some(Status::Failed),
+                Some(Status::Refunded),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn test_success_rate() {
+        let mut payments = AccountPayments::new("acct_123", "user@example.com", "eur");
+        assert!(payments.success_rate().abs() < f64::EPSILON);
+
+        payments.add_payment(100, Status::Paid);
+        payments.add_payment(200, Status::Paid);
+        payments.add_payment(300, Status::Refunded);
+        payments.add_payment(400, Status::Failed);
+        assert_eq!(payments.total_captured, 600);
+        assert!((payments.success_rate() - 75.0).abs() < f64::EPSILON);
+    }
+
+    parser_test!(
+        test_parse_groups_by_account,
+        file,
+        format!(
+            "{HEADER}\nch_1,2025-12-31 14:30,\"10,00\",Paid,acct_123,user@example.com\nch_2,2025-12-31 15:30,\"5,00\",Failed,acct_123,user@example.com\nch_3,2025-12-31 16:30,\"2,50\",Refunded,acct_123,user@example.com\nch_4,2025-12-30 10:00,\"20,00\",Paid,acct_456,test@example.com\nch_5,2025-12-30 11:00,\"1,00\",Pending,acct_456,test@example.com"
+        ),
+        [
+            AccountPayments::csv_header(),
+            "acct_123,user@example.com,eur,1,1,1,12.50,66.67",
+            "acct_456,test@example.com,eur,1,0,0,20.00,100.00"
+        ]
+    );
+
+    parser_test!(
+        test_parse_groups_by_currency,
+        file,
+        "Amount,Status,User ID,User Email,Currency\n\"10,00\",Paid,acct_123,user@example.com,eur\n1000,Paid,acct_123,user@example.com,jpy\n500,Failed,acct_123,user@example.com,jpy",
+        [
+            AccountPayments::csv_header(),
+            "acct_123,user@example.com,eur,1,0,0,10.00,100.00",
+            "acct_123,user@example.com,jpy,1,1,0,1000,50.00"
+        ]
+    );
+
+    parser_test!(
+        test_parse_empty_csv,
+        file,
+        HEADER,
+        [AccountPayments::csv_header()]
+    );
+
+    parser_test!(
+        test_parse_invalid_csv_format,
+        file,
+        format!("{HEADER}\nch_1,2025-12-31 14:30,invalid,Paid,acct_123,user@example.com"),
+        is_error
+    );
+
+    #[test]
+    fn test_parse_file_not_found() {
+        let non_existent_path = PathBuf::from("/tmp/non_existent_payments_12345.csv");
+        let result = parse(non_existent_path, None);
+
+        assert!(result.is_err());
+        let err_msg = result.expect_err("should be error").to_string();
+        assert!(err_msg.contains("does not exist"));
+    }
+}
@@ -0,0 +1,187 @@
+use super::common;
+use crate::amount_serde::{self, deserialize as amount_serde};
+use std::{collections::BTreeMap, path::PathBuf};
+
+#[derive(Debug, serde::Deserialize)]
+struct Entry {
+    #[serde(rename = "Amount", deserialize_with = "amount_serde")]
+    pub amount: i64,
+    #[serde(rename = "Status")]
+    pub status: String,
+    #[serde(rename = "Currency")]
+    pub currency: String,
+}
+
+impl Entry {
+    pub fn is_failed(&self) -> bool {
+        self.status.eq_ignore_ascii_case("failed")
+    }
+}
+
+#[derive(Debug)]
+struct CurrencyPayouts {
+    pub currency: String,
+    pub payout_count: u32,
+    pub failed_count: u32,
+    pub total_amount: i64,
+}
+
+impl CurrencyPayouts {
+    pub fn new(currency: &str) -> Self {
+        Self {
+            currency: currency.to_string(),
+            payout_count: 0,
+            failed_count: 0,
+            total_amount: 0,
+        }
+    }
+
+    pub const fn add_payout(&mut self, amount: i64) {
+        self.payout_count += 1;
+        self.total_amount += amount;
+    }
+
+    pub const fn add_failed(&mut self) {
+        self.failed_count += 1;
+    }
+
+    pub const fn csv_header() -> &'static str {
+        "currency,payout_count,failed_count,total_amount"
+    }
+
+    pub fn record(&self) -> [String; 4] {
+        [
+            self.currency.clone(),
+            self.payout_count.to_string(),
+            self.failed_count.to_string(),
+            amount_serde::format(
+                self.total_amount,
+                amount_serde::currency_exponent(&self.currency),
+            ),
+        ]
+    }
+}
+
+/// Parse a Stripe payouts CSV file located at the given path, `-` reads from stdin.
+///
+/// Payouts are grouped by currency, failed payouts are excluded from the totals and counted
+/// separately. Currencies are sorted and amounts have the decimals of their currency.
+///
+/// # Errors
+///
+/// File was not found or could not be read.
+/// Unable to parse the CSV file.
+/// Unable to create an Entry from a CSV line.
+/// Output file could not be created or written to, `-` writes to stdout.
+pub fn parse(file: PathBuf, output: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut csv_reader, output) = common::open(file, output, "payouts")?;
+    let mut statistics: BTreeMap<String, CurrencyPayouts> = BTreeMap::new();
+    for result in common::entries(&mut csv_reader)? {
+        let entry: Entry = result?;
+        let currency = entry.currency.to_lowercase();
+        let payouts = statistics
+            .entry(currency)
+            .or_insert_with_key(|currency| CurrencyPayouts::new(currency));
+        if entry.is_failed() {
+            payouts.add_failed();
+        } else {
+            payouts.add_payout(entry.amount);
+        }
+    }
+
+    common::write_csv(
+        &output,
+        CurrencyPayouts::csv_header(),
+        statistics.values().map(CurrencyPayouts::record),
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::common::parser_test;
+
+    const HEADER: &str = "id,Amount,Arrival Date (UTC),Status,Currency";
+
+    parser_test!(
+        entry_deserialize_valid,
+        format!("{HEADER}\npo_123,\"100,50\",2025-12-31,paid,eur"),
+        amount is 10050,
+        status is "paid".to_string(),
+        currency is "eur".to_string()
+    );
+
+    parser_test!(
+        test_entry_deserialize_missing_status,
+        "id,Amount,Arrival Date (UTC),Currency\npo_123,\"100,50\",2025-12-31,eur",
+        is_error
+    );
+
+    parser_test!(
+        test_entry_deserialize_invalid_amount,
+        format!("{HEADER}\npo_123,invalid,2025-12-31,paid,eur"),
+        is_error
+    );
+
+    parser_test!(
+        test_parse_groups_by_currency,
+        file,
+        format!(
+            "{HEADER}\npo_1,\"100,00\",2025-12-31,paid,eur\npo_2,\"50,25\",2025-12-30,paid,eur\npo_3,\"10,00\",2025-12-30,paid,usd"
+        ),
+        [
+            CurrencyPayouts::csv_header(),
+            "eur,2,0,150.25",
+            "usd,1,0,10.00"
+        ]
+    );
+
+    parser_test!(
+        test_parse_currency_exponent,
+        file,
+        format!(
+            "{HEADER}\npo_1,1000,2025-12-31,paid,jpy\npo_2,\"1,500\",2025-12-30,paid,bhd\npo_3,\"10,00\",2025-12-30,paid,eur"
+        ),
+        [
+            CurrencyPayouts::csv_header(),
+            "bhd,1,0,1.500",
+            "eur,1,0,10.00",
+            "jpy,1,0,1000"
+        ]
+    );
+
+    parser_test!(
+        test_parse_excludes_failed_payouts,
+        file,
+        format!(
+            "{HEADER}\npo_1,\"100,00\",2025-12-31,paid,eur\npo_2,\"50,25\",2025-12-30,failed,eur\npo_3,\"10,00\",2025-12-30,Failed,eur"
+        ),
+        [CurrencyPayouts::csv_header(), "eur,1,2,100.00"]
+    );
+
+    parser_test!(
+        test_parse_empty_csv,
+        file,
+        HEADER,
+        [CurrencyPayouts::csv_header()]
+    );
+
+    parser_test!(
+        test_parse_invalid_csv_format,
+        file,
+        format!("{HEADER}\npo_1,invalid,2025-12-31,paid,eur"),
+        is_error
+    );
+
+    #[test]
+    fn test_parse_file_not_found() {
+        let non_existent_path = PathBuf::from("/tmp/non_existent_payouts_12345.csv");
+        let result = parse(non_existent_path, None);
+
+        assert!(result.is_err());
+        let err_msg = result.expect_err("should be error").to_string();
+        assert!(err_msg.contains("does not exist"));
+    }
+}
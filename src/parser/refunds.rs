@@ -0,0 +1,191 @@
+use super::common;
+use crate::{amount_serde, timestamp_serde::deserialize as timestamp_serde};
+use chrono::NaiveDateTime;
+use std::{collections::BTreeMap, path::PathBuf};
+
+#[derive(Debug, serde::Deserialize)]
+struct Entry {
+    /// Refunded amount, a blank cell is no refund.
+    #[serde(
+        rename = "Amount Refunded",
+        deserialize_with = "common::amount_or_zero"
+    )]
+    pub amount_refunded: i64,
+    #[serde(rename = "User ID")]
+    pub account_id: String,
+    #[serde(rename = "User Email")]
+    pub email: String,
+    /// Currency as written in the export, `eur` when the export has no `Currency` column.
+    #[serde(rename = "Currency", default = "common::default_currency")]
+    pub currency: String,
+    #[serde(rename = "Created (UTC)", deserialize_with = "timestamp_serde")]
+    pub created: Option<NaiveDateTime>,
+}
+
+#[derive(Debug)]
+struct AccountRefunds {
+    pub account_id: String,
+    pub email: String,
+    pub currency: String,
+    pub refund_count: u32,
+    pub total_refunded: i64,
+}
+
+impl AccountRefunds {
+    pub fn new(account_id: &str, email: &str, currency: &str) -> Self {
+        Self {
+            account_id: account_id.to_string(),
+            email: email.to_string(),
+            currency: currency.to_string(),
+            refund_count: 0,
+            total_refunded: 0,
+        }
+    }
+
+    pub const fn add_refund(&mut self, amount: i64) {
+        self.refund_count += 1;
+        self.total_refunded += amount;
+    }
+
+    pub const fn csv_header() -> &'static str {
+        "account_id,email,currency,refund_count,total_refunded"
+    }
+
+    pub fn record(&self) -> [String; 5] {
+        [
+            self.account_id.clone(),
+            self.email.clone(),
+            self.currency.clone(),
+            self.refund_count.to_string(),
+            amount_serde::format(
+                self.total_refunded,
+                amount_serde::currency_exponent(&self.currency),
+            ),
+        ]
+    }
+}
+
+/// Parse a CSV file with an `Amount Refunded` column located at the given path, `-` reads from stdin.
+///
+/// Refunds are grouped by account and currency, sorted by account id, rows without a refunded
+/// amount are ignored so the fees export can be analyzed as well.
+/// The period covered by the refunds is printed to stderr.
+///
+/// # Errors
+///
+/// File was not found or could not be read.
+/// Unable to parse the CSV file.
+/// Unable to create an Entry from a CSV line.
+/// Output file could not be created or written to, `-` writes to stdout.
+pub fn parse(file: PathBuf, output: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut csv_reader, output) = common::open(file, output, "refunds")?;
+    let mut statistics: BTreeMap<(String, String), AccountRefunds> = BTreeMap::new();
+    let mut period = common::Period::default();
+    for result in common::entries(&mut csv_reader)? {
+        let entry: Entry = result?;
+        if entry.amount_refunded == 0 {
+            continue;
+        }
+        period.add(entry.created);
+
+        statistics
+            .entry((entry.account_id.clone(), entry.currency.to_lowercase()))
+            .or_insert_with_key(|(account_id, currency)| {
+                AccountRefunds::new(account_id, &entry.email, currency)
+            })
+            .add_refund(entry.amount_refunded);
+    }
+    period.report("refunds");
+
+    common::write_csv(
+        &output,
+        AccountRefunds::csv_header(),
+        statistics.values().map(AccountRefunds::record),
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::common::parser_test;
+
+    const HEADER: &str = "id,Created (UTC),Amount Refunded,User ID,User Email";
+
+    parser_test!(
+        entry_deserialize_valid,
+        format!("{HEADER}\nre_123,2025-12-31 14:30,\"1,50\",acct_123,user@example.com"),
+        amount_refunded is 150,
+        account_id is "acct_123".to_string(),
+        email is "user@example.com".to_string()
+    );
+
+    parser_test!(
+        test_entry_deserialize_missing_amount_refunded,
+        "User ID,User Email\nacct_123,user@example.com",
+        is_error
+    );
+
+    parser_test!(
+        test_entry_deserialize_blank_amount_refunded,
+        format!("{HEADER}\nre_123,2025-12-31 14:30,,acct_123,user@example.com"),
+        amount_refunded is 0,
+        currency is "eur".to_string(),
+        created is NaiveDateTime::parse_from_str("2025-12-31 14:30", "%Y-%m-%d %H:%M").ok()
+    );
+
+    parser_test!(
+        test_entry_deserialize_invalid_amount,
+        format!("{HEADER}\nre_123,2025-12-31 14:30,invalid,acct_123,user@example.com"),
+        is_error
+    );
+
+    parser_test!(
+        test_parse_groups_by_account,
+        file,
+        format!(
+            "{HEADER}\nre_1,2025-12-31 14:30,\"1,50\",acct_123,user@example.com\nre_2,2025-12-31 15:30,\"0,50\",acct_123,user@example.com\nre_3,2025-12-30 10:00,\"2,00\",acct_456,test@example.com"
+        ),
+        [
+            AccountRefunds::csv_header(),
+            "acct_123,user@example.com,eur,2,2.00",
+            "acct_456,test@example.com,eur,1,2.00"
+        ]
+    );
+
+    parser_test!(
+        test_parse_fees_export_ignores_rows_without_refund_by_currency,
+        file,
+        "id,Created (UTC),Amount,Amount Refunded,Currency,User ID,User Email\nfee_1,2025-12-31 14:30,\"0,25\",\"0,00\",eur,acct_123,user@example.com\nfee_2,2025-12-31 15:30,\"0,50\",\"0,50\",eur,acct_123,user@example.com\nfee_3,2025-12-30 10:00,\"0,35\",,eur,acct_456,test@example.com\nfee_4,2025-12-30 11:00,100,50,jpy,acct_123,user@example.com",
+        [
+            AccountRefunds::csv_header(),
+            "acct_123,user@example.com,eur,1,0.50",
+            "acct_123,user@example.com,jpy,1,50"
+        ]
+    );
+
+    parser_test!(
+        test_parse_empty_csv,
+        file,
+        HEADER,
+        [AccountRefunds::csv_header()]
+    );
+
+    parser_test!(
+        test_parse_invalid_csv_format,
+        file,
+        format!("{HEADER}\nre_1,2025-12-31 14:30,invalid,acct_123,user@example.com"),
+        is_error
+    );
+
+    #[test]
+    fn test_parse_file_not_found() {
+        let non_existent_path = PathBuf::from("/tmp/non_existent_refunds_12345.csv");
+        let result = parse(non_existent_path, None);
+
+        assert!(result.is_err());
+        let err_msg = result.expect_err("should be error").to_string();
+        assert!(err_msg.contains("does not exist"));
+    }
+}
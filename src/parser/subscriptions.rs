@@ -0,0 +1,252 @@
+use super::common;
+use crate::amount_serde::deserialize as amount_serde;
+use std::{collections::HashSet, path::PathBuf};
+
+#[derive(thiserror::Error, Debug)]
+enum Error {
+    #[error("unsupported billing interval '{0}', expected month or year")]
+    UnsupportedInterval(String),
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Entry {
+    #[serde(rename = "Amount", deserialize_with = "amount_serde")]
+    pub amount: i64,
+    #[serde(rename = "Interval")]
+    pub interval: String,
+    #[serde(rename = "Status")]
+    pub status: String,
+    #[serde(rename = "Customer Email")]
+    pub email: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Interval {
+    Month,
+    Year,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Active,
+    Trialing,
+    Canceled,
+}
+
+impl Entry {
+    /// Billing interval of the subscription.
+    pub fn interval(&self) -> Result<Interval, Error> {
+        match self.interval.to_ascii_lowercase().as_str() {
+            "month" => Ok(Interval::Month),
+            "year" => Ok(Interval::Year),
+            _ => Err(Error::UnsupportedInterval(self.interval.clone())),
+        }
+    }
+
+    /// Status of the subscription, `None` for statuses which are not analyzed such as past due ones.
+    pub fn status(&self) -> Option<Status> {
+        match self.status.to_ascii_lowercase().as_str() {
+            "active" => Some(Status::Active),
+            "trialing" => Some(Status::Trialing),
+            "canceled" => Some(Status::Canceled),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct RecurringRevenue {
+    pub active_count: u32,
+    pub trialing_count: u32,
+    pub canceled_count: u32,
+    pub customers: HashSet<String>,
+    /// Sum of the amounts of the monthly subscriptions.
+    pub monthly_amount: i64,
+    /// Sum of the amounts of the yearly subscriptions, divided by 12 only once in [`Self::mrr`].
+    pub yearly_amount: i64,
+}
+
+impl RecurringRevenue {
+    /// Adds a subscription, canceled subscriptions are counted but do not recur.
+    pub fn add_subscription(&mut self, entry: &Entry, status: Status) -> Result<(), Error> {
+        match status {
+            Status::Active => self.active_count += 1,
+            Status::Trialing => self.trialing_count += 1,
+            Status::Canceled => {
+                self.canceled_count += 1;
+                return Ok(());
+            }
+        }
+
+        match entry.interval()? {
+            Interval::Month => self.monthly_amount += entry.amount,
+            Interval::Year => self.yearly_amount += entry.amount,
+        }
+        self.customers.insert(entry.email.to_lowercase());
+        Ok(())
+    }
+
+    /// Monthly recurring revenue in eurocents, yearly amounts are spread over 12 months
+    /// and halves of a cent are rounded away from zero.
+    pub const fn mrr(&self) -> i64 {
+        let (months, remainder) = (self.yearly_amount / 12, self.yearly_amount % 12);
+        let rounding = if remainder.abs() * 2 >= 12 {
+            remainder.signum()
+        } else {
+            0
+        };
+        self.monthly_amount + months + rounding
+    }
+
+    pub const fn csv_header() -> &'static str {
+        "active_count,trialing_count,canceled_count,customer_count,total_mrr_eurocents"
+    }
+
+    pub fn record(&self) -> [String; 5] {
+        [
+            self.active_count.to_string(),
+            self.trialing_count.to_string(),
+            self.canceled_count.to_string(),
+            self.customers.len().to_string(),
+            self.mrr().to_string(),
+        ]
+    }
+}
+
+/// Parse a Stripe subscriptions CSV file located at the given path, `-` reads from stdin.
+///
+/// Active and trialing subscriptions are counted and their amounts summed into the monthly
+/// recurring revenue, yearly amounts being divided by 12. Canceled subscriptions are counted
+/// separately and subscriptions with another status, such as past due ones, are ignored.
+///
+/// # Errors
+///
+/// File was not found or could not be read.
+/// Unable to parse the CSV file.
+/// Unable to create an Entry from a CSV line.
+/// A recurring subscription is not billed monthly or yearly.
+/// Output file could not be created or written to, `-` writes to stdout.
+pub fn parse(file: PathBuf, output: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut csv_reader, output) = common::open(file, output, "subscriptions")?;
+    let mut revenue = RecurringRevenue::default();
+    for result in common::entries(&mut csv_reader)? {
+        let entry: Entry = result?;
+        if let Some(status) = entry.status() {
+            revenue.add_subscription(&entry, status)?;
+        }
+    }
+
+    common::write_csv(&output, RecurringRevenue::csv_header(), [revenue.record()])?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::common::parser_test;
+
+    const HEADER: &str = "id,Customer Email,Amount,Interval,Status";
+
+    parser_test!(
+        entry_deserialize_valid,
+        format!("{HEADER}\nsub_123,user@example.com,\"29,00\",month,active"),
+        amount is 2900,
+        interval is "month".to_string(),
+        status is "active".to_string(),
+        email is "user@example.com".to_string()
+    );
+
+    parser_test!(
+        test_entry_deserialize_missing_interval,
+        "Customer Email,Amount,Status\nuser@example.com,\"29,00\",active",
+        is_error
+    );
+
+    parser_test!(
+        test_entry_deserialize_invalid_amount,
+        format!("{HEADER}\nsub_123,user@example.com,invalid,month,active"),
+        is_error
+    );
+
+    #[test]
+    fn test_mrr() {
+        let data = format!(
+            "{HEADER}\nsub_1,a@example.com,\"10,00\",month,active\nsub_2,b@example.com,\"100,00\",Year,trialing\nsub_3,c@example.com,\"50,00\",month,canceled\nsub_4,d@example.com,\"70,00\",month,past_due"
+        );
+        let mut reader = csv::Reader::from_reader(data.as_bytes());
+        let mut revenue = RecurringRevenue::default();
+        for entry in reader.deserialize::<Entry>() {
+            let entry = entry.expect("should be ok");
+            if let Some(status) = entry.status() {
+                revenue
+                    .add_subscription(&entry, status)
+                    .expect("should add subscription");
+            }
+        }
+
+        assert_eq!(revenue.active_count, 1);
+        assert_eq!(revenue.trialing_count, 1);
+        assert_eq!(revenue.canceled_count, 1);
+        assert_eq!(revenue.mrr(), 1000 + 833);
+    }
+
+    #[test]
+    fn test_yearly_amounts_divided_once() {
+        let mut revenue = RecurringRevenue::default();
+        let entry = Entry {
+            amount: 1,
+            interval: "year".to_string(),
+            status: "active".to_string(),
+            email: "user@example.com".to_string(),
+        };
+        for _ in 0..12 {
+            revenue
+                .add_subscription(&entry, Status::Active)
+                .expect("should add subscription");
+        }
+
+        assert_eq!(revenue.mrr(), 1);
+        assert_eq!(revenue.customers.len(), 1);
+    }
+
+    parser_test!(
+        test_parse_mrr,
+        file,
+        format!(
+            "{HEADER}\nsub_1,user@example.com,\"29,00\",month,active\nsub_2,user@example.com,\"120,00\",year,active\nsub_3,test@example.com,\"9,99\",month,trialing\nsub_4,old@example.com,\"49,00\",month,canceled"
+        ),
+        [RecurringRevenue::csv_header(), "2,1,1,2,4899"]
+    );
+
+    parser_test!(
+        test_parse_empty_csv,
+        file,
+        HEADER,
+        [RecurringRevenue::csv_header(), "0,0,0,0,0"]
+    );
+
+    parser_test!(
+        test_parse_unsupported_interval,
+        file,
+        format!("{HEADER}\nsub_1,user@example.com,\"5,00\",week,active"),
+        is_error
+    );
+
+    parser_test!(
+        test_parse_invalid_csv_format,
+        file,
+        format!("{HEADER}\nsub_1,user@example.com,invalid,month,active"),
+        is_error
+    );
+
+    #[test]
+    fn test_parse_file_not_found() {
+        let non_existent_path = PathBuf::from("/tmp/non_existent_subscriptions_12345.csv");
+        let result = parse(non_existent_path, None);
+
+        assert!(result.is_err());
+        let err_msg = result.expect_err("should be error").to_string();
+        assert!(err_msg.contains("does not exist"));
+    }
+}
@@ -0,0 +1,176 @@
+use super::common;
+use crate::{
+    amount_serde::{self, deserialize as amount_serde},
+    timestamp_serde::deserialize as timestamp_serde,
+};
+use chrono::NaiveDateTime;
+use std::{collections::BTreeMap, path::PathBuf};
+
+#[derive(Debug, serde::Deserialize)]
+struct Entry {
+    #[serde(rename = "Tax Amount", deserialize_with = "amount_serde")]
+    pub tax_amount: i64,
+    #[serde(rename = "Jurisdiction")]
+    pub jurisdiction: String,
+    #[serde(rename = "Currency")]
+    pub currency: String,
+    #[serde(rename = "Created (UTC)", deserialize_with = "timestamp_serde")]
+    pub created: Option<NaiveDateTime>,
+}
+
+#[derive(Debug)]
+struct JurisdictionTax {
+    pub jurisdiction: String,
+    pub currency: String,
+    pub transaction_count: u32,
+    pub total_tax: i64,
+}
+
+impl JurisdictionTax {
+    pub fn new(jurisdiction: &str, currency: &str) -> Self {
+        Self {
+            jurisdiction: jurisdiction.to_string(),
+            currency: currency.to_string(),
+            transaction_count: 0,
+            total_tax: 0,
+        }
+    }
+
+    pub const fn add_tax(&mut self, entry: &Entry) {
+        self.transaction_count += 1;
+        self.total_tax += entry.tax_amount;
+    }
+
+    pub const fn csv_header() -> &'static str {
+        "jurisdiction,currency,transaction_count,total_tax"
+    }
+
+    pub fn record(&self) -> [String; 4] {
+        [
+            self.jurisdiction.clone(),
+            self.currency.clone(),
+            self.transaction_count.to_string(),
+            amount_serde::format(
+                self.total_tax,
+                amount_serde::currency_exponent(&self.currency),
+            ),
+        ]
+    }
+}
+
+/// Parse a Stripe Tax CSV file located at the given path, `-` reads from stdin.
+///
+/// The collected tax is grouped by jurisdiction and currency, sorted by jurisdiction, refunded tax being negative
+/// amounts which are subtracted from the total. The period covered by the transactions
+/// is printed to stderr.
+///
+/// # Errors
+///
+/// File was not found or could not be read.
+/// Unable to parse the CSV file.
+/// Unable to create an Entry from a CSV line.
+/// Output file could not be created or written to, `-` writes to stdout.
+pub fn parse(file: PathBuf, output: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut csv_reader, output) = common::open(file, output, "tax")?;
+    let mut statistics: BTreeMap<(String, String), JurisdictionTax> = BTreeMap::new();
+    let mut period = common::Period::default();
+    for result in common::entries(&mut csv_reader)? {
+        let entry: Entry = result?;
+        period.add(entry.created);
+
+        statistics
+            .entry((entry.jurisdiction.clone(), entry.currency.to_lowercase()))
+            .or_insert_with_key(|(jurisdiction, currency)| {
+                JurisdictionTax::new(jurisdiction, currency)
+            })
+            .add_tax(&entry);
+    }
+    period.report("tax transactions");
+
+    common::write_csv(
+        &output,
+        JurisdictionTax::csv_header(),
+        statistics.values().map(JurisdictionTax::record),
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::common::parser_test;
+
+    const HEADER: &str = "id,Tax Amount,Currency,Jurisdiction,Tax Rate,Created (UTC)";
+
+    parser_test!(
+        entry_deserialize_valid,
+        format!("{HEADER}\ntxi_123,\"3,80\",eur,DE,19%,2025-12-31 14:30"),
+        tax_amount is 380,
+        jurisdiction is "DE".to_string(),
+        currency is "eur".to_string()
+    );
+
+    parser_test!(
+        test_entry_deserialize_missing_jurisdiction,
+        "id,Tax Amount,Currency,Tax Rate,Created (UTC)\ntxi_123,\"3,80\",eur,19%,2025-12-31 14:30",
+        is_error
+    );
+
+    parser_test!(
+        test_entry_deserialize_invalid_tax_amount,
+        format!("{HEADER}\ntxi_123,invalid,eur,DE,19%,2025-12-31 14:30"),
+        is_error
+    );
+
+    parser_test!(
+        test_parse_groups_by_jurisdiction,
+        file,
+        format!(
+            "{HEADER}\ntxi_1,\"3,80\",eur,DE,19%,2025-12-31 14:30\ntxi_2,\"0,70\",eur,DE,7%,2025-12-30 10:00\ntxi_3,\"2,00\",eur,FR,20%,2025-12-30 11:00\ntxi_4,\"1,50\",usd,US-CA,7.25%,2025-12-29 09:00\ntxi_5,\"-3,80\",eur,DE,19%,2025-12-31 16:00"
+        ),
+        [
+            JurisdictionTax::csv_header(),
+            "DE,eur,3,0.70",
+            "FR,eur,1,2.00",
+            "US-CA,usd,1,1.50"
+        ]
+    );
+
+    parser_test!(
+        test_parse_quotes_jurisdiction,
+        file,
+        format!(
+            "{HEADER}\ntxi_1,100,jpy,\"Tokyo, JP\",10%,2025-12-31 14:30\ntxi_2,\"0,250\",kwd,KW,5%,2025-12-30 10:00"
+        ),
+        [
+            JurisdictionTax::csv_header(),
+            "KW,kwd,1,0.250",
+            "\"Tokyo, JP\",jpy,1,100"
+        ]
+    );
+
+    parser_test!(
+        test_parse_empty_csv,
+        file,
+        HEADER,
+        [JurisdictionTax::csv_header()]
+    );
+
+    parser_test!(
+        test_parse_invalid_csv_format,
+        file,
+        format!("{HEADER}\ntxi_1,\"3,80\",eur,DE,19%,31/12/2025"),
+        is_error
+    );
+
+    #[test]
+    fn test_parse_file_not_found() {
+        let non_existent_path = PathBuf::from("/tmp/non_existent_tax_12345.csv");
+        let result = parse(non_existent_path, None);
+
+        assert!(result.is_err());
+        let err_msg = result.expect_err("should be error").to_string();
+        assert!(err_msg.contains("does not exist"));
+    }
+}
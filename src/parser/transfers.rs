@@ -0,0 +1,211 @@
+use super::common;
+use crate::{
+    amount_serde::{self, deserialize as amount_serde},
+    timestamp_serde::deserialize as timestamp_serde,
+};
+use chrono::NaiveDateTime;
+use std::{collections::BTreeMap, path::PathBuf};
+
+#[derive(Debug, serde::Deserialize)]
+struct Entry {
+    #[serde(rename = "Amount", deserialize_with = "amount_serde")]
+    pub amount: i64,
+    #[serde(rename = "Destination")]
+    pub destination: String,
+    #[serde(rename = "Status")]
+    pub status: String,
+    #[serde(rename = "Created (UTC)", deserialize_with = "timestamp_serde")]
+    pub created: Option<NaiveDateTime>,
+    #[serde(rename = "Currency")]
+    pub currency: String,
+}
+
+impl Entry {
+    /// Whether the transfer did not reach the connected account, failed and reversed transfers
+    /// are excluded from the totals.
+    pub fn is_excluded(&self) -> bool {
+        matches!(
+            self.status.to_ascii_lowercase().as_str(),
+            "failed" | "reversed"
+        )
+    }
+}
+
+#[derive(Debug)]
+struct DestinationTransfers {
+    pub destination: String,
+    pub currency: String,
+    pub transfer_count: u32,
+    pub excluded_count: u32,
+    pub total_transferred: i64,
+}
+
+impl DestinationTransfers {
+    pub fn new(destination: &str, currency: &str) -> Self {
+        Self {
+            destination: destination.to_string(),
+            currency: currency.to_string(),
+            transfer_count: 0,
+            excluded_count: 0,
+            total_transferred: 0,
+        }
+    }
+
+    /// Adds a transfer, failed and reversed transfers are counted but not summed.
+    pub fn add_transfer(&mut self, entry: &Entry) {
+        self.transfer_count += 1;
+        if entry.is_excluded() {
+            self.excluded_count += 1;
+        } else {
+            self.total_transferred += entry.amount;
+        }
+    }
+
+    pub const fn csv_header() -> &'static str {
+        "destination,currency,transfer_count,excluded_count,total_transferred"
+    }
+
+    pub fn record(&self) -> [String; 5] {
+        [
+            self.destination.clone(),
+            self.currency.clone(),
+            self.transfer_count.to_string(),
+            self.excluded_count.to_string(),
+            amount_serde::format(
+                self.total_transferred,
+                amount_serde::currency_exponent(&self.currency),
+            ),
+        ]
+    }
+}
+
+/// Parse a Stripe Connect transfers CSV file located at the given path, `-` reads from stdin.
+///
+/// Transfers are grouped by destination account and currency, sorted by destination, amounts
+/// having the decimals of their currency. Failed and reversed transfers
+/// are counted in the transfer count, and separately, but excluded from the total transferred.
+/// The period covered by the transfers is printed to stderr.
+///
+/// # Errors
+///
+/// File was not found or could not be read.
+/// Unable to parse the CSV file.
+/// Unable to create an Entry from a CSV line.
+/// Output file could not be created or written to, `-` writes to stdout.
+pub fn parse(file: PathBuf, output: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut csv_reader, output) = common::open(file, output, "transfers")?;
+    let mut statistics: BTreeMap<(String, String), DestinationTransfers> = BTreeMap::new();
+    let mut period = common::Period::default();
+    for result in common::entries(&mut csv_reader)? {
+        let entry: Entry = result?;
+        period.add(entry.created);
+
+        statistics
+            .entry((entry.destination.clone(), entry.currency.to_lowercase()))
+            .or_insert_with_key(|(destination, currency)| {
+                DestinationTransfers::new(destination, currency)
+            })
+            .add_transfer(&entry);
+    }
+    period.report("transfers");
+
+    common::write_csv(
+        &output,
+        DestinationTransfers::csv_header(),
+        statistics.values().map(DestinationTransfers::record),
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::common::parser_test;
+
+    const HEADER: &str = "id,Amount,Currency,Destination,Status,Created (UTC)";
+
+    parser_test!(
+        entry_deserialize_valid,
+        format!("{HEADER}\ntr_123,\"100,50\",eur,acct_123,paid,2025-12-31 14:30"),
+        amount is 10050,
+        destination is "acct_123".to_string(),
+        status is "paid".to_string(),
+        currency is "eur".to_string()
+    );
+
+    parser_test!(
+        test_entry_deserialize_missing_destination,
+        "id,Amount,Currency,Status,Created (UTC)\ntr_123,\"100,50\",eur,paid,2025-12-31 14:30",
+        is_error
+    );
+
+    parser_test!(
+        test_entry_deserialize_invalid_created,
+        format!("{HEADER}\ntr_123,\"100,50\",eur,acct_123,paid,31/12/2025"),
+        is_error
+    );
+
+    parser_test!(
+        test_parse_groups_by_destination,
+        file,
+        format!(
+            "{HEADER}\ntr_1,\"100,00\",eur,acct_123,paid,2025-12-31 14:30\ntr_2,\"50,25\",eur,acct_123,paid,2025-12-30 10:00\ntr_3,\"10,00\",usd,acct_123,paid,2025-12-30 11:00\ntr_4,\"20,00\",eur,acct_456,pending,2025-12-29 09:00"
+        ),
+        [
+            DestinationTransfers::csv_header(),
+            "acct_123,eur,2,0,150.25",
+            "acct_123,usd,1,0,10.00",
+            "acct_456,eur,1,0,20.00"
+        ]
+    );
+
+    parser_test!(
+        test_parse_excludes_failed_and_reversed_transfers,
+        file,
+        format!(
+            "{HEADER}\ntr_1,\"100,00\",eur,acct_123,paid,2025-12-31 14:30\ntr_2,\"50,25\",eur,acct_123,failed,2025-12-30 10:00\ntr_3,\"10,00\",eur,acct_123,Reversed,2025-12-30 11:00"
+        ),
+        [
+            DestinationTransfers::csv_header(),
+            "acct_123,eur,3,2,100.00"
+        ]
+    );
+
+    parser_test!(
+        test_parse_currency_exponent,
+        file,
+        format!(
+            "{HEADER}\ntr_1,1000,jpy,acct_456,paid,2025-12-31 14:30\ntr_2,\"1,500\",BHD,acct_123,paid,2025-12-30 10:00"
+        ),
+        [
+            DestinationTransfers::csv_header(),
+            "acct_123,bhd,1,0,1.500",
+            "acct_456,jpy,1,0,1000"
+        ]
+    );
+
+    parser_test!(
+        test_parse_empty_csv,
+        file,
+        HEADER,
+        [DestinationTransfers::csv_header()]
+    );
+
+    parser_test!(
+        test_parse_invalid_csv_format,
+        file,
+        format!("{HEADER}\ntr_1,invalid,eur,acct_123,paid,2025-12-31 14:30"),
+        is_error
+    );
+
+    #[test]
+    fn test_parse_file_not_found() {
+        let non_existent_path = PathBuf::from("/tmp/non_existent_transfers_12345.csv");
+        let result = parse(non_existent_path, None);
+
+        assert!(result.is_err());
+        let err_msg = result.expect_err("should be error").to_string();
+        assert!(err_msg.contains("does not exist"));
+    }
+}
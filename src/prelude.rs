@@ -0,0 +1,45 @@
+//! Commonly used items, `use stripe_csv::prelude::*;` imports the arguments, the parse function
+//! of every analysis and the error types.
+//!
+//! The parse functions are named after their analysis since every parser module has a `parse`.
+
+pub use crate::amount_serde::{self, Error as AmountError};
+pub use crate::args::{Args, CsvType, OutputFormat, SortKey};
+pub use crate::config::{Config, Error as ConfigError};
+pub use crate::parser::balance::parse as parse_balance_transactions;
+pub use crate::parser::checkout::parse as parse_checkout_sessions;
+pub use crate::parser::common::OutputRequired;
+pub use crate::parser::customers::parse as parse_customers;
+pub use crate::parser::disputes::parse as parse_disputes;
+pub use crate::parser::events::parse as parse_events;
+pub use crate::parser::fees::{
+    FeesError, Options as FeesOptions, parse as parse_fees, parse_files as parse_fees_files,
+};
+pub use crate::parser::invoices::parse as parse_invoices;
+pub use crate::parser::payments::parse as parse_payments;
+pub use crate::parser::payouts::parse as parse_payouts;
+pub use crate::parser::refunds::parse as parse_refunds;
+pub use crate::parser::subscriptions::parse as parse_subscriptions;
+pub use crate::parser::tax::parse as parse_tax;
+pub use crate::parser::transfers::parse as parse_transfers;
+pub use crate::timestamp_serde::Error as TimestampError;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn test_prelude_items() {
+        let arguments =
+            Args::try_parse_from(["stripe-csv", "fees.csv", "fees"]).expect("should parse");
+        assert_eq!(arguments.csv_type, CsvType::Fees);
+        assert_eq!(
+            amount_serde::parse("1,50").expect("should parse amount"),
+            150
+        );
+
+        let error: AmountError = amount_serde::parse("invalid").expect_err("should be error");
+        assert!(!error.to_string().is_empty());
+    }
+}
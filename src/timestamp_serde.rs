@@ -0,0 +1,165 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    #[error(
+        "invalid timestamp '{0}', expected YYYY-MM-DD HH:MM, YYYY-MM-DD HH:MM:SS or ISO 8601 such as 2025-12-31T14:30:05Z"
+    )]
+    InvalidTimestamp(String),
+    #[error("invalid date '{0}', expected YYYY-MM-DD")]
+    InvalidDate(String),
+}
+
+/// Formats of the timestamps of the Stripe exports, e.g. "2025-12-31 14:30",
+/// the ISO 8601 timestamps with an offset are parsed separately.
+const TIMESTAMP_FORMATS: [&str; 4] = [
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%dT%H:%M:%S",
+];
+
+/// Parses optional timestamps in Stripe CSV, an empty value is `None`.
+///
+/// See [`parse`] for the accepted formats.
+///
+/// # Errors
+///
+/// The timestamp is not in the specified format.
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<NaiveDateTime>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let s: String = serde::Deserialize::deserialize(deserializer)?;
+    if s.is_empty() {
+        return Ok(None);
+    }
+    parse(&s).map(Some).map_err(serde::de::Error::custom)
+}
+
+/// Parses a timestamp of the Stripe exports.
+///
+/// Accepted formats: "YYYY-MM-DD HH:MM", optionally followed by the seconds, the date and time
+/// may be separated by a `T` instead of a space, and ISO 8601 timestamps with a `Z` or an offset
+/// such as "2025-12-31T14:30:05Z" or "2025-12-31T16:30:05+02:00", which are converted to UTC.
+///
+/// # Errors
+///
+/// The timestamp is in none of the accepted formats.
+pub fn parse(timestamp: &str) -> Result<NaiveDateTime, Error> {
+    TIMESTAMP_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(timestamp, format).ok())
+        .or_else(|| {
+            DateTime::parse_from_rfc3339(timestamp)
+                .ok()
+                .map(|timestamp| timestamp.naive_utc())
+        })
+        .ok_or_else(|| Error::InvalidTimestamp(timestamp.to_string()))
+}
+
+/// Parses a date given on the command line.
+///
+/// Accepted format: "YYYY-MM-DD".
+///
+/// # Errors
+///
+/// The date is not in the specified format.
+pub fn parse_date(date: &str) -> Result<NaiveDate, Error> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| Error::InvalidDate(date.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(serde::Deserialize)]
+    struct TestTimestamp {
+        #[serde(deserialize_with = "deserialize")]
+        created: Option<NaiveDateTime>,
+    }
+
+    fn deserialize_timestamp(timestamp: &str) -> csv::Result<Option<NaiveDateTime>> {
+        let csv_data = format!("created\n\"{timestamp}\"");
+        let mut reader = csv::Reader::from_reader(csv_data.as_bytes());
+        let result: csv::Result<TestTimestamp> =
+            reader.deserialize().next().expect("should have a row");
+        result.map(|row| row.created)
+    }
+
+    fn timestamp(
+        year: i32,
+        month: u32,
+        day: u32,
+        hour: u32,
+        minute: u32,
+        second: u32,
+    ) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(year, month, day)
+            .and_then(|date| date.and_hms_opt(hour, minute, second))
+            .expect("should be a valid timestamp")
+    }
+
+    #[test]
+    fn test_deserialize() {
+        assert_eq!(
+            deserialize_timestamp("2025-12-31 14:30").expect("should deserialize"),
+            Some(timestamp(2025, 12, 31, 14, 30, 0))
+        );
+        assert_eq!(
+            deserialize_timestamp("2025-12-31 14:30:15").expect("should deserialize"),
+            Some(timestamp(2025, 12, 31, 14, 30, 15))
+        );
+        assert_eq!(deserialize_timestamp("").expect("should deserialize"), None);
+    }
+
+    #[test]
+    fn test_parse_iso_8601() {
+        assert_eq!(
+            parse("2025-12-31T14:30:05Z"),
+            Ok(timestamp(2025, 12, 31, 14, 30, 5))
+        );
+        assert_eq!(
+            parse("2025-12-31T14:30:05.250Z"),
+            parse("2025-12-31T14:30:05Z")
+                .map(|timestamp| timestamp + chrono::Duration::milliseconds(250))
+        );
+        assert_eq!(
+            parse("2026-01-01T01:30:05+02:00"),
+            Ok(timestamp(2025, 12, 31, 23, 30, 5))
+        );
+        assert_eq!(
+            parse("2025-12-31T14:30"),
+            Ok(timestamp(2025, 12, 31, 14, 30, 0))
+        );
+        assert_eq!(
+            parse("2025-12-31T14:30:05"),
+            Ok(timestamp(2025, 12, 31, 14, 30, 5))
+        );
+    }
+
+    #[test]
+    fn test_deserialize_invalid() {
+        let error = deserialize_timestamp("31/12/2025").expect_err("should be error");
+        assert!(error.to_string().contains("invalid timestamp '31/12/2025'"));
+        assert!(deserialize_timestamp("2025-13-01 10:00").is_err());
+        assert!(deserialize_timestamp("2025-12-31").is_err());
+        assert_eq!(
+            parse("2025-12-31T25:00:00Z"),
+            Err(Error::InvalidTimestamp("2025-12-31T25:00:00Z".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(
+            parse_date("2025-12-01"),
+            Ok(NaiveDate::from_ymd_opt(2025, 12, 1).expect("should be a valid date"))
+        );
+        assert_eq!(
+            parse_date("2025-02-30"),
+            Err(Error::InvalidDate("2025-02-30".to_string()))
+        );
+        assert!(parse_date("12/01/2025").is_err());
+    }
+}